@@ -8,7 +8,7 @@
 //!
 //! Run with: cargo run --example basic
 
-use tesaiot_analytics::{AnalyticsClient, TimeRange};
+use tesaiot_analytics::{AnalyticsClient, AnomalyQuery, AnomalySort, TimeRange};
 
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -22,13 +22,8 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
     // 1. Get recent anomalies
     println!("\n1. Fetching recent anomalies...");
     match client
-        .get_anomalies(
-            None,
-            None, // Don't filter by severity - get all
-            None,
-            5,
-            0,
-        )
+        // No severity or metric filter - get all
+        .get_anomalies(AnomalyQuery::new().sort(AnomalySort::ScoreDesc).limit(5))
         .await
     {
         Ok(result) => {
@@ -45,7 +40,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 2. Get latency statistics
     println!("\n2. Fetching latency statistics...");
-    match client.get_latency_stats(24).await {
+    match client.get_latency_stats(24, None).await {
         Ok(result) => {
             println!("   Average latency: {} ms", result.summary.overall_avg_ms);
             println!("   P95 latency: {} ms", result.summary.overall_p95_ms);
@@ -55,7 +50,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 3. Get throughput statistics
     println!("\n3. Fetching throughput statistics...");
-    match client.get_throughput_stats(24).await {
+    match client.get_throughput_stats(24, None).await {
         Ok(result) => {
             println!("   Total messages in: {}", result.summary.total_messages_in);
             println!("   Avg per minute: {:.2}", result.summary.avg_messages_per_minute);
@@ -65,7 +60,7 @@ async fn main() -> Result<(), Box<dyn std::error::Error>> {
 
     // 4. Get connectivity status
     println!("\n4. Fetching connectivity status...");
-    match client.get_connectivity_status(None).await {
+    match client.get_connectivity_status(None, None, None).await {
         Ok(result) => {
             println!("   Total devices: {}", result.summary.total_devices);
             println!("   Online: {}", result.summary.online_count);
@@ -97,15 +92,9 @@ async fn custom_time_range_example(client: &AnalyticsClient) -> Result<(), Box<d
     println!("    Start: {}", time_range.start);
     println!("    End: {}", time_range.end);
 
-    // Get anomalies with custom time range
+    // Get anomalies from the last 7 days
     let anomalies = client
-        .get_anomalies(
-            Some(TimeRange::last_days(7)),
-            None,
-            None,
-            10,
-            0,
-        )
+        .get_anomalies(AnomalyQuery::new().hours(7 * 24).limit(10))
         .await?;
 
     println!("  Anomalies in last 7 days: {}", anomalies.summary.total);
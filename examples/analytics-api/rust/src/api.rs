@@ -0,0 +1,356 @@
+//! Trait over the public client surface
+//!
+//! Code that takes `&dyn AnalyticsApi` (or a generic `A: AnalyticsApi`) can be
+//! exercised in tests with a fake implementation instead of a live server.
+//! Enable the `test-util` feature for a ready-made [`crate::mock::MockAnalyticsClient`].
+
+use std::future::Future;
+
+use async_trait::async_trait;
+use chrono::Utc;
+use tokio::sync::Semaphore;
+
+use crate::{
+    AnalyticsClient, AnalyticsSnapshot, AnomaliesResponse, Anomaly, AnomalyQuery, Cluster,
+    ClusterAlgorithm, ClustersResponse, ConnectionStatus, ConnectivityResponse, DeviceHealth,
+    InsightsResponse, LatencyResponse, Outlier, QualityResponse, Result, SnapshotOptions,
+    ThroughputResponse, TimeRange, TimelineResponse,
+};
+
+/// Analytics API operations, implemented by [`AnalyticsClient`]
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+pub trait AnalyticsApi: Send + Sync {
+    /// Get aggregated anomalies
+    async fn get_anomalies(&self, query: AnomalyQuery) -> Result<AnomaliesResponse>;
+
+    /// Get anomalies for a single metric
+    async fn get_metric_anomalies(
+        &self,
+        metric: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<AnomaliesResponse> {
+        self.get_anomalies(
+            AnomalyQuery::new()
+                .metrics([metric])
+                .limit(limit)
+                .offset(offset),
+        )
+        .await
+    }
+
+    /// Get a single anomaly by id
+    async fn get_anomaly(&self, id: &str) -> Result<Anomaly>;
+
+    /// Get a single anomaly by id, or `None` if it doesn't exist
+    async fn get_anomaly_opt(&self, id: &str) -> Result<Option<Anomaly>> {
+        match self.get_anomaly(id).await {
+            Ok(anomaly) => Ok(Some(anomaly)),
+            Err(crate::AnalyticsError::Api { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get anomaly timeline
+    async fn get_anomaly_timeline(&self, days: i64, group_by: &str) -> Result<TimelineResponse>;
+
+    /// Get anomaly timeline for a specific window
+    async fn get_anomaly_timeline_range(
+        &self,
+        range: TimeRange,
+        group_by: &str,
+    ) -> Result<TimelineResponse>;
+
+    /// Get K-means clusters
+    async fn get_clusters(
+        &self,
+        metric_name: &str,
+        n_clusters: i32,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.get_clusters_with(
+            metric_name,
+            ClusterAlgorithm::KMeans { n_clusters },
+            time_range,
+            include_outliers,
+        )
+        .await
+    }
+
+    /// Get clusters using the given algorithm
+    async fn get_clusters_with(
+        &self,
+        metric_name: &str,
+        algorithm: ClusterAlgorithm,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse>;
+
+    /// Get the cluster a single device belongs to
+    async fn get_device_cluster(
+        &self,
+        metric_name: &str,
+        device_id: &str,
+    ) -> Result<Option<Cluster>>;
+
+    /// Get outlier devices without running clustering
+    async fn get_outliers(
+        &self,
+        metric_name: &str,
+        time_range: Option<TimeRange>,
+        threshold: f64,
+    ) -> Result<Vec<Outlier>>;
+
+    /// Get AI insights
+    async fn get_insights(
+        &self,
+        days: i64,
+        insight_types: Option<Vec<&str>>,
+        min_confidence: f64,
+    ) -> Result<InsightsResponse>;
+
+    /// Get insight counts over time
+    async fn get_insights_timeline(&self, days: i64, group_by: &str) -> Result<TimelineResponse>;
+
+    /// Submit feedback on an insight
+    async fn submit_insight_feedback(
+        &self,
+        insight_id: &str,
+        useful: bool,
+        note: Option<&str>,
+    ) -> Result<()>;
+
+    /// Get connectivity status
+    async fn get_connectivity_status(
+        &self,
+        status_filter: Option<ConnectionStatus>,
+        device_ids: Option<Vec<&str>>,
+        name_contains: Option<&str>,
+    ) -> Result<ConnectivityResponse>;
+
+    /// Get latency statistics
+    async fn get_latency_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<LatencyResponse>;
+
+    /// Get throughput statistics
+    async fn get_throughput_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<ThroughputResponse>;
+
+    /// Get connection quality
+    async fn get_connection_quality(&self) -> Result<QualityResponse>;
+
+    /// Get a single device's rolled-up health
+    async fn get_device_health(
+        &self,
+        device_id: &str,
+        time_range: Option<TimeRange>,
+    ) -> Result<DeviceHealth>;
+
+    /// GET an endpoint the typed API doesn't cover yet, as untyped JSON
+    async fn get_raw(&self, path: &str, params: &[(&str, String)]) -> Result<serde_json::Value>;
+
+    /// POST to an endpoint the typed API doesn't cover yet, as untyped JSON
+    async fn post_raw(&self, path: &str, body: &serde_json::Value) -> Result<serde_json::Value>;
+
+    /// Fetch every analytics domain concurrently
+    async fn snapshot(&self, opts: SnapshotOptions) -> Result<AnalyticsSnapshot> {
+        fetch_snapshot(self, opts, None).await
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AnalyticsApi for AnalyticsClient {
+    async fn get_anomalies(&self, query: AnomalyQuery) -> Result<AnomaliesResponse> {
+        AnalyticsClient::get_anomalies(self, query).await
+    }
+
+    async fn get_anomaly(&self, id: &str) -> Result<Anomaly> {
+        AnalyticsClient::get_anomaly(self, id).await
+    }
+
+    async fn get_anomaly_timeline(&self, days: i64, group_by: &str) -> Result<TimelineResponse> {
+        AnalyticsClient::get_anomaly_timeline(self, days, group_by).await
+    }
+
+    async fn get_anomaly_timeline_range(
+        &self,
+        range: TimeRange,
+        group_by: &str,
+    ) -> Result<TimelineResponse> {
+        AnalyticsClient::get_anomaly_timeline_range(self, range, group_by).await
+    }
+
+    async fn get_clusters_with(
+        &self,
+        metric_name: &str,
+        algorithm: ClusterAlgorithm,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        AnalyticsClient::get_clusters_with(
+            self,
+            metric_name,
+            algorithm,
+            time_range,
+            include_outliers,
+        )
+        .await
+    }
+
+    async fn get_device_cluster(
+        &self,
+        metric_name: &str,
+        device_id: &str,
+    ) -> Result<Option<Cluster>> {
+        AnalyticsClient::get_device_cluster(self, metric_name, device_id).await
+    }
+
+    async fn get_outliers(
+        &self,
+        metric_name: &str,
+        time_range: Option<TimeRange>,
+        threshold: f64,
+    ) -> Result<Vec<Outlier>> {
+        AnalyticsClient::get_outliers(self, metric_name, time_range, threshold).await
+    }
+
+    async fn get_insights(
+        &self,
+        days: i64,
+        insight_types: Option<Vec<&str>>,
+        min_confidence: f64,
+    ) -> Result<InsightsResponse> {
+        AnalyticsClient::get_insights(self, days, insight_types, min_confidence).await
+    }
+
+    async fn get_insights_timeline(&self, days: i64, group_by: &str) -> Result<TimelineResponse> {
+        AnalyticsClient::get_insights_timeline(self, days, group_by).await
+    }
+
+    async fn submit_insight_feedback(
+        &self,
+        insight_id: &str,
+        useful: bool,
+        note: Option<&str>,
+    ) -> Result<()> {
+        AnalyticsClient::submit_insight_feedback(self, insight_id, useful, note).await
+    }
+
+    async fn get_connectivity_status(
+        &self,
+        status_filter: Option<ConnectionStatus>,
+        device_ids: Option<Vec<&str>>,
+        name_contains: Option<&str>,
+    ) -> Result<ConnectivityResponse> {
+        AnalyticsClient::get_connectivity_status(self, status_filter, device_ids, name_contains)
+            .await
+    }
+
+    async fn get_latency_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<LatencyResponse> {
+        AnalyticsClient::get_latency_stats(self, hours, device_ids).await
+    }
+
+    async fn get_throughput_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<ThroughputResponse> {
+        AnalyticsClient::get_throughput_stats(self, hours, device_ids).await
+    }
+
+    async fn get_connection_quality(&self) -> Result<QualityResponse> {
+        AnalyticsClient::get_connection_quality(self).await
+    }
+
+    async fn get_device_health(
+        &self,
+        device_id: &str,
+        time_range: Option<TimeRange>,
+    ) -> Result<DeviceHealth> {
+        AnalyticsClient::get_device_health(self, device_id, time_range).await
+    }
+
+    async fn get_raw(&self, path: &str, params: &[(&str, String)]) -> Result<serde_json::Value> {
+        AnalyticsClient::get_raw(self, path, params).await
+    }
+
+    async fn post_raw(&self, path: &str, body: &serde_json::Value) -> Result<serde_json::Value> {
+        AnalyticsClient::post_raw(self, path, body).await
+    }
+
+    async fn snapshot(&self, opts: SnapshotOptions) -> Result<AnalyticsSnapshot> {
+        fetch_snapshot(self, opts, Some(&self.fan_out)).await
+    }
+}
+
+/// Shared body of [`AnalyticsApi::snapshot`].
+///
+/// With a semaphore, each request waits for a permit before it is sent, so at
+/// most that many are in flight at once.
+pub(crate) async fn fetch_snapshot<A: AnalyticsApi + ?Sized>(
+    api: &A,
+    opts: SnapshotOptions,
+    limit: Option<&Semaphore>,
+) -> Result<AnalyticsSnapshot> {
+    let (anomalies, clusters, insights, connectivity, latency, throughput, quality) = tokio::try_join!(
+        limited(
+            limit,
+            api.get_anomalies(
+                AnomalyQuery::new()
+                    .severities(opts.anomaly_severities.iter().map(|s| s.as_str()))
+                    .limit(opts.anomaly_limit)
+            )
+        ),
+        limited(
+            limit,
+            api.get_clusters(
+                &opts.cluster_metric,
+                opts.n_clusters,
+                opts.cluster_range.clone(),
+                true,
+            )
+        ),
+        limited(
+            limit,
+            api.get_insights(opts.insight_days, None, opts.min_confidence)
+        ),
+        limited(limit, api.get_connectivity_status(None, None, None)),
+        limited(limit, api.get_latency_stats(opts.stats_hours, None)),
+        limited(limit, api.get_throughput_stats(opts.stats_hours, None)),
+        limited(limit, api.get_connection_quality())
+    )?;
+
+    Ok(AnalyticsSnapshot {
+        fetched_at: Utc::now(),
+        anomalies,
+        clusters,
+        insights,
+        connectivity,
+        latency,
+        throughput,
+        quality,
+    })
+}
+
+/// Run `fut` while holding a permit from `limit`, if there is one
+async fn limited<T>(limit: Option<&Semaphore>, fut: impl Future<Output = T>) -> T {
+    let _permit = match limit {
+        // The semaphore is owned by the client and never closed
+        Some(semaphore) => semaphore.acquire().await.ok(),
+        None => None,
+    };
+    fut.await
+}
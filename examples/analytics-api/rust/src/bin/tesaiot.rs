@@ -0,0 +1,446 @@
+//! `tesaiot` - command-line access to the TESAIoT Analytics API
+//!
+//! Reads credentials from the same environment variables as
+//! `AnalyticsClient::from_env` (`TESAIOT_API_TOKEN`, `TESAIOT_API_URL`, ...).
+//! Prints a table by default, or the full response as JSON with `--json`.
+//!
+//! Install with: cargo install tesaiot-analytics --features cli
+
+use clap::{Parser, Subcommand};
+use serde::Serialize;
+use tesaiot_analytics::{AnalyticsClient, AnomalyQuery, AnomalySort, ConnectionStatus, TimeRange};
+
+const SEVERITIES: [&str; 6] = ["critical", "high", "medium", "low", "warning", "info"];
+
+#[derive(Parser)]
+#[command(name = "tesaiot", version, about = "Query the TESAIoT Analytics API")]
+struct Cli {
+    /// Print the full response as JSON instead of a table
+    #[arg(long, global = true)]
+    json: bool,
+
+    #[command(subcommand)]
+    command: Command,
+}
+
+#[derive(Subcommand)]
+enum Command {
+    /// Recent anomalies, highest score first
+    Anomalies {
+        /// Only anomalies from the last N hours
+        #[arg(long)]
+        hours: Option<i64>,
+        /// Only these severities (repeat or comma-separate)
+        #[arg(long, value_delimiter = ',', value_parser = SEVERITIES, ignore_case = true)]
+        severity: Vec<String>,
+        /// Only these metrics (repeat or comma-separate)
+        #[arg(long, value_delimiter = ',')]
+        metric: Vec<String>,
+        /// Only unresolved anomalies
+        #[arg(long)]
+        open: bool,
+        /// Maximum number of anomalies to list
+        #[arg(long, default_value_t = 20)]
+        limit: i64,
+    },
+    /// Device connection status
+    Connectivity {
+        /// online, offline or unknown
+        #[arg(long)]
+        status: Option<String>,
+        /// Only devices whose name or id contains this text
+        #[arg(long)]
+        search: Option<String>,
+    },
+    /// Latency percentiles per device
+    Latency {
+        #[arg(long, default_value_t = 24)]
+        hours: i64,
+        /// Only these devices (repeat or comma-separate)
+        #[arg(long = "device", value_delimiter = ',')]
+        devices: Vec<String>,
+    },
+    /// Message and byte counts per device
+    Throughput {
+        #[arg(long, default_value_t = 24)]
+        hours: i64,
+        /// Only these devices (repeat or comma-separate)
+        #[arg(long = "device", value_delimiter = ',')]
+        devices: Vec<String>,
+    },
+    /// AI insights, most confident first
+    Insights {
+        #[arg(long, default_value_t = 7)]
+        days: i64,
+        /// Only these insight types (repeat or comma-separate)
+        #[arg(long = "type", value_delimiter = ',')]
+        types: Vec<String>,
+        /// Only insights with this severity
+        #[arg(long, value_parser = SEVERITIES, ignore_case = true)]
+        severity: Option<String>,
+        #[arg(long, default_value_t = 0.0)]
+        min_confidence: f64,
+    },
+    /// K-means clusters for a metric
+    Clusters {
+        /// Metric to cluster devices on, e.g. temperature
+        #[arg(long)]
+        metric: String,
+        #[arg(long, default_value_t = 3)]
+        clusters: i32,
+        /// Only data from the last N hours
+        #[arg(long)]
+        hours: Option<i64>,
+    },
+}
+
+#[tokio::main]
+async fn main() {
+    let cli = Cli::parse();
+    if let Err(e) = run(cli).await {
+        eprintln!("error: {}", e);
+        std::process::exit(1);
+    }
+}
+
+async fn run(cli: Cli) -> Result<(), Box<dyn std::error::Error>> {
+    let client = AnalyticsClient::from_env()?;
+
+    match cli.command {
+        Command::Anomalies {
+            hours,
+            severity,
+            metric,
+            open,
+            limit,
+        } => {
+            let query = anomaly_query(hours, &severity, &metric, open, limit);
+            let result = client.get_anomalies(query).await?;
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &[
+                    "DEVICE",
+                    "METRIC",
+                    "VALUE",
+                    "SEVERITY",
+                    "SCORE",
+                    "TIMESTAMP",
+                ],
+                result
+                    .anomalies
+                    .iter()
+                    .map(|a| {
+                        vec![
+                            a.device_name.clone(),
+                            a.metric.clone(),
+                            format!("{:.2}", a.value),
+                            a.severity.clone(),
+                            format!("{:.2}", a.score),
+                            a.timestamp.clone(),
+                        ]
+                    })
+                    .collect(),
+            );
+            println!(
+                "\n{} of {} anomalies",
+                result.anomalies.len(),
+                result.summary.total
+            );
+        }
+
+        Command::Connectivity { status, search } => {
+            let result = client
+                .get_connectivity_status(
+                    status.map(ConnectionStatus::from),
+                    None,
+                    search.as_deref(),
+                )
+                .await?;
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &["DEVICE", "NAME", "STATUS", "UPTIME", "LAST SEEN"],
+                result
+                    .devices
+                    .iter()
+                    .map(|d| {
+                        vec![
+                            d.device_id.clone(),
+                            d.device_name.clone(),
+                            d.status.to_string(),
+                            format!("{:.1}%", d.uptime_percent),
+                            d.last_seen.clone(),
+                        ]
+                    })
+                    .collect(),
+            );
+            let summary = &result.summary;
+            println!(
+                "\n{} devices: {} online, {} offline ({:.1}% online)",
+                summary.total_devices,
+                summary.online_count,
+                summary.offline_count,
+                summary.computed_online_percentage()
+            );
+        }
+
+        Command::Latency { hours, devices } => {
+            let result = client.get_latency_stats(hours, filter(&devices)).await?;
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &["DEVICE", "AVG MS", "P95 MS", "P99 MS", "SAMPLES"],
+                result
+                    .devices
+                    .iter()
+                    .map(|d| {
+                        vec![
+                            d.device_id.clone(),
+                            format!("{:.1}", d.avg_ms),
+                            format!("{:.1}", d.p95_ms),
+                            format!("{:.1}", d.p99_ms),
+                            d.sample_count.to_string(),
+                        ]
+                    })
+                    .collect(),
+            );
+            let summary = &result.summary;
+            println!(
+                "\nOverall: avg {:.1} ms, p95 {:.1} ms, p99 {:.1} ms ({} devices with high latency)",
+                summary.overall_avg_ms,
+                summary.overall_p95_ms,
+                summary.overall_p99_ms,
+                summary.devices_with_high_latency
+            );
+        }
+
+        Command::Throughput { hours, devices } => {
+            let result = client.get_throughput_stats(hours, filter(&devices)).await?;
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &["DEVICE", "MSGS IN", "MSGS OUT", "BYTES IN", "BYTES OUT"],
+                result
+                    .devices
+                    .iter()
+                    .map(|d| {
+                        vec![
+                            d.device_id.clone(),
+                            d.messages_in.to_string(),
+                            d.messages_out.to_string(),
+                            d.bytes_in.to_string(),
+                            d.bytes_out.to_string(),
+                        ]
+                    })
+                    .collect(),
+            );
+            let summary = &result.summary;
+            println!(
+                "\nTotal: {} messages in, {} in / {} out, {:.2} messages/min",
+                summary.total_messages_in,
+                summary.bytes_in_human(),
+                summary.bytes_out_human(),
+                summary.avg_messages_per_minute
+            );
+        }
+
+        Command::Insights {
+            days,
+            types,
+            severity,
+            min_confidence,
+        } => {
+            let mut result = client
+                .get_insights(days, filter(&types), min_confidence)
+                .await?;
+            if let Some(severity) = &severity {
+                result
+                    .insights
+                    .retain(|i| i.severity.eq_ignore_ascii_case(severity));
+            }
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &["TYPE", "SEVERITY", "CONFIDENCE", "TITLE"],
+                result
+                    .sorted_by_confidence()
+                    .into_iter()
+                    .map(|i| {
+                        vec![
+                            i.insight_type.to_string(),
+                            i.severity.clone(),
+                            format!("{:.0}%", i.confidence * 100.0),
+                            i.title.clone(),
+                        ]
+                    })
+                    .collect(),
+            );
+            let fleet = &result.fleet_summary;
+            println!(
+                "\nFleet health: {:.1} ({}), {} of {} devices active",
+                fleet.health_score,
+                fleet.grade(),
+                fleet.active_devices,
+                fleet.total_devices
+            );
+        }
+
+        Command::Clusters {
+            metric,
+            clusters,
+            hours,
+        } => {
+            let result = client
+                .get_clusters(&metric, clusters, hours.map(TimeRange::last_hours), true)
+                .await?;
+            if cli.json {
+                return print_json(&result);
+            }
+            print_table(
+                &["ID", "NAME", "DEVICES"],
+                result
+                    .clusters
+                    .iter()
+                    .map(|c| {
+                        vec![
+                            c.cluster_id.to_string(),
+                            c.cluster_name.clone(),
+                            c.device_count.to_string(),
+                        ]
+                    })
+                    .collect(),
+            );
+            println!(
+                "\nSilhouette score: {:.3} ({}), {} outliers",
+                result.silhouette_score,
+                result.clustering_quality(),
+                result.outliers.len()
+            );
+        }
+    }
+
+    Ok(())
+}
+
+/// Query for the `anomalies` subcommand's flags
+fn anomaly_query(
+    hours: Option<i64>,
+    severity: &[String],
+    metric: &[String],
+    open: bool,
+    limit: i64,
+) -> AnomalyQuery {
+    let mut query = AnomalyQuery::new()
+        .severities(severity)
+        .metrics(metric)
+        .sort(AnomalySort::ScoreDesc)
+        .limit(limit);
+    if let Some(hours) = hours {
+        query = query.hours(hours);
+    }
+    if open {
+        query = query.resolved(false);
+    }
+    query
+}
+
+/// `None` (no filter) for an empty flag list
+fn filter(values: &[String]) -> Option<Vec<&str>> {
+    if values.is_empty() {
+        None
+    } else {
+        Some(values.iter().map(String::as_str).collect())
+    }
+}
+
+fn print_json<T: Serialize>(value: &T) -> Result<(), Box<dyn std::error::Error>> {
+    println!("{}", serde_json::to_string_pretty(value)?);
+    Ok(())
+}
+
+/// Print rows as left-aligned columns sized to their widest cell
+fn print_table(headers: &[&str], rows: Vec<Vec<String>>) {
+    if rows.is_empty() {
+        println!("(no results)");
+        return;
+    }
+
+    let mut widths: Vec<usize> = headers.iter().map(|h| h.chars().count()).collect();
+    for row in &rows {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+
+    let header: Vec<String> = headers.iter().map(|h| h.to_string()).collect();
+    let rule: Vec<String> = widths.iter().map(|w| "-".repeat(*w)).collect();
+    for row in [&header, &rule].into_iter().chain(&rows) {
+        let line: Vec<String> = row
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:<width$}", cell, width = width))
+            .collect();
+        println!("{}", line.join("  ").trim_end());
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse_anomalies(args: &[&str]) -> AnomalyQuery {
+        let cli = Cli::try_parse_from(["tesaiot", "anomalies"].iter().chain(args)).unwrap();
+        match cli.command {
+            Command::Anomalies {
+                hours,
+                severity,
+                metric,
+                open,
+                limit,
+            } => anomaly_query(hours, &severity, &metric, open, limit),
+            _ => unreachable!(),
+        }
+    }
+
+    #[test]
+    fn anomalies_hours_sets_the_query_window() {
+        assert_eq!(
+            parse_anomalies(&["--hours", "1"]),
+            AnomalyQuery::new()
+                .sort(AnomalySort::ScoreDesc)
+                .limit(20)
+                .hours(1)
+        );
+    }
+
+    #[test]
+    fn anomalies_flags_map_onto_the_query() {
+        assert_eq!(
+            parse_anomalies(&[
+                "--severity",
+                "critical,high",
+                "--metric",
+                "temperature",
+                "--open",
+                "--limit",
+                "5",
+            ]),
+            AnomalyQuery::new()
+                .severities(["critical", "high"])
+                .metrics(["temperature"])
+                .sort(AnomalySort::ScoreDesc)
+                .limit(5)
+                .resolved(false)
+        );
+        assert_eq!(
+            parse_anomalies(&[]),
+            AnomalyQuery::new().sort(AnomalySort::ScoreDesc).limit(20)
+        );
+    }
+}
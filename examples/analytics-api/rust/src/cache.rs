@@ -0,0 +1,116 @@
+//! In-memory response cache with per-method TTLs
+//!
+//! Entries are keyed by method name and arguments and hold the response as
+//! JSON, so a hit skips the network and only pays for deserialization.
+//! Mutating calls clear the whole cache.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::Duration;
+
+use crate::Instant;
+
+/// Settings for [`AnalyticsClientBuilder::cache`](crate::AnalyticsClientBuilder::cache)
+#[derive(Debug, Clone)]
+pub struct CacheConfig {
+    /// TTL for methods without an entry in `ttls`
+    pub default_ttl: Duration,
+    /// TTL per client method name, e.g. `"get_clusters"`. A zero TTL turns
+    /// caching off for that method.
+    pub ttls: HashMap<String, Duration>,
+    /// Entries kept before the ones closest to expiry are evicted
+    pub max_entries: usize,
+}
+
+impl Default for CacheConfig {
+    fn default() -> Self {
+        Self {
+            default_ttl: Duration::from_secs(30),
+            ttls: HashMap::new(),
+            max_entries: 256,
+        }
+    }
+}
+
+impl CacheConfig {
+    /// Set the TTL for one client method
+    pub fn with_ttl(mut self, method: &str, ttl: Duration) -> Self {
+        self.ttls.insert(method.to_string(), ttl);
+        self
+    }
+
+    fn ttl_for(&self, method: &str) -> Duration {
+        self.ttls.get(method).copied().unwrap_or(self.default_ttl)
+    }
+}
+
+#[derive(Debug)]
+struct Entry {
+    value: serde_json::Value,
+    expires: Instant,
+}
+
+#[derive(Debug)]
+pub(crate) struct ResponseCache {
+    config: CacheConfig,
+    entries: Mutex<HashMap<String, Entry>>,
+}
+
+impl ResponseCache {
+    pub(crate) fn new(config: CacheConfig) -> Self {
+        Self {
+            config,
+            entries: Mutex::new(HashMap::new()),
+        }
+    }
+
+    /// Whether responses from `method` are cached at all
+    pub(crate) fn enabled_for(&self, method: &str) -> bool {
+        !self.config.ttl_for(method).is_zero()
+    }
+
+    /// Fresh cached response for `key`, dropping it if it has expired
+    pub(crate) fn get(&self, key: &str) -> Option<serde_json::Value> {
+        let mut entries = self.entries();
+        match entries.get(key) {
+            Some(entry) if entry.expires > Instant::now() => Some(entry.value.clone()),
+            Some(_) => {
+                entries.remove(key);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub(crate) fn insert(&self, method: &str, key: String, value: serde_json::Value) {
+        let now = Instant::now();
+        let expires = now + self.config.ttl_for(method);
+        let mut entries = self.entries();
+
+        if entries.len() >= self.config.max_entries && !entries.contains_key(&key) {
+            entries.retain(|_, entry| entry.expires > now);
+            while entries.len() >= self.config.max_entries {
+                let Some(oldest) = entries
+                    .iter()
+                    .min_by_key(|(_, entry)| entry.expires)
+                    .map(|(key, _)| key.clone())
+                else {
+                    // max_entries is 0: nothing can be cached
+                    return;
+                };
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(key, Entry { value, expires });
+    }
+
+    pub(crate) fn clear(&self) {
+        self.entries().clear();
+    }
+
+    fn entries(&self) -> std::sync::MutexGuard<'_, HashMap<String, Entry>> {
+        self.entries
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
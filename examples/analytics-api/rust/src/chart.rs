@@ -0,0 +1,177 @@
+//! Text bar charts for count breakdowns
+//!
+//! Renders maps such as [`AnomalySummary::by_severity`](crate::AnomalySummary)
+//! and `by_metric` as one labelled bar per line, for terminal dashboards.
+
+use std::collections::HashMap;
+use std::fmt::Write;
+
+use crate::Severity;
+
+/// Known severities, most severe first; other labels follow them
+const SEVERITY_ORDER: [Severity; 6] = [
+    Severity::Critical,
+    Severity::High,
+    Severity::Medium,
+    Severity::Low,
+    Severity::Warning,
+    Severity::Info,
+];
+
+/// Partial blocks for 1/8 to 7/8 of a character cell
+const PARTIAL_BLOCKS: [char; 7] = ['▏', '▎', '▍', '▌', '▋', '▊', '▉'];
+
+/// Render counts as a horizontal bar chart, one `label  count  bar` line each
+///
+/// The largest count fills `width` characters and the others are scaled to it
+/// in eighths of a character, so any non-zero count gets a visible bar.
+/// Severity labels come first in order critical → low; other labels (e.g.
+/// metric names) follow by descending count. Empty input renders as an empty
+/// string.
+pub fn render_bar_chart(counts: &HashMap<String, i64>, width: usize) -> String {
+    let mut entries: Vec<(&str, i64)> = counts
+        .iter()
+        .map(|(label, count)| (label.as_str(), *count))
+        .collect();
+    entries.sort_by(|a, b| {
+        severity_rank(a.0)
+            .cmp(&severity_rank(b.0))
+            .then_with(|| b.1.cmp(&a.1))
+            .then_with(|| a.0.cmp(b.0))
+    });
+
+    let max = entries.iter().map(|(_, count)| *count).max().unwrap_or(0);
+    let label_width = entries
+        .iter()
+        .map(|(label, _)| label.chars().count())
+        .max()
+        .unwrap_or(0);
+    let count_width = entries
+        .iter()
+        .map(|(_, count)| count.to_string().len())
+        .max()
+        .unwrap_or(0);
+
+    let mut out = String::new();
+    for (label, count) in entries {
+        let line = format!(
+            "{:<label_width$}  {:>count_width$}  {}",
+            label,
+            count,
+            bar(count, max, width),
+            label_width = label_width,
+            count_width = count_width
+        );
+        let _ = writeln!(out, "{}", line.trim_end());
+    }
+    out
+}
+
+fn severity_rank(label: &str) -> usize {
+    SEVERITY_ORDER
+        .iter()
+        .position(|severity| severity.matches(label))
+        .unwrap_or(SEVERITY_ORDER.len())
+}
+
+/// Bar for `count` out of `max`, at most `width` characters
+fn bar(count: i64, max: i64, width: usize) -> String {
+    if count <= 0 || max <= 0 || width == 0 {
+        return String::new();
+    }
+    let eighths = ((count as f64 / max as f64) * width as f64 * 8.0).round() as usize;
+    let eighths = eighths.clamp(1, width * 8);
+
+    let mut bar = "█".repeat(eighths / 8);
+    let partial = eighths % 8;
+    if partial > 0 {
+        bar.push(PARTIAL_BLOCKS[partial - 1]);
+    }
+    bar
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn counts(pairs: &[(&str, i64)]) -> HashMap<String, i64> {
+        pairs
+            .iter()
+            .map(|(label, count)| (label.to_string(), *count))
+            .collect()
+    }
+
+    #[test]
+    fn empty_input_renders_nothing() {
+        assert_eq!(render_bar_chart(&HashMap::new(), 20), "");
+    }
+
+    #[test]
+    fn single_bucket_fills_the_width() {
+        assert_eq!(
+            render_bar_chart(&counts(&[("high", 7)]), 4),
+            "high  7  ████\n"
+        );
+    }
+
+    #[test]
+    fn zero_counts_and_zero_width_have_no_bar() {
+        assert_eq!(
+            render_bar_chart(&counts(&[("low", 0), ("high", 0)]), 10),
+            "high  0\nlow   0\n"
+        );
+        assert_eq!(render_bar_chart(&counts(&[("low", 5)]), 0), "low  5\n");
+    }
+
+    #[test]
+    fn severities_come_first_in_order() {
+        let chart = render_bar_chart(
+            &counts(&[
+                ("low", 9),
+                ("temperature", 50),
+                ("Critical", 1),
+                ("info", 3),
+                ("medium", 2),
+                ("humidity", 50),
+                ("high", 4),
+            ]),
+            10,
+        );
+        let labels: Vec<&str> = chart
+            .lines()
+            .map(|line| line.split_whitespace().next().unwrap())
+            .collect();
+        assert_eq!(
+            labels,
+            [
+                "Critical",
+                "high",
+                "medium",
+                "low",
+                "info",
+                "humidity",
+                "temperature"
+            ]
+        );
+    }
+
+    #[test]
+    fn bars_scale_in_eighths() {
+        assert_eq!(bar(8, 8, 2), "██");
+        assert_eq!(bar(4, 8, 2), "█");
+        assert_eq!(bar(3, 8, 2), "▊");
+        assert_eq!(bar(5, 8, 2), "█▎");
+        // Tiny counts still get the thinnest visible bar
+        assert_eq!(bar(1, 1000, 10), "▏");
+        assert_eq!(bar(-1, 10, 10), "");
+    }
+
+    #[test]
+    fn columns_are_aligned() {
+        let chart = render_bar_chart(&counts(&[("critical", 120), ("low", 6)]), 20);
+        assert_eq!(
+            chart,
+            "critical  120  ████████████████████\nlow         6  █\n"
+        );
+    }
+}
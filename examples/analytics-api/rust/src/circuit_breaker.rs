@@ -0,0 +1,285 @@
+//! Circuit breaker that stops calling a backend that keeps failing
+//!
+//! Closed: requests flow and failures are counted within a sliding window.
+//! Open: once the threshold is hit, requests fail fast with
+//! [`AnalyticsError::CircuitOpen`](crate::AnalyticsError::CircuitOpen) until
+//! the cooldown elapses. Half-open: a single probe request is let through;
+//! success closes the circuit, failure reopens it for another cooldown.
+
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use chrono::{DateTime, Utc};
+
+use crate::clock::{Clock, SystemClock};
+
+/// Thresholds for [`AnalyticsClientBuilder::circuit_breaker`](crate::AnalyticsClientBuilder::circuit_breaker)
+#[derive(Debug, Clone)]
+pub struct CircuitBreakerConfig {
+    /// Consecutive failures within `window` that open the circuit
+    pub failure_threshold: u32,
+    /// How far back failures are counted
+    pub window: Duration,
+    /// How long the circuit stays open before a probe request is allowed
+    pub cooldown: Duration,
+}
+
+impl Default for CircuitBreakerConfig {
+    fn default() -> Self {
+        Self {
+            failure_threshold: 5,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(30),
+        }
+    }
+}
+
+#[derive(Debug)]
+enum State {
+    Closed { failures: VecDeque<DateTime<Utc>> },
+    Open { until: DateTime<Utc> },
+    HalfOpen { probe_started: DateTime<Utc> },
+}
+
+pub(crate) struct CircuitBreaker {
+    config: CircuitBreakerConfig,
+    state: Mutex<State>,
+    clock: Arc<dyn Clock + Send + Sync>,
+}
+
+impl std::fmt::Debug for CircuitBreaker {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("CircuitBreaker")
+            .field("config", &self.config)
+            .field("state", &self.state)
+            .finish()
+    }
+}
+
+impl CircuitBreaker {
+    pub(crate) fn new(config: CircuitBreakerConfig) -> Self {
+        Self::with_clock(config, Arc::new(SystemClock))
+    }
+
+    /// Breaker that reads the time from `clock`, so tests can step through
+    /// the window and cooldown
+    pub(crate) fn with_clock(
+        config: CircuitBreakerConfig,
+        clock: Arc<dyn Clock + Send + Sync>,
+    ) -> Self {
+        Self {
+            config,
+            state: Mutex::new(State::Closed {
+                failures: VecDeque::new(),
+            }),
+            clock,
+        }
+    }
+
+    /// Check whether a request may be sent, returning how long until the next
+    /// probe if the circuit is open
+    pub(crate) fn acquire(&self) -> std::result::Result<(), Duration> {
+        let now = self.clock.now();
+        let mut state = self.lock();
+        match *state {
+            State::Closed { .. } => Ok(()),
+            State::Open { until } if now >= until => {
+                *state = State::HalfOpen { probe_started: now };
+                Ok(())
+            }
+            State::Open { until } => Err(elapsed(now, until)),
+            // A probe whose future was dropped never reports back; allow
+            // another one after a full cooldown
+            State::HalfOpen { probe_started } => {
+                let waited = elapsed(probe_started, now);
+                if waited >= self.config.cooldown {
+                    *state = State::HalfOpen { probe_started: now };
+                    Ok(())
+                } else {
+                    Err(self.config.cooldown - waited)
+                }
+            }
+        }
+    }
+
+    /// The backend answered; close the circuit and forget past failures
+    pub(crate) fn record_success(&self) {
+        *self.lock() = State::Closed {
+            failures: VecDeque::new(),
+        };
+    }
+
+    /// The backend failed; open the circuit if the threshold is reached
+    pub(crate) fn record_failure(&self) {
+        let now = self.clock.now();
+        let mut state = self.lock();
+        match &mut *state {
+            State::Closed { failures } => {
+                failures.push_back(now);
+                while let Some(oldest) = failures.front() {
+                    if elapsed(*oldest, now) > self.config.window {
+                        failures.pop_front();
+                    } else {
+                        break;
+                    }
+                }
+                if failures.len() >= self.config.failure_threshold as usize {
+                    *state = State::Open {
+                        until: after(now, self.config.cooldown),
+                    };
+                }
+            }
+            State::HalfOpen { .. } => {
+                *state = State::Open {
+                    until: after(now, self.config.cooldown),
+                };
+            }
+            State::Open { .. } => {}
+        }
+    }
+
+    fn lock(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+}
+
+/// Time from `from` to `to`, zero if the clock went backwards
+fn elapsed(from: DateTime<Utc>, to: DateTime<Utc>) -> Duration {
+    (to - from).to_std().unwrap_or_default()
+}
+
+/// `duration` after `now`, saturating at the latest representable time
+fn after(now: DateTime<Utc>, duration: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(duration)
+        .ok()
+        .and_then(|duration| now.checked_add_signed(duration))
+        .unwrap_or(DateTime::<Utc>::MAX_UTC)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Clock that only moves when the test advances it
+    struct ManualClock(Mutex<DateTime<Utc>>);
+
+    impl ManualClock {
+        fn advance(&self, by: Duration) {
+            let mut now = self.0.lock().unwrap();
+            *now = after(*now, by);
+        }
+    }
+
+    impl Clock for ManualClock {
+        fn now(&self) -> DateTime<Utc> {
+            *self.0.lock().unwrap()
+        }
+    }
+
+    fn breaker() -> (CircuitBreaker, Arc<ManualClock>) {
+        let clock = Arc::new(ManualClock(Mutex::new(
+            "2024-01-15T10:00:00Z".parse().unwrap(),
+        )));
+        let config = CircuitBreakerConfig {
+            failure_threshold: 3,
+            window: Duration::from_secs(60),
+            cooldown: Duration::from_secs(30),
+        };
+        (CircuitBreaker::with_clock(config, clock.clone()), clock)
+    }
+
+    fn open(breaker: &CircuitBreaker) {
+        for _ in 0..breaker.config.failure_threshold {
+            breaker.acquire().unwrap();
+            breaker.record_failure();
+        }
+    }
+
+    #[test]
+    fn opens_after_threshold_failures() {
+        let (breaker, _clock) = breaker();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.acquire().is_ok());
+
+        breaker.record_failure();
+        assert_eq!(breaker.acquire(), Err(Duration::from_secs(30)));
+    }
+
+    #[test]
+    fn failures_outside_the_window_are_forgotten() {
+        let (breaker, clock) = breaker();
+        breaker.record_failure();
+        breaker.record_failure();
+        clock.advance(Duration::from_secs(61));
+        breaker.record_failure();
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn success_resets_the_failure_count() {
+        let (breaker, _clock) = breaker();
+        breaker.record_failure();
+        breaker.record_failure();
+        breaker.record_success();
+        breaker.record_failure();
+        breaker.record_failure();
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn half_opens_after_cooldown() {
+        let (breaker, clock) = breaker();
+        open(&breaker);
+
+        clock.advance(Duration::from_secs(10));
+        assert_eq!(breaker.acquire(), Err(Duration::from_secs(20)));
+
+        clock.advance(Duration::from_secs(20));
+        assert!(breaker.acquire().is_ok(), "probe allowed");
+        assert_eq!(
+            breaker.acquire(),
+            Err(Duration::from_secs(30)),
+            "only one probe at a time"
+        );
+    }
+
+    #[test]
+    fn successful_probe_closes_the_circuit() {
+        let (breaker, clock) = breaker();
+        open(&breaker);
+        clock.advance(Duration::from_secs(30));
+        breaker.acquire().unwrap();
+
+        breaker.record_success();
+        assert!(breaker.acquire().is_ok());
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn failed_probe_reopens_for_another_cooldown() {
+        let (breaker, clock) = breaker();
+        open(&breaker);
+        clock.advance(Duration::from_secs(30));
+        breaker.acquire().unwrap();
+
+        breaker.record_failure();
+        assert_eq!(breaker.acquire(), Err(Duration::from_secs(30)));
+        clock.advance(Duration::from_secs(30));
+        assert!(breaker.acquire().is_ok());
+    }
+
+    #[test]
+    fn abandoned_probe_is_retried_after_cooldown() {
+        let (breaker, clock) = breaker();
+        open(&breaker);
+        clock.advance(Duration::from_secs(30));
+        breaker.acquire().unwrap();
+
+        clock.advance(Duration::from_secs(30));
+        assert!(breaker.acquire().is_ok());
+    }
+}
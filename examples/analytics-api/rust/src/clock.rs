@@ -0,0 +1,34 @@
+//! Source of "now" for time-derived helpers
+//!
+//! Helpers such as [`TimeRange::last_days`](crate::TimeRange::last_days) read
+//! the system clock. Their `_with` variants take a [`Clock`] instead, so tests
+//! can pin the current time.
+
+use chrono::{DateTime, Utc};
+
+/// Provides the current time
+pub trait Clock {
+    fn now(&self) -> DateTime<Utc>;
+}
+
+/// The system clock, used by all helpers without a `_with` suffix
+#[derive(Debug, Clone, Copy, Default)]
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> DateTime<Utc> {
+        Utc::now()
+    }
+}
+
+/// Clock frozen at a fixed instant (requires `test-util`)
+#[cfg(feature = "test-util")]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FixedClock(pub DateTime<Utc>);
+
+#[cfg(feature = "test-util")]
+impl Clock for FixedClock {
+    fn now(&self) -> DateTime<Utc> {
+        self.0
+    }
+}
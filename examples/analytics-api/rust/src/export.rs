@@ -0,0 +1,5 @@
+//! Exporters that render analytics data in third-party formats
+
+pub mod influx;
+pub mod markdown;
+pub mod prometheus;
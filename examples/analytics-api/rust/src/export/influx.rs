@@ -0,0 +1,269 @@
+//! InfluxDB line protocol
+//!
+//! Renders latency and throughput summaries as points that can be written
+//! with the InfluxDB `/write` API or `influx write`.
+
+use std::fmt::Write;
+
+use chrono::{DateTime, Utc};
+
+use crate::AnalyticsSnapshot;
+
+/// A field value in a line protocol point
+enum Field {
+    Float(f64),
+    Int(i64),
+}
+
+/// Render a snapshot's latency and throughput as line protocol points,
+/// all stamped with `timestamp` (nanosecond precision)
+pub fn line_protocol(snapshot: &AnalyticsSnapshot, timestamp: DateTime<Utc>) -> String {
+    let mut out = String::new();
+    let ts = timestamp_nanos(timestamp);
+
+    let latency = &snapshot.latency.summary;
+    for (percentile, value) in [
+        ("avg", latency.overall_avg_ms),
+        ("p95", latency.overall_p95_ms),
+        ("p99", latency.overall_p99_ms),
+    ] {
+        write_point(
+            &mut out,
+            "tesaiot_latency",
+            &[("p", percentile)],
+            &[("value", Field::Float(value))],
+            ts,
+        );
+    }
+    for device in &snapshot.latency.devices {
+        write_point(
+            &mut out,
+            "tesaiot_device_latency",
+            &[("device_id", &device.device_id)],
+            &[
+                ("avg_ms", Field::Float(device.avg_ms)),
+                ("p95_ms", Field::Float(device.p95_ms)),
+                ("p99_ms", Field::Float(device.p99_ms)),
+                ("sample_count", Field::Int(device.sample_count)),
+            ],
+            ts,
+        );
+    }
+
+    let throughput = &snapshot.throughput.summary;
+    write_point(
+        &mut out,
+        "tesaiot_throughput",
+        &[],
+        &[
+            ("messages_in", Field::Int(throughput.total_messages_in)),
+            ("messages_out", Field::Int(throughput.total_messages_out)),
+            ("bytes_in", Field::Int(throughput.total_bytes_in)),
+            ("bytes_out", Field::Int(throughput.total_bytes_out)),
+            (
+                "avg_messages_per_minute",
+                Field::Float(throughput.avg_messages_per_minute),
+            ),
+            (
+                "peak_messages_per_minute",
+                Field::Int(throughput.peak_messages_per_minute),
+            ),
+            (
+                "avg_active_connections",
+                Field::Float(throughput.avg_active_connections),
+            ),
+        ],
+        ts,
+    );
+    for device in &snapshot.throughput.devices {
+        write_point(
+            &mut out,
+            "tesaiot_device_throughput",
+            &[("device_id", &device.device_id)],
+            &[
+                ("messages_in", Field::Int(device.messages_in)),
+                ("messages_out", Field::Int(device.messages_out)),
+                ("bytes_in", Field::Int(device.bytes_in)),
+                ("bytes_out", Field::Int(device.bytes_out)),
+            ],
+            ts,
+        );
+    }
+
+    out
+}
+
+/// Write one point. Non-finite floats aren't representable in line protocol,
+/// so those fields are dropped, and a point left without fields is skipped.
+fn write_point(
+    out: &mut String,
+    measurement: &str,
+    tags: &[(&str, &str)],
+    fields: &[(&str, Field)],
+    ts: i64,
+) {
+    let fields: Vec<String> = fields
+        .iter()
+        .filter_map(|(key, field)| {
+            let value = match field {
+                Field::Float(v) if v.is_finite() => v.to_string(),
+                Field::Float(_) => return None,
+                Field::Int(v) => format!("{}i", v),
+            };
+            Some(format!("{}={}", escape_key(key), value))
+        })
+        .collect();
+    if fields.is_empty() {
+        return;
+    }
+
+    out.push_str(&escape_measurement(measurement));
+    for (key, value) in tags {
+        // Empty tag values are not allowed; leave the tag off instead
+        if value.is_empty() {
+            continue;
+        }
+        let _ = write!(out, ",{}={}", escape_key(key), escape_key(value));
+    }
+    let _ = writeln!(out, " {} {}", fields.join(","), ts);
+}
+
+/// Escape a measurement name: commas and spaces
+fn escape_measurement(value: &str) -> String {
+    value.replace(',', "\\,").replace(' ', "\\ ")
+}
+
+/// Escape a tag key, tag value or field key: commas, equals signs and spaces
+fn escape_key(value: &str) -> String {
+    value
+        .replace(',', "\\,")
+        .replace('=', "\\=")
+        .replace(' ', "\\ ")
+        .replace(['\r', '\n'], "")
+}
+
+fn timestamp_nanos(timestamp: DateTime<Utc>) -> i64 {
+    timestamp
+        .timestamp()
+        .saturating_mul(1_000_000_000)
+        .saturating_add(i64::from(timestamp.timestamp_subsec_nanos()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{DeviceLatency, LatencyResponse, LatencySummary, ThroughputResponse};
+
+    fn snapshot() -> AnalyticsSnapshot {
+        AnalyticsSnapshot {
+            fetched_at: Utc::now(),
+            anomalies: Default::default(),
+            clusters: Default::default(),
+            insights: Default::default(),
+            connectivity: Default::default(),
+            latency: LatencyResponse {
+                summary: LatencySummary {
+                    overall_avg_ms: 42.5,
+                    overall_p95_ms: 123.0,
+                    overall_p99_ms: f64::NAN,
+                    ..Default::default()
+                },
+                devices: vec![DeviceLatency {
+                    device_id: "boiler room,1=a".to_string(),
+                    avg_ms: 10.0,
+                    p95_ms: 20.0,
+                    p99_ms: 30.0,
+                    sample_count: 7,
+                }],
+            },
+            throughput: ThroughputResponse::default(),
+            quality: Default::default(),
+        }
+    }
+
+    /// Split on `sep` where it isn't escaped with a backslash
+    fn split_unescaped(line: &str, sep: char) -> Vec<String> {
+        let mut parts = vec![String::new()];
+        let mut chars = line.chars();
+        while let Some(c) = chars.next() {
+            if c == '\\' {
+                parts.last_mut().unwrap().push(chars.next().unwrap());
+            } else if c == sep {
+                parts.push(String::new());
+            } else {
+                parts.last_mut().unwrap().push(c);
+            }
+        }
+        parts
+    }
+
+    /// Parse `measurement,tags fields timestamp` into its unescaped parts.
+    /// Fields here are all numeric, so only the head can hold escaped spaces.
+    fn parse(line: &str) -> (Vec<String>, Vec<String>, i64) {
+        let sections: Vec<&str> = line.rsplitn(3, ' ').collect();
+        assert_eq!(sections.len(), 3, "{}", line);
+        (
+            split_unescaped(sections[2], ','),
+            split_unescaped(sections[1], ','),
+            sections[0].parse().unwrap(),
+        )
+    }
+
+    #[test]
+    fn every_line_parses_with_the_given_timestamp() {
+        let timestamp: DateTime<Utc> = "2024-01-15T10:00:00.5Z".parse().unwrap();
+        let out = line_protocol(&snapshot(), timestamp);
+
+        let lines: Vec<&str> = out.lines().collect();
+        assert_eq!(lines.len(), 4, "{}", out);
+        for line in &lines {
+            let (head, fields, ts) = parse(line);
+            assert!(head[0].starts_with("tesaiot_"));
+            assert!(fields.iter().all(|f| f.contains('=')));
+            assert_eq!(ts, 1_705_312_800_500_000_000);
+        }
+
+        assert_eq!(
+            lines[0],
+            "tesaiot_latency,p=avg value=42.5 1705312800500000000"
+        );
+        assert_eq!(
+            lines[1],
+            "tesaiot_latency,p=p95 value=123 1705312800500000000"
+        );
+    }
+
+    #[test]
+    fn non_finite_values_drop_the_point() {
+        let out = line_protocol(&snapshot(), Utc::now());
+        assert!(!out.contains("p=p99"));
+        assert!(!out.contains("NaN"));
+    }
+
+    #[test]
+    fn tag_values_are_escaped() {
+        let out = line_protocol(&snapshot(), Utc::now());
+        let line = out
+            .lines()
+            .find(|l| l.starts_with("tesaiot_device_latency"))
+            .unwrap();
+        assert!(line.starts_with(r"tesaiot_device_latency,device_id=boiler\ room\,1\=a "));
+
+        let (head, fields, _) = parse(line);
+        assert_eq!(
+            head,
+            ["tesaiot_device_latency", "device_id=boiler room,1=a"]
+        );
+        assert_eq!(
+            fields,
+            ["avg_ms=10", "p95_ms=20", "p99_ms=30", "sample_count=7i"]
+        );
+    }
+
+    #[test]
+    fn escaping_rules() {
+        assert_eq!(escape_measurement("a b,c=d"), r"a\ b\,c=d");
+        assert_eq!(escape_key("a b,c=d"), r"a\ b\,c\=d");
+        assert_eq!(escape_key("line\r\nbreak"), "linebreak");
+    }
+}
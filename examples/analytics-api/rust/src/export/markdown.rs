@@ -0,0 +1,240 @@
+//! Markdown reports
+//!
+//! Renders a snapshot as a Markdown document suitable for pasting into a
+//! ticket, wiki page or chat message.
+
+use std::fmt::Write;
+
+use crate::AnalyticsSnapshot;
+
+/// Render a snapshot as a Markdown report with fleet health, anomaly,
+/// connectivity and latency tables
+pub fn snapshot_to_markdown(snapshot: &AnalyticsSnapshot) -> String {
+    let mut out = String::new();
+
+    let _ = writeln!(out, "# TESAIoT Analytics Report");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "_Generated: {}_", snapshot.fetched_at.to_rfc3339());
+    let _ = writeln!(out);
+
+    // Fleet health
+    let health = snapshot.fleet_health();
+    let _ = writeln!(
+        out,
+        "## Fleet Health: {:.0}/100 ({})",
+        health.overall_score, health.status
+    );
+    let _ = writeln!(out);
+    let mut components: Vec<_> = health.component_scores.iter().collect();
+    components.sort_by(|a, b| a.0.cmp(b.0));
+    write_table(
+        &mut out,
+        &["Component", "Score"],
+        components
+            .into_iter()
+            .map(|(component, score)| vec![component.clone(), format!("{:.0}", score)]),
+    );
+
+    // Anomalies
+    let summary = &snapshot.anomalies.summary;
+    let _ = writeln!(out, "## Anomalies by Severity");
+    let _ = writeln!(out);
+    let _ = writeln!(out, "Total: {}", summary.total);
+    let _ = writeln!(out);
+    let mut severities: Vec<_> = summary.by_severity.iter().collect();
+    severities.sort_by(|a, b| b.1.cmp(a.1).then_with(|| a.0.cmp(b.0)));
+    write_table(
+        &mut out,
+        &["Severity", "Count"],
+        severities
+            .into_iter()
+            .map(|(severity, count)| vec![severity.clone(), count.to_string()]),
+    );
+
+    // Connectivity
+    let connectivity = &snapshot.connectivity.summary;
+    let _ = writeln!(out, "## Connectivity");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "{}/{} devices online ({:.1}%)",
+        connectivity.online_count,
+        connectivity.total_devices,
+        connectivity.computed_online_percentage()
+    );
+    let _ = writeln!(out);
+    write_table(
+        &mut out,
+        &["Device", "Status", "Uptime (%)", "Last Seen"],
+        snapshot.connectivity.devices.iter().map(|device| {
+            vec![
+                device.device_name.clone(),
+                device.status.to_string(),
+                format!("{:.1}", device.uptime_percent),
+                device.last_seen.clone(),
+            ]
+        }),
+    );
+
+    // Latency
+    let latency = &snapshot.latency.summary;
+    let _ = writeln!(out, "## Latency");
+    let _ = writeln!(out);
+    let _ = writeln!(
+        out,
+        "Average {:.1} ms, P95 {:.1} ms, P99 {:.1} ms",
+        latency.overall_avg_ms, latency.overall_p95_ms, latency.overall_p99_ms
+    );
+    let _ = writeln!(out);
+    write_table(
+        &mut out,
+        &["Device", "Avg (ms)", "P95 (ms)", "P99 (ms)", "Samples"],
+        snapshot.latency.devices.iter().map(|device| {
+            vec![
+                device.device_id.clone(),
+                format!("{:.1}", device.avg_ms),
+                format!("{:.1}", device.p95_ms),
+                format!("{:.1}", device.p99_ms),
+                device.sample_count.to_string(),
+            ]
+        }),
+    );
+
+    out
+}
+
+fn write_table(out: &mut String, headers: &[&str], rows: impl Iterator<Item = Vec<String>>) {
+    let _ = writeln!(out, "| {} |", headers.join(" | "));
+    let _ = writeln!(out, "|{}", "---|".repeat(headers.len()));
+    for row in rows {
+        let cells: Vec<String> = row.iter().map(|cell| escape_cell(cell)).collect();
+        let _ = writeln!(out, "| {} |", cells.join(" | "));
+    }
+    let _ = writeln!(out);
+}
+
+/// Escape a table cell: pipes would split the cell, newlines would end the row
+fn escape_cell(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('|', "\\|")
+        .replace(['\r', '\n'], " ")
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        ConnectionStatus, ConnectivityResponse, DeviceLatency, DeviceStatus, LatencyResponse,
+    };
+    use chrono::{TimeZone, Utc};
+
+    fn snapshot() -> AnalyticsSnapshot {
+        AnalyticsSnapshot {
+            fetched_at: Utc.with_ymd_and_hms(2024, 1, 15, 10, 0, 0).unwrap(),
+            anomalies: Default::default(),
+            clusters: Default::default(),
+            insights: Default::default(),
+            connectivity: ConnectivityResponse {
+                devices: vec![
+                    DeviceStatus {
+                        device_id: "d1".to_string(),
+                        device_name: "a|b\nc".to_string(),
+                        status: ConnectionStatus::Online,
+                        last_seen: "2024-01-15T09:59:00Z".to_string(),
+                        uptime_percent: 99.5,
+                    },
+                    DeviceStatus {
+                        device_id: "d2".to_string(),
+                        device_name: r"C:\pump|2".to_string(),
+                        status: ConnectionStatus::Offline,
+                        last_seen: String::new(),
+                        uptime_percent: 12.0,
+                    },
+                ],
+                summary: Default::default(),
+            },
+            latency: LatencyResponse {
+                summary: Default::default(),
+                devices: vec![DeviceLatency {
+                    device_id: "gw|1".to_string(),
+                    avg_ms: 10.0,
+                    p95_ms: 20.0,
+                    p99_ms: 30.0,
+                    sample_count: 7,
+                }],
+            },
+            throughput: Default::default(),
+            quality: Default::default(),
+        }
+    }
+
+    /// Table rows (header, rule and data) under the `## {heading}` section
+    fn table<'a>(report: &'a str, heading: &str) -> Vec<&'a str> {
+        report
+            .lines()
+            .skip_while(|line| !line.starts_with(&format!("## {}", heading)))
+            .skip(1)
+            .skip_while(|line| !line.starts_with('|'))
+            .take_while(|line| line.starts_with('|'))
+            .collect()
+    }
+
+    /// Columns in a table row, ignoring pipes escaped with a backslash
+    fn columns(row: &str) -> usize {
+        let mut pipes = 0;
+        let mut chars = row.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => {
+                    chars.next();
+                }
+                '|' => pipes += 1,
+                _ => {}
+            }
+        }
+        pipes - 1
+    }
+
+    #[test]
+    fn device_names_are_escaped_in_table_cells() {
+        let report = snapshot_to_markdown(&snapshot());
+
+        let connectivity = table(&report, "Connectivity");
+        assert_eq!(
+            connectivity,
+            [
+                "| Device | Status | Uptime (%) | Last Seen |",
+                "|---|---|---|---|",
+                r"| a\|b c | online | 99.5 | 2024-01-15T09:59:00Z |",
+                r"| C:\\pump\|2 | offline | 12.0 |  |",
+            ]
+        );
+        for row in connectivity {
+            assert_eq!(columns(row), 4, "{}", row);
+        }
+
+        let latency = table(&report, "Latency");
+        assert_eq!(latency[2], r"| gw\|1 | 10.0 | 20.0 | 30.0 | 7 |");
+        for row in latency {
+            assert_eq!(columns(row), 5, "{}", row);
+        }
+    }
+
+    #[test]
+    fn every_table_row_has_the_header_column_count() {
+        let report = snapshot_to_markdown(&snapshot());
+        for (heading, width) in [
+            ("Fleet Health", 2),
+            ("Anomalies by Severity", 2),
+            ("Connectivity", 4),
+            ("Latency", 5),
+        ] {
+            let rows = table(&report, heading);
+            assert!(rows.len() >= 2, "{}", heading);
+            for row in rows {
+                assert_eq!(columns(row), width, "{}: {}", heading, row);
+            }
+        }
+    }
+}
@@ -0,0 +1,287 @@
+//! Prometheus text exposition format
+//!
+//! Renders analytics data as gauges that can be served from a `/metrics`
+//! endpoint or written to a node-exporter textfile collector.
+
+use std::fmt::Write;
+
+use crate::{ConnectionStatus, ConnectivityResponse, FleetHealth};
+
+/// Render fleet health as Prometheus gauges
+pub fn fleet_health_to_prometheus(health: &FleetHealth) -> String {
+    let mut out = String::new();
+
+    write_header(
+        &mut out,
+        "tesaiot_fleet_health_score",
+        "Overall fleet health score (0-100)",
+    );
+    write_sample(
+        &mut out,
+        "tesaiot_fleet_health_score",
+        &[],
+        health.overall_score,
+    );
+
+    write_header(
+        &mut out,
+        "tesaiot_fleet_health_component_score",
+        "Fleet health score per component (0-100)",
+    );
+    let mut components: Vec<_> = health.component_scores.iter().collect();
+    components.sort_by(|a, b| a.0.cmp(b.0));
+    for (component, score) in components {
+        write_sample(
+            &mut out,
+            "tesaiot_fleet_health_component_score",
+            &[("component", component)],
+            *score,
+        );
+    }
+
+    out
+}
+
+/// Render connectivity summary and per-device status as Prometheus gauges
+pub fn connectivity_to_prometheus(connectivity: &ConnectivityResponse) -> String {
+    let mut out = String::new();
+    let summary = &connectivity.summary;
+
+    let totals = [
+        (
+            "tesaiot_devices_total",
+            "Total number of devices",
+            summary.total_devices as f64,
+        ),
+        (
+            "tesaiot_devices_online",
+            "Number of online devices",
+            summary.online_count as f64,
+        ),
+        (
+            "tesaiot_devices_offline",
+            "Number of offline devices",
+            summary.offline_count as f64,
+        ),
+        (
+            "tesaiot_devices_unknown",
+            "Number of devices with unknown status",
+            summary.unknown_count as f64,
+        ),
+        (
+            "tesaiot_devices_online_percentage",
+            "Percentage of devices online",
+            summary.computed_online_percentage(),
+        ),
+    ];
+    for (name, help, value) in totals {
+        write_header(&mut out, name, help);
+        write_sample(&mut out, name, &[], value);
+    }
+
+    write_header(
+        &mut out,
+        "tesaiot_device_online",
+        "Whether the device is online (1) or not (0)",
+    );
+    for device in &connectivity.devices {
+        let online = if device.status == ConnectionStatus::Online {
+            1.0
+        } else {
+            0.0
+        };
+        write_sample(
+            &mut out,
+            "tesaiot_device_online",
+            &[
+                ("device_id", &device.device_id),
+                ("device_name", &device.device_name),
+            ],
+            online,
+        );
+    }
+
+    write_header(
+        &mut out,
+        "tesaiot_device_uptime_percent",
+        "Device uptime percentage",
+    );
+    for device in &connectivity.devices {
+        write_sample(
+            &mut out,
+            "tesaiot_device_uptime_percent",
+            &[
+                ("device_id", &device.device_id),
+                ("device_name", &device.device_name),
+            ],
+            device.uptime_percent,
+        );
+    }
+
+    out
+}
+
+fn write_header(out: &mut String, name: &str, help: &str) {
+    let _ = writeln!(out, "# HELP {} {}", name, escape_help(help));
+    let _ = writeln!(out, "# TYPE {} gauge", name);
+}
+
+fn write_sample(out: &mut String, name: &str, labels: &[(&str, &str)], value: f64) {
+    out.push_str(name);
+    if !labels.is_empty() {
+        out.push('{');
+        for (i, (key, val)) in labels.iter().enumerate() {
+            if i > 0 {
+                out.push(',');
+            }
+            let _ = write!(out, "{}=\"{}\"", key, escape_label_value(val));
+        }
+        out.push('}');
+    }
+    let _ = writeln!(out, " {}", format_value(value));
+}
+
+/// Escape a label value: backslash, double quote and line feed
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// Escape HELP text: backslash and line feed
+fn escape_help(help: &str) -> String {
+    help.replace('\\', "\\\\").replace('\n', "\\n")
+}
+
+fn format_value(value: f64) -> String {
+    if value.is_nan() {
+        "NaN".to_string()
+    } else if value.is_infinite() {
+        if value > 0.0 { "+Inf" } else { "-Inf" }.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{ConnectivitySummary, DeviceStatus};
+    use std::collections::HashMap;
+
+    fn device(id: &str, name: &str, status: ConnectionStatus, uptime: f64) -> DeviceStatus {
+        DeviceStatus {
+            device_id: id.to_string(),
+            device_name: name.to_string(),
+            status,
+            last_seen: String::new(),
+            uptime_percent: uptime,
+        }
+    }
+
+    #[test]
+    fn fleet_health_exact_output() {
+        let health = FleetHealth {
+            overall_score: 87.5,
+            component_scores: HashMap::from([
+                ("latency".to_string(), 80.0),
+                ("anomaly".to_string(), 100.0),
+            ]),
+            status: "GOOD".to_string(),
+        };
+
+        assert_eq!(
+            fleet_health_to_prometheus(&health),
+            "# HELP tesaiot_fleet_health_score Overall fleet health score (0-100)\n\
+             # TYPE tesaiot_fleet_health_score gauge\n\
+             tesaiot_fleet_health_score 87.5\n\
+             # HELP tesaiot_fleet_health_component_score Fleet health score per component (0-100)\n\
+             # TYPE tesaiot_fleet_health_component_score gauge\n\
+             tesaiot_fleet_health_component_score{component=\"anomaly\"} 100\n\
+             tesaiot_fleet_health_component_score{component=\"latency\"} 80\n"
+        );
+    }
+
+    #[test]
+    fn non_finite_values_use_prometheus_spellings() {
+        let health = FleetHealth {
+            overall_score: f64::NAN,
+            component_scores: HashMap::from([
+                ("a".to_string(), f64::INFINITY),
+                ("b".to_string(), f64::NEG_INFINITY),
+            ]),
+            status: String::new(),
+        };
+
+        let out = fleet_health_to_prometheus(&health);
+        let samples: Vec<&str> = out.lines().filter(|line| !line.starts_with('#')).collect();
+        assert_eq!(
+            samples,
+            [
+                "tesaiot_fleet_health_score NaN",
+                "tesaiot_fleet_health_component_score{component=\"a\"} +Inf",
+                "tesaiot_fleet_health_component_score{component=\"b\"} -Inf",
+            ]
+        );
+    }
+
+    #[test]
+    fn connectivity_exact_output_with_escaped_labels() {
+        let connectivity = ConnectivityResponse {
+            devices: vec![
+                device(
+                    "d1",
+                    "Boiler \"B\"\nC:\\temp",
+                    ConnectionStatus::Online,
+                    99.5,
+                ),
+                device("d2", "plain", ConnectionStatus::Offline, f64::NAN),
+            ],
+            summary: ConnectivitySummary {
+                total_devices: 4,
+                online_count: 2,
+                offline_count: 1,
+                unknown_count: 1,
+                ..Default::default()
+            },
+        };
+
+        let d1 = r#"device_id="d1",device_name="Boiler \"B\"\nC:\\temp""#;
+        let d2 = r#"device_id="d2",device_name="plain""#;
+        let expected = format!(
+            "# HELP tesaiot_devices_total Total number of devices\n\
+             # TYPE tesaiot_devices_total gauge\n\
+             tesaiot_devices_total 4\n\
+             # HELP tesaiot_devices_online Number of online devices\n\
+             # TYPE tesaiot_devices_online gauge\n\
+             tesaiot_devices_online 2\n\
+             # HELP tesaiot_devices_offline Number of offline devices\n\
+             # TYPE tesaiot_devices_offline gauge\n\
+             tesaiot_devices_offline 1\n\
+             # HELP tesaiot_devices_unknown Number of devices with unknown status\n\
+             # TYPE tesaiot_devices_unknown gauge\n\
+             tesaiot_devices_unknown 1\n\
+             # HELP tesaiot_devices_online_percentage Percentage of devices online\n\
+             # TYPE tesaiot_devices_online_percentage gauge\n\
+             tesaiot_devices_online_percentage 50\n\
+             # HELP tesaiot_device_online Whether the device is online (1) or not (0)\n\
+             # TYPE tesaiot_device_online gauge\n\
+             tesaiot_device_online{{{d1}}} 1\n\
+             tesaiot_device_online{{{d2}}} 0\n\
+             # HELP tesaiot_device_uptime_percent Device uptime percentage\n\
+             # TYPE tesaiot_device_uptime_percent gauge\n\
+             tesaiot_device_uptime_percent{{{d1}}} 99.5\n\
+             tesaiot_device_uptime_percent{{{d2}}} NaN\n",
+        );
+        assert_eq!(connectivity_to_prometheus(&connectivity), expected);
+    }
+
+    #[test]
+    fn help_text_escapes_backslash_and_newline() {
+        assert_eq!(escape_help("a\\b\nc \"d\""), "a\\\\b\\nc \"d\"");
+        let mut out = String::new();
+        write_header(&mut out, "m", "line one\nline two");
+        assert_eq!(out, "# HELP m line one\\nline two\n# TYPE m gauge\n");
+    }
+}
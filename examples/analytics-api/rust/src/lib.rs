@@ -4,8 +4,8 @@
 //!
 //! # Usage
 //!
-//! ```rust
-//! use tesaiot_analytics::{AnalyticsClient, TimeRange};
+//! ```rust,no_run
+//! use tesaiot_analytics::{AnalyticsClient, AnomalyQuery};
 //!
 //! #[tokio::main]
 //! async fn main() -> Result<(), Box<dyn std::error::Error>> {
@@ -14,19 +14,61 @@
 //!         "your_jwt_token"
 //!     )?;
 //!
-//!     let anomalies = client.get_anomalies(None, None, None, 100, 0).await?;
+//!     let anomalies = client.get_anomalies(AnomalyQuery::new().limit(100)).await?;
 //!     println!("Found {} anomalies", anomalies.summary.total);
 //!
 //!     Ok(())
 //! }
 //! ```
 
-use chrono::{DateTime, Duration, Utc};
+use chrono::{DateTime, Datelike, Duration, NaiveDate, NaiveTime, Utc};
 use reqwest::Client;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use thiserror::Error;
 
+pub mod api;
+mod cache;
+mod chart;
+mod circuit_breaker;
+mod clock;
+pub mod export;
+#[cfg(feature = "test-util")]
+pub mod mock;
+mod numeric;
+#[cfg(not(target_arch = "wasm32"))]
+mod retry;
+mod stream;
+#[cfg(test)]
+mod test_server;
+
+pub use api::AnalyticsApi;
+pub use cache::CacheConfig;
+pub use chart::render_bar_chart;
+pub use circuit_breaker::CircuitBreakerConfig;
+#[cfg(feature = "test-util")]
+pub use clock::FixedClock;
+pub use clock::{Clock, SystemClock};
+#[cfg(feature = "test-util")]
+pub use mock::MockAnalyticsClient;
+#[cfg(not(target_arch = "wasm32"))]
+pub use retry::RetryConfig;
+
+#[cfg(all(target_arch = "wasm32", not(feature = "wasm")))]
+compile_error!("building for wasm32 requires the `wasm` feature");
+
+// `std::time::Instant` panics in the browser
+#[cfg(not(target_arch = "wasm32"))]
+pub(crate) use std::time::Instant;
+#[cfg(target_arch = "wasm32")]
+pub(crate) use web_time::Instant;
+
+#[cfg(all(feature = "rustls-tls", feature = "native-tls"))]
+compile_error!(
+    "features `rustls-tls` and `native-tls` are mutually exclusive; \
+     use `default-features = false, features = [\"native-tls\"]` to select native-tls"
+);
+
 // ============================================================
 // Error Types
 // ============================================================
@@ -39,11 +81,29 @@ pub enum AnalyticsError {
     #[error("API error {status}: {message}")]
     Api { status: u16, message: String },
 
+    #[error("Unauthorized (401): {message}")]
+    Unauthorized { message: String },
+
+    #[error("Forbidden (403): {message}")]
+    Forbidden { message: String },
+
+    #[error("Request timed out after {elapsed:?}")]
+    Timeout { elapsed: std::time::Duration },
+
+    #[error("Circuit breaker open; retry in {retry_after:?}")]
+    CircuitOpen { retry_after: std::time::Duration },
+
+    #[error("Empty response body (HTTP {status})")]
+    EmptyResponse { status: u16 },
+
     #[error("Configuration error: {0}")]
     Config(String),
 
     #[error("Serialization error: {0}")]
     Serialization(#[from] serde_json::Error),
+
+    #[error("I/O error: {0}")]
+    Io(#[from] std::io::Error),
 }
 
 pub type Result<T> = std::result::Result<T, AnalyticsError>;
@@ -61,7 +121,12 @@ pub struct TimeRange {
 impl TimeRange {
     /// Create time range for last N days
     pub fn last_days(days: i64) -> Self {
-        let end = Utc::now();
+        Self::last_days_with(days, &SystemClock)
+    }
+
+    /// Like [`last_days`](Self::last_days), ending at `clock.now()`
+    pub fn last_days_with(days: i64, clock: &dyn Clock) -> Self {
+        let end = clock.now();
         let start = end - Duration::days(days);
         Self {
             start: start.to_rfc3339(),
@@ -71,7 +136,12 @@ impl TimeRange {
 
     /// Create time range for last N hours
     pub fn last_hours(hours: i64) -> Self {
-        let end = Utc::now();
+        Self::last_hours_with(hours, &SystemClock)
+    }
+
+    /// Like [`last_hours`](Self::last_hours), ending at `clock.now()`
+    pub fn last_hours_with(hours: i64, clock: &dyn Clock) -> Self {
+        let end = clock.now();
         let start = end - Duration::hours(hours);
         Self {
             start: start.to_rfc3339(),
@@ -86,12 +156,328 @@ impl TimeRange {
             end: end.to_rfc3339(),
         }
     }
+
+    /// Create time range from midnight UTC today until now
+    pub fn today() -> Self {
+        Self::today_with(&SystemClock)
+    }
+
+    /// Like [`today`](Self::today), using `clock` for now
+    pub fn today_with(clock: &dyn Clock) -> Self {
+        let end = clock.now();
+        Self::custom(Self::start_of_day(end.date_naive()), end)
+    }
+
+    /// Create time range from Monday 00:00 UTC of the current ISO week until now
+    pub fn this_week() -> Self {
+        Self::this_week_with(&SystemClock)
+    }
+
+    /// Like [`this_week`](Self::this_week), using `clock` for now
+    pub fn this_week_with(clock: &dyn Clock) -> Self {
+        let end = clock.now();
+        let today = end.date_naive();
+        let monday = today - Duration::days(today.weekday().num_days_from_monday() as i64);
+        Self::custom(Self::start_of_day(monday), end)
+    }
+
+    /// Create time range from the 1st of the current month 00:00 UTC until now
+    pub fn this_month() -> Self {
+        Self::this_month_with(&SystemClock)
+    }
+
+    /// Like [`this_month`](Self::this_month), using `clock` for now
+    pub fn this_month_with(clock: &dyn Clock) -> Self {
+        let end = clock.now();
+        let first = end
+            .date_naive()
+            .with_day(1)
+            .expect("day 1 exists in every month");
+        Self::custom(Self::start_of_day(first), end)
+    }
+
+    fn start_of_day(date: NaiveDate) -> DateTime<Utc> {
+        date.and_time(NaiveTime::MIN).and_utc()
+    }
+
+    /// Parse a shorthand window like "90m", "24h", "7d" or "2w" ending now
+    pub fn parse(spec: &str) -> Result<Self> {
+        Self::parse_with(spec, &SystemClock)
+    }
+
+    /// Like [`parse`](Self::parse), ending at `clock.now()`
+    pub fn parse_with(spec: &str, clock: &dyn Clock) -> Result<Self> {
+        let spec = spec.trim();
+        let invalid = || {
+            AnalyticsError::Config(format!(
+                "Invalid time range '{}': expected <number><m|h|d|w>, e.g. \"24h\"",
+                spec
+            ))
+        };
+
+        let unit = spec.chars().last().ok_or_else(invalid)?;
+        let amount: i64 = spec[..spec.len() - unit.len_utf8()]
+            .parse()
+            .map_err(|_| invalid())?;
+        if amount <= 0 {
+            return Err(invalid());
+        }
+
+        let duration = match unit {
+            'm' => Duration::try_minutes(amount),
+            'h' => Duration::try_hours(amount),
+            'd' => Duration::try_days(amount),
+            'w' => Duration::try_weeks(amount),
+            _ => None,
+        }
+        .ok_or_else(invalid)?;
+
+        let end = clock.now();
+        Ok(Self::custom(end - duration, end))
+    }
+
+    /// Parse the start of the range as RFC3339
+    pub fn start_dt(&self) -> Result<DateTime<Utc>> {
+        Self::parse_endpoint("start", &self.start)
+    }
+
+    /// Parse the end of the range as RFC3339
+    pub fn end_dt(&self) -> Result<DateTime<Utc>> {
+        Self::parse_endpoint("end", &self.end)
+    }
+
+    /// Check both endpoints are valid RFC3339 and start is before end
+    pub fn validate(&self) -> Result<()> {
+        let start = self.start_dt()?;
+        let end = self.end_dt()?;
+        if start >= end {
+            return Err(AnalyticsError::Config(format!(
+                "Invalid time range: start {} is not before end {}",
+                self.start, self.end
+            )));
+        }
+        Ok(())
+    }
+
+    /// Length of the range
+    pub fn duration(&self) -> Result<Duration> {
+        self.validate()?;
+        Ok(self.end_dt()? - self.start_dt()?)
+    }
+
+    fn parse_endpoint(name: &str, value: &str) -> Result<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(value)
+            .map(|dt| dt.with_timezone(&Utc))
+            .map_err(|e| {
+                AnalyticsError::Config(format!("Invalid time range {} '{}': {}", name, value, e))
+            })
+    }
 }
 
 // ============================================================
 // Data Models
 // ============================================================
 
+/// Severity levels used by anomalies and insights
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Severity {
+    Critical,
+    High,
+    Medium,
+    Low,
+    Warning,
+    Info,
+}
+
+impl Severity {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Severity::Critical => "critical",
+            Severity::High => "high",
+            Severity::Medium => "medium",
+            Severity::Low => "low",
+            Severity::Warning => "warning",
+            Severity::Info => "info",
+        }
+    }
+
+    /// Check whether a raw severity string from the API matches this level
+    pub fn matches(&self, value: &str) -> bool {
+        value.eq_ignore_ascii_case(self.as_str())
+    }
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+/// Server-side sort order for anomaly listings
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum AnomalySort {
+    ScoreDesc,
+    ScoreAsc,
+    TimestampDesc,
+    TimestampAsc,
+    SeverityDesc,
+}
+
+impl AnomalySort {
+    /// Value of the `sort` query parameter (`-` prefix means descending)
+    pub fn as_param(&self) -> &'static str {
+        match self {
+            AnomalySort::ScoreDesc => "-score",
+            AnomalySort::ScoreAsc => "score",
+            AnomalySort::TimestampDesc => "-timestamp",
+            AnomalySort::TimestampAsc => "timestamp",
+            AnomalySort::SeverityDesc => "-severity",
+        }
+    }
+}
+
+/// Filters, ordering and paging for [`AnalyticsClient::get_anomalies`]
+///
+/// ```
+/// use tesaiot_analytics::{AnomalyQuery, AnomalySort};
+///
+/// // Open critical/high temperature anomalies, highest score first
+/// let query = AnomalyQuery::new()
+///     .severities(["critical", "high"])
+///     .metrics(["temperature"])
+///     .acknowledged(false)
+///     .resolved(false)
+///     .sort(AnomalySort::ScoreDesc)
+///     .limit(50);
+/// ```
+#[derive(Debug, Clone, PartialEq)]
+pub struct AnomalyQuery {
+    hours: Option<i64>,
+    severities: Vec<String>,
+    device_ids: Vec<String>,
+    metrics: Vec<String>,
+    acknowledged: Option<bool>,
+    resolved: Option<bool>,
+    sort: Option<AnomalySort>,
+    limit: i64,
+    offset: i64,
+}
+
+impl Default for AnomalyQuery {
+    fn default() -> Self {
+        Self {
+            hours: None,
+            severities: Vec::new(),
+            device_ids: Vec::new(),
+            metrics: Vec::new(),
+            acknowledged: None,
+            resolved: None,
+            sort: None,
+            limit: DEFAULT_LIMIT,
+            offset: 0,
+        }
+    }
+}
+
+impl AnomalyQuery {
+    /// Every anomaly, server ordering, first [`DEFAULT_LIMIT`] results
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Only anomalies from the last `hours` hours
+    pub fn hours(mut self, hours: i64) -> Self {
+        self.hours = Some(hours);
+        self
+    }
+
+    /// Only these severities
+    pub fn severities(mut self, severities: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.severities = severities.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only these devices
+    pub fn device_ids(mut self, device_ids: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.device_ids = device_ids.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only these metrics; the server combines several as OR
+    pub fn metrics(mut self, metrics: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        self.metrics = metrics.into_iter().map(Into::into).collect();
+        self
+    }
+
+    /// Only anomalies whose acknowledged flag is `acknowledged`
+    pub fn acknowledged(mut self, acknowledged: bool) -> Self {
+        self.acknowledged = Some(acknowledged);
+        self
+    }
+
+    /// Only anomalies whose resolved flag is `resolved`
+    pub fn resolved(mut self, resolved: bool) -> Self {
+        self.resolved = Some(resolved);
+        self
+    }
+
+    /// Have the server order results before paging
+    pub fn sort(mut self, sort: AnomalySort) -> Self {
+        self.sort = Some(sort);
+        self
+    }
+
+    /// Page size; `get_anomalies` caps it at [`MAX_LIMIT`]
+    pub fn limit(mut self, limit: i64) -> Self {
+        self.limit = limit;
+        self
+    }
+
+    pub fn offset(mut self, offset: i64) -> Self {
+        self.offset = offset;
+        self
+    }
+
+    /// Query string parameters, sending `limit` as the page size
+    fn params(&self, limit: i64) -> Vec<(&'static str, String)> {
+        let mut params = vec![
+            ("limit", limit.to_string()),
+            ("offset", self.offset.to_string()),
+        ];
+
+        if let Some(hours) = self.hours {
+            params.push(("hours", hours.to_string()));
+        }
+
+        for severity in &self.severities {
+            params.push(("severity", severity.clone()));
+        }
+
+        for id in &self.device_ids {
+            params.push(("device_id", id.clone()));
+        }
+
+        // Repeated `metric` params are combined as OR by the server
+        for metric in &self.metrics {
+            params.push(("metric", metric.clone()));
+        }
+
+        if let Some(acknowledged) = self.acknowledged {
+            params.push(("acknowledged", acknowledged.to_string()));
+        }
+        if let Some(resolved) = self.resolved {
+            params.push(("resolved", resolved.to_string()));
+        }
+
+        if let Some(sort) = self.sort {
+            params.push(("sort", sort.as_param().to_string()));
+        }
+
+        params
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Anomaly {
     pub id: String,
@@ -108,34 +494,179 @@ pub struct Anomaly {
     pub resolved: bool,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AnomalySummary {
+    #[serde(deserialize_with = "numeric::int")]
     pub total: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int_map")]
     pub by_severity: HashMap<String, i64>,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int_map")]
     pub by_metric: HashMap<String, i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct AnomaliesResponse {
     pub anomalies: Vec<Anomaly>,
     pub summary: AnomalySummary,
 }
 
+impl AnomaliesResponse {
+    /// Write anomalies as CSV with a header row
+    pub fn to_csv(&self, mut writer: impl std::io::Write) -> Result<()> {
+        writeln!(
+            writer,
+            "id,device_id,device_name,metric,value,severity,score,timestamp,acknowledged,resolved"
+        )?;
+
+        for a in &self.anomalies {
+            writeln!(
+                writer,
+                "{},{},{},{},{},{},{},{},{},{}",
+                csv_field(&a.id),
+                csv_field(&a.device_id),
+                csv_field(&a.device_name),
+                csv_field(&a.metric),
+                a.value,
+                csv_field(&a.severity),
+                a.score,
+                csv_field(&a.timestamp),
+                a.acknowledged,
+                a.resolved
+            )?;
+        }
+
+        Ok(())
+    }
+
+    /// The `n` highest-scoring anomalies, highest first. NaN scores sort last.
+    pub fn top_by_score(&self, n: usize) -> Vec<&Anomaly> {
+        let mut sorted: Vec<&Anomaly> = self.anomalies.iter().collect();
+        sorted.sort_by(|a, b| score_descending(a.score, b.score));
+        sorted.truncate(n);
+        sorted
+    }
+
+    /// Anomalies with a score of at least `min`, in their original order
+    pub fn filter_min_score(&self, min: f64) -> Vec<&Anomaly> {
+        self.anomalies.iter().filter(|a| a.score >= min).collect()
+    }
+
+    /// Anomalies bucketed by device id, each bucket in the original order
+    pub fn group_by_device(&self) -> HashMap<String, Vec<&Anomaly>> {
+        self.group_by(|a| &a.device_id)
+    }
+
+    /// Anomalies bucketed by metric name, each bucket in the original order
+    pub fn group_by_metric(&self) -> HashMap<String, Vec<&Anomaly>> {
+        self.group_by(|a| &a.metric)
+    }
+
+    /// Drop later anomalies whose id was already seen, keeping the first
+    /// occurrence, and reduce `summary.total` by the number removed
+    pub fn dedup_by_id(&mut self) {
+        let before = self.anomalies.len();
+        let mut seen = HashSet::new();
+        self.anomalies.retain(|a| seen.insert(a.id.clone()));
+        let removed = (before - self.anomalies.len()) as i64;
+        self.summary.total = (self.summary.total - removed).max(0);
+    }
+
+    fn group_by(&self, key: impl Fn(&Anomaly) -> &String) -> HashMap<String, Vec<&Anomaly>> {
+        let mut groups: HashMap<String, Vec<&Anomaly>> = HashMap::new();
+        for anomaly in &self.anomalies {
+            groups
+                .entry(key(anomaly).clone())
+                .or_default()
+                .push(anomaly);
+        }
+        groups
+    }
+}
+
+/// Order scores highest first, placing NaN after every number
+fn score_descending(a: f64, b: f64) -> std::cmp::Ordering {
+    match (a.is_nan(), b.is_nan()) {
+        (true, true) => std::cmp::Ordering::Equal,
+        (true, false) => std::cmp::Ordering::Greater,
+        (false, true) => std::cmp::Ordering::Less,
+        (false, false) => b.total_cmp(&a),
+    }
+}
+
+/// Quote a CSV field if it contains a delimiter, quote or line break
+fn csv_field(value: &str) -> std::borrow::Cow<'_, str> {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\"")).into()
+    } else {
+        value.into()
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TimelinePoint {
     pub date: String,
     pub counts: HashMap<String, i64>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Direction of an anomaly trend, with a fallback for values this client doesn't know yet
+#[derive(Debug, Clone, Default, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum TrendDirection {
+    Up,
+    Down,
+    #[default]
+    Stable,
+    Other(String),
+}
+
+impl TrendDirection {
+    pub fn as_str(&self) -> &str {
+        match self {
+            TrendDirection::Up => "up",
+            TrendDirection::Down => "down",
+            TrendDirection::Stable => "stable",
+            TrendDirection::Other(s) => s,
+        }
+    }
+}
+
+impl From<String> for TrendDirection {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "up" => TrendDirection::Up,
+            "down" => TrendDirection::Down,
+            "stable" => TrendDirection::Stable,
+            _ => TrendDirection::Other(s),
+        }
+    }
+}
+
+impl From<TrendDirection> for String {
+    fn from(direction: TrendDirection) -> Self {
+        direction.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for TrendDirection {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct Trend {
-    pub direction: String,
+    pub direction: TrendDirection,
     pub percent_change: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Trend {
+    /// Whether anomalies are increasing (an upward anomaly trend is bad)
+    pub fn is_worsening(&self) -> bool {
+        self.direction == TrendDirection::Up
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct TimelineResponse {
     pub timeline: Vec<TimelinePoint>,
     pub trend: Trend,
@@ -150,14 +681,82 @@ pub struct Cluster {
     pub devices: Vec<String>,
 }
 
+/// Category of an outlier's free-text reason, with a fallback for reasons
+/// this client doesn't recognise
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum OutlierReason {
+    HighVariance,
+    Spike,
+    Flatline,
+    Other(String),
+}
+
+impl OutlierReason {
+    /// Classify a reason string
+    ///
+    /// The whole reason must name a category: matching ignores case and
+    /// treats spaces and hyphens like underscores, so "High variance" is
+    /// `HighVariance` but "low_variance" is `Other`. Unrecognised reasons keep
+    /// the raw string.
+    pub fn classify(reason: &str) -> Self {
+        let normalized = reason.trim().to_lowercase().replace([' ', '-'], "_");
+        match normalized.as_str() {
+            "high_variance" | "variance" => OutlierReason::HighVariance,
+            "spike" | "spikes" => OutlierReason::Spike,
+            "flatline" | "flat_line" | "flat" => OutlierReason::Flatline,
+            _ => OutlierReason::Other(reason.to_string()),
+        }
+    }
+
+    pub fn as_str(&self) -> &str {
+        match self {
+            OutlierReason::HighVariance => "high_variance",
+            OutlierReason::Spike => "spike",
+            OutlierReason::Flatline => "flatline",
+            OutlierReason::Other(s) => s,
+        }
+    }
+}
+
+impl From<String> for OutlierReason {
+    fn from(s: String) -> Self {
+        OutlierReason::classify(&s)
+    }
+}
+
+impl From<OutlierReason> for String {
+    fn from(reason: OutlierReason) -> Self {
+        reason.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for OutlierReason {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Outlier {
     pub device_id: String,
     pub outlier_score: f64,
+    /// Free-text reason as sent by the server; see
+    /// [`reason_category`](Self::reason_category) for grouping
     pub reason: Option<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl Outlier {
+    /// Category of `reason`, or `None` when the server gave no reason
+    pub fn reason_category(&self) -> Option<OutlierReason> {
+        self.reason
+            .as_deref()
+            .filter(|reason| !reason.trim().is_empty())
+            .map(OutlierReason::classify)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ClustersResponse {
     pub clusters: Vec<Cluster>,
     pub silhouette_score: f64,
@@ -165,11 +764,170 @@ pub struct ClustersResponse {
     pub outliers: Vec<Outlier>,
 }
 
+impl ClustersResponse {
+    /// Find the cluster a device belongs to, if any
+    pub fn cluster_for_device(&self, device_id: &str) -> Option<&Cluster> {
+        self.clusters
+            .iter()
+            .find(|c| c.devices.iter().any(|d| d == device_id))
+    }
+
+    /// Interpret the silhouette score
+    pub fn clustering_quality(&self) -> ClusteringQuality {
+        ClusteringQuality::from_silhouette(self.silhouette_score)
+    }
+}
+
+/// Banding of a silhouette score in [-1, 1]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ClusteringQuality {
+    Poor,
+    Weak,
+    Reasonable,
+    Strong,
+}
+
+impl ClusteringQuality {
+    /// Grade a score: ≥0.7 strong, ≥0.5 reasonable, ≥0.25 weak, else poor
+    pub fn from_silhouette(score: f64) -> Self {
+        if score >= 0.7 {
+            ClusteringQuality::Strong
+        } else if score >= 0.5 {
+            ClusteringQuality::Reasonable
+        } else if score >= 0.25 {
+            ClusteringQuality::Weak
+        } else {
+            ClusteringQuality::Poor
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ClusteringQuality::Strong => "strong",
+            ClusteringQuality::Reasonable => "reasonable",
+            ClusteringQuality::Weak => "weak",
+            ClusteringQuality::Poor => "poor",
+        }
+    }
+
+    /// One-line guidance on how far to trust the clusters
+    pub fn explanation(&self) -> &'static str {
+        match self {
+            ClusteringQuality::Strong => {
+                "Clusters are well separated; device groupings are reliable"
+            }
+            ClusteringQuality::Reasonable => {
+                "Clusters are distinct with some overlap; groupings are usable"
+            }
+            ClusteringQuality::Weak => {
+                "Clusters overlap heavily; try a different n_clusters or metric"
+            }
+            ClusteringQuality::Poor => {
+                "No meaningful structure found; treat cluster assignments as noise"
+            }
+        }
+    }
+}
+
+impl std::fmt::Display for ClusteringQuality {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+struct DeviceClusterResponse {
+    #[serde(default)]
+    cluster: Option<Cluster>,
+}
+
+/// Clustering algorithm and its parameters
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(tag = "algorithm", rename_all = "lowercase")]
+pub enum ClusterAlgorithm {
+    /// K-means with a fixed number of clusters
+    KMeans { n_clusters: i32 },
+    /// Density-based clustering (DBSCAN)
+    Dbscan { eps: f64, min_samples: i32 },
+}
+
+impl ClusterAlgorithm {
+    /// Check the parameters before they are sent to the server
+    pub fn validate(&self) -> Result<()> {
+        if let ClusterAlgorithm::KMeans { n_clusters } = self {
+            if *n_clusters < 2 {
+                return Err(AnalyticsError::Config(format!(
+                    "n_clusters must be at least 2 for K-means, got {}",
+                    n_clusters
+                )));
+            }
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct OutliersResponse {
+    #[serde(default)]
+    pub outliers: Vec<Outlier>,
+}
+
+/// Insight category, with a fallback for values this client doesn't know yet
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum InsightType {
+    AnomalyPattern,
+    AnomalySpike,
+    Trend,
+    Correlation,
+    Recommendation,
+    Other(String),
+}
+
+impl InsightType {
+    pub fn as_str(&self) -> &str {
+        match self {
+            InsightType::AnomalyPattern => "anomaly_pattern",
+            InsightType::AnomalySpike => "anomaly_spike",
+            InsightType::Trend => "trend",
+            InsightType::Correlation => "correlation",
+            InsightType::Recommendation => "recommendation",
+            InsightType::Other(s) => s,
+        }
+    }
+}
+
+impl From<String> for InsightType {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "anomaly_pattern" => InsightType::AnomalyPattern,
+            "anomaly_spike" => InsightType::AnomalySpike,
+            "trend" => InsightType::Trend,
+            "correlation" => InsightType::Correlation,
+            "recommendation" => InsightType::Recommendation,
+            _ => InsightType::Other(s),
+        }
+    }
+}
+
+impl From<InsightType> for String {
+    fn from(insight_type: InsightType) -> Self {
+        insight_type.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for InsightType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Insight {
     pub id: String,
     #[serde(rename = "type")]
-    pub insight_type: String,
+    pub insight_type: InsightType,
     pub severity: String,
     pub title: String,
     pub description: String,
@@ -179,98 +937,416 @@ pub struct Insight {
     pub recommended_actions: Vec<String>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct FleetSummary {
+    #[serde(deserialize_with = "numeric::int")]
     pub total_devices: i64,
+    #[serde(deserialize_with = "numeric::int")]
     pub active_devices: i64,
+    #[serde(deserialize_with = "numeric::float")]
     pub anomaly_rate: f64,
+    #[serde(deserialize_with = "numeric::float")]
     pub health_score: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl FleetSummary {
+    /// Grade band of `health_score`
+    pub fn grade(&self) -> HealthGrade {
+        HealthGrade::from_score(self.health_score)
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct InsightsResponse {
     pub insights: Vec<Insight>,
     pub fleet_summary: FleetSummary,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct DeviceStatus {
-    pub device_id: String,
-    pub device_name: String,
-    pub status: String,
-    pub last_seen: String,
-    pub uptime_percent: f64,
-}
+impl InsightsResponse {
+    /// Insights flagged as actionable
+    pub fn actionable(&self) -> Vec<&Insight> {
+        self.insights.iter().filter(|i| i.actionable).collect()
+    }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
-pub struct ConnectivitySummary {
-    #[serde(alias = "total", alias = "total_devices")]
+    /// Insights with the given severity
+    pub fn by_severity(&self, severity: Severity) -> Vec<&Insight> {
+        self.insights
+            .iter()
+            .filter(|i| severity.matches(&i.severity))
+            .collect()
+    }
+
+    /// Insights of the given type
+    pub fn of_type(&self, insight_type: InsightType) -> Vec<&Insight> {
+        self.insights
+            .iter()
+            .filter(|i| i.insight_type == insight_type)
+            .collect()
+    }
+
+    /// Insights sorted by confidence, highest first (ties keep API order)
+    pub fn sorted_by_confidence(&self) -> Vec<&Insight> {
+        let mut sorted: Vec<&Insight> = self.insights.iter().collect();
+        sorted.sort_by(|a, b| b.confidence.total_cmp(&a.confidence));
+        sorted
+    }
+}
+
+/// Device connection status, with a fallback for values this client doesn't know yet
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(from = "String", into = "String")]
+pub enum ConnectionStatus {
+    Online,
+    Offline,
+    Unknown,
+    Other(String),
+}
+
+impl ConnectionStatus {
+    pub fn as_str(&self) -> &str {
+        match self {
+            ConnectionStatus::Online => "online",
+            ConnectionStatus::Offline => "offline",
+            ConnectionStatus::Unknown => "unknown",
+            ConnectionStatus::Other(s) => s,
+        }
+    }
+}
+
+impl From<String> for ConnectionStatus {
+    fn from(s: String) -> Self {
+        match s.to_lowercase().as_str() {
+            "online" => ConnectionStatus::Online,
+            "offline" => ConnectionStatus::Offline,
+            "unknown" => ConnectionStatus::Unknown,
+            _ => ConnectionStatus::Other(s),
+        }
+    }
+}
+
+impl From<ConnectionStatus> for String {
+    fn from(status: ConnectionStatus) -> Self {
+        status.as_str().to_string()
+    }
+}
+
+impl std::fmt::Display for ConnectionStatus {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceStatus {
+    pub device_id: String,
+    pub device_name: String,
+    pub status: ConnectionStatus,
+    pub last_seen: String,
+    pub uptime_percent: f64,
+}
+
+impl DeviceStatus {
+    /// Parse `last_seen` as RFC3339
+    pub fn last_seen_dt(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.last_seen)
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ConnectivitySummary {
+    #[serde(
+        alias = "total",
+        alias = "total_devices",
+        deserialize_with = "numeric::int"
+    )]
     pub total_devices: i64,
-    #[serde(alias = "online", alias = "online_count")]
+    #[serde(
+        alias = "online",
+        alias = "online_count",
+        deserialize_with = "numeric::int"
+    )]
     pub online_count: i64,
-    #[serde(alias = "offline", alias = "offline_count")]
+    #[serde(
+        alias = "offline",
+        alias = "offline_count",
+        deserialize_with = "numeric::int"
+    )]
     pub offline_count: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int")]
     pub unknown_count: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::float")]
     pub online_percentage: f64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::float")]
     pub avg_connection_duration_hours: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ConnectivitySummary {
+    /// Online percentage, derived from the counts when the server omits it
+    pub fn computed_online_percentage(&self) -> f64 {
+        if self.online_percentage == 0.0 && self.online_count > 0 && self.total_devices > 0 {
+            self.online_count as f64 / self.total_devices as f64 * 100.0
+        } else {
+            self.online_percentage
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ConnectivityResponse {
     pub devices: Vec<DeviceStatus>,
     pub summary: ConnectivitySummary,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ConnectivityResponse {
+    /// Devices whose name or id contains `query`, ignoring case
+    pub fn search(&self, query: &str) -> Vec<&DeviceStatus> {
+        let query = query.to_lowercase();
+        self.devices
+            .iter()
+            .filter(|device| device_matches(device, &query))
+            .collect()
+    }
+
+    /// Devices not seen within `max_age` of now. Devices whose `last_seen`
+    /// can't be parsed are treated as stale; use
+    /// [`unparseable_last_seen`](Self::unparseable_last_seen) to report them.
+    pub fn stale_devices(&self, max_age: Duration) -> Vec<&DeviceStatus> {
+        self.stale_devices_with(max_age, &SystemClock)
+    }
+
+    /// Like [`stale_devices`](Self::stale_devices), using `clock` for now
+    pub fn stale_devices_with(&self, max_age: Duration, clock: &dyn Clock) -> Vec<&DeviceStatus> {
+        let cutoff = clock.now() - max_age;
+        self.devices
+            .iter()
+            .filter(|d| match d.last_seen_dt() {
+                Some(seen) => seen < cutoff,
+                None => {
+                    #[cfg(feature = "tracing")]
+                    tracing::warn!(
+                        device_id = %d.device_id,
+                        last_seen = %d.last_seen,
+                        "unparseable last_seen, treating device as stale"
+                    );
+                    true
+                }
+            })
+            .collect()
+    }
+
+    /// Devices whose `last_seen` isn't a valid RFC3339 timestamp
+    pub fn unparseable_last_seen(&self) -> Vec<&DeviceStatus> {
+        self.devices
+            .iter()
+            .filter(|d| d.last_seen_dt().is_none())
+            .collect()
+    }
+
+    /// Devices ordered by `uptime_percent` ascending, ties broken by device id
+    pub fn sorted_by_uptime(&self) -> Vec<&DeviceStatus> {
+        let mut sorted: Vec<&DeviceStatus> = self.devices.iter().collect();
+        sorted.sort_by(|a, b| {
+            a.uptime_percent
+                .total_cmp(&b.uptime_percent)
+                .then_with(|| a.device_id.cmp(&b.device_id))
+        });
+        sorted
+    }
+
+    /// The `n` devices with the lowest uptime
+    pub fn lowest_uptime(&self, n: usize) -> Vec<&DeviceStatus> {
+        let mut sorted = self.sorted_by_uptime();
+        sorted.truncate(n);
+        sorted
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LatencySummary {
-    #[serde(alias = "avg_latency_ms", alias = "overall_avg_ms")]
+    #[serde(
+        alias = "avg_latency_ms",
+        alias = "overall_avg_ms",
+        deserialize_with = "numeric::float"
+    )]
     pub overall_avg_ms: f64,
-    #[serde(alias = "p95_latency_ms", alias = "overall_p95_ms")]
+    #[serde(
+        alias = "p95_latency_ms",
+        alias = "overall_p95_ms",
+        deserialize_with = "numeric::float"
+    )]
     pub overall_p95_ms: f64,
-    #[serde(alias = "p99_latency_ms", alias = "overall_p99_ms")]
+    #[serde(
+        alias = "p99_latency_ms",
+        alias = "overall_p99_ms",
+        deserialize_with = "numeric::float"
+    )]
     pub overall_p99_ms: f64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int")]
     pub devices_with_high_latency: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::float")]
     pub high_latency_threshold_ms: f64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct DeviceLatency {
+    #[serde(default)]
+    pub device_id: String,
+    #[serde(alias = "avg_latency_ms", default)]
+    pub avg_ms: f64,
+    #[serde(alias = "p95_latency_ms", default)]
+    pub p95_ms: f64,
+    #[serde(alias = "p99_latency_ms", default)]
+    pub p99_ms: f64,
+    #[serde(alias = "samples", default)]
+    pub sample_count: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct LatencyResponse {
     pub summary: LatencySummary,
     #[serde(default)]
-    pub devices: Vec<HashMap<String, serde_json::Value>>,
+    pub devices: Vec<DeviceLatency>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+/// Threshold used when the server doesn't report `high_latency_threshold_ms`
+pub const DEFAULT_HIGH_LATENCY_THRESHOLD_MS: f64 = 500.0;
+
+impl LatencyResponse {
+    /// Devices whose p95 latency exceeds the summary's high-latency threshold
+    /// (or [`DEFAULT_HIGH_LATENCY_THRESHOLD_MS`] when it is zero or unset)
+    pub fn high_latency_devices(&self) -> Vec<&DeviceLatency> {
+        let threshold = if self.summary.high_latency_threshold_ms > 0.0 {
+            self.summary.high_latency_threshold_ms
+        } else {
+            DEFAULT_HIGH_LATENCY_THRESHOLD_MS
+        };
+        self.devices
+            .iter()
+            .filter(|d| d.p95_ms > threshold)
+            .collect()
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ThroughputSummary {
-    #[serde(alias = "total_messages", alias = "total_messages_in")]
+    #[serde(
+        alias = "total_messages",
+        alias = "total_messages_in",
+        deserialize_with = "numeric::int"
+    )]
     pub total_messages_in: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int")]
     pub total_messages_out: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int")]
     pub total_bytes_in: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::int")]
     pub total_bytes_out: i64,
-    #[serde(alias = "avg_per_hour", alias = "avg_messages_per_minute")]
+    #[serde(
+        alias = "avg_per_hour",
+        alias = "avg_messages_per_minute",
+        deserialize_with = "numeric::float"
+    )]
     pub avg_messages_per_minute: f64,
-    #[serde(alias = "peak_per_hour", alias = "peak_messages_per_minute", default)]
+    #[serde(
+        alias = "peak_per_hour",
+        alias = "peak_messages_per_minute",
+        default,
+        deserialize_with = "numeric::int"
+    )]
     pub peak_messages_per_minute: i64,
-    #[serde(default)]
+    #[serde(default, deserialize_with = "numeric::float")]
     pub avg_active_connections: f64,
 }
 
+impl ThroughputSummary {
+    /// `total_bytes_in` formatted with base-1024 units, e.g. "1.4 GiB"
+    pub fn bytes_in_human(&self) -> String {
+        format_bytes(self.total_bytes_in)
+    }
+
+    /// `total_bytes_out` formatted with base-1024 units, e.g. "1.4 GiB"
+    pub fn bytes_out_human(&self) -> String {
+        format_bytes(self.total_bytes_out)
+    }
+}
+
+/// Format a byte count with base-1024 units; negative counts are shown as 0 B
+fn format_bytes(bytes: i64) -> String {
+    const UNITS: [&str; 6] = ["KiB", "MiB", "GiB", "TiB", "PiB", "EiB"];
+
+    let bytes = bytes.max(0);
+    if bytes < 1024 {
+        return format!("{} B", bytes);
+    }
+
+    let mut value = bytes as f64 / 1024.0;
+    let mut unit = 0;
+    // Move up a unit if one decimal place would round to "1024.0"
+    while value >= 1023.95 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    format!("{:.1} {}", value, UNITS[unit])
+}
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ThroughputPoint {
+    #[serde(alias = "time", alias = "hour", alias = "bucket")]
+    pub timestamp: String,
+    #[serde(alias = "messages", alias = "message_count", default)]
+    pub messages_in: i64,
+    #[serde(default)]
+    pub messages_out: i64,
+    #[serde(alias = "bytes", default)]
+    pub bytes_in: i64,
+    #[serde(default)]
+    pub bytes_out: i64,
+}
+
+impl ThroughputPoint {
+    /// Parse the point timestamp as RFC3339
+    pub fn timestamp_dt(&self) -> Option<DateTime<Utc>> {
+        DateTime::parse_from_rfc3339(&self.timestamp)
+            .ok()
+            .map(|dt| dt.with_timezone(&Utc))
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceThroughput {
+    pub device_id: String,
+    #[serde(alias = "messages", alias = "message_count", default)]
+    pub messages_in: i64,
+    #[serde(default)]
+    pub messages_out: i64,
+    #[serde(alias = "bytes", default)]
+    pub bytes_in: i64,
+    #[serde(default)]
+    pub bytes_out: i64,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct ThroughputResponse {
     pub summary: ThroughputSummary,
     #[serde(default)]
-    pub timeline: Vec<HashMap<String, serde_json::Value>>,
+    pub timeline: Vec<ThroughputPoint>,
+    #[serde(default)]
+    pub devices: Vec<DeviceThroughput>,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl ThroughputResponse {
+    /// The `n` devices sending the most messages, busiest first
+    pub fn top_talkers(&self, n: usize) -> Vec<&DeviceThroughput> {
+        let mut sorted: Vec<&DeviceThroughput> = self.devices.iter().collect();
+        sorted.sort_by_key(|d| std::cmp::Reverse(d.messages_in));
+        sorted.truncate(n);
+        sorted
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QualityDistribution {
     pub excellent: i64,
     pub good: i64,
@@ -278,229 +1354,3637 @@ pub struct QualityDistribution {
     pub poor: i64,
 }
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+impl QualityDistribution {
+    /// Number of devices across all bands
+    pub fn total(&self) -> i64 {
+        self.excellent + self.good + self.fair + self.poor
+    }
+
+    /// Each band's share of the total as a percentage (all 0.0 when empty)
+    pub fn percentages(&self) -> HashMap<&'static str, f64> {
+        let total = self.total();
+        let pct = |count: i64| {
+            if total > 0 {
+                count as f64 / total as f64 * 100.0
+            } else {
+                0.0
+            }
+        };
+
+        HashMap::from([
+            ("excellent", pct(self.excellent)),
+            ("good", pct(self.good)),
+            ("fair", pct(self.fair)),
+            ("poor", pct(self.poor)),
+        ])
+    }
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QualitySummary {
+    #[serde(deserialize_with = "numeric::float")]
     pub average_quality_score: f64,
     pub distribution: QualityDistribution,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct QualityIssue {
+    pub device_id: String,
+    #[serde(alias = "type", alias = "issue", default)]
+    pub issue_type: String,
+    #[serde(default)]
+    pub severity: String,
+    #[serde(alias = "message", default)]
+    pub description: String,
+    #[serde(alias = "timestamp", alias = "detected", default)]
+    pub detected_at: String,
+}
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
 pub struct QualityResponse {
     pub summary: QualitySummary,
     #[serde(default)]
-    pub issues: Vec<HashMap<String, serde_json::Value>>,
+    pub issues: Vec<QualityIssue>,
+}
+
+impl QualityResponse {
+    /// Distinct ids of devices with a critical or high severity issue, in the
+    /// order they first appear. The API doesn't score issues individually, so
+    /// severity stands in for the `poor` band.
+    pub fn poor_device_ids(&self) -> Vec<&str> {
+        let mut seen = HashSet::new();
+        self.issues
+            .iter()
+            .filter(|issue| {
+                Severity::Critical.matches(&issue.severity)
+                    || Severity::High.matches(&issue.severity)
+            })
+            .map(|issue| issue.device_id.as_str())
+            .filter(|id| seen.insert(*id))
+            .collect()
+    }
+}
+
+/// Rolled-up status of a single device across anomalies, connectivity, and quality
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DeviceHealth {
+    #[serde(default)]
+    pub device_id: String,
+    pub health_score: f64,
+    #[serde(default)]
+    pub anomaly_count: i64,
+    pub status: String,
+    #[serde(default)]
+    pub avg_latency_ms: Option<f64>,
+    #[serde(default)]
+    pub quality_score: Option<f64>,
 }
 
 // ============================================================
-// Analytics Client
+// Fleet Health
 // ============================================================
 
-pub struct AnalyticsClient {
-    client: Client,
-    base_url: String,
-    api_token: String,
+/// Relative weights of each component in the overall fleet health score
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetHealthWeights {
+    pub anomaly: f64,
+    pub connectivity: f64,
+    pub latency: f64,
+    pub insights: f64,
 }
 
-impl AnalyticsClient {
-    /// Create a new Analytics client
-    pub fn new(base_url: &str, api_token: &str) -> Result<Self> {
-        if api_token.is_empty() {
-            return Err(AnalyticsError::Config(
-                "API token is required".to_string(),
-            ));
+impl Default for FleetHealthWeights {
+    fn default() -> Self {
+        Self {
+            anomaly: 0.3,
+            connectivity: 0.3,
+            latency: 0.2,
+            insights: 0.2,
         }
+    }
+}
 
-        let client = Client::builder()
-            .timeout(std::time::Duration::from_secs(30))
-            .build()?;
+/// Banding of a 0-100 health score
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum HealthGrade {
+    Critical,
+    Poor,
+    Fair,
+    Good,
+    Excellent,
+}
 
-        Ok(Self {
-            client,
-            base_url: base_url.to_string(),
-            api_token: api_token.to_string(),
-        })
+impl HealthGrade {
+    /// Grade a score: ≥90 excellent, ≥70 good, ≥50 fair, ≥30 poor, else critical
+    pub fn from_score(score: f64) -> Self {
+        if score >= 90.0 {
+            HealthGrade::Excellent
+        } else if score >= 70.0 {
+            HealthGrade::Good
+        } else if score >= 50.0 {
+            HealthGrade::Fair
+        } else if score >= 30.0 {
+            HealthGrade::Poor
+        } else {
+            HealthGrade::Critical
+        }
     }
 
-    /// Create client from environment variables
-    pub fn from_env() -> Result<Self> {
-        let base_url = std::env::var("TESAIOT_API_URL")
-            .unwrap_or_else(|_| "https://admin.tesaiot.com/api/v1/bdh-ai".to_string());
-        let api_token = std::env::var("TESAIOT_API_TOKEN")
-            .map_err(|_| AnalyticsError::Config("TESAIOT_API_TOKEN not set".to_string()))?;
-
-        Self::new(&base_url, &api_token)
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            HealthGrade::Excellent => "EXCELLENT",
+            HealthGrade::Good => "GOOD",
+            HealthGrade::Fair => "FAIR",
+            HealthGrade::Poor => "POOR",
+            HealthGrade::Critical => "CRITICAL",
+        }
     }
+}
 
-    /// Make a POST request
-    async fn post<T: Serialize, R: for<'de> Deserialize<'de>>(
-        &self,
-        path: &str,
-        body: &T,
-    ) -> Result<R> {
-        let url = format!("{}{}", self.base_url, path);
-        let response = self
-            .client
-            .post(&url)
-            .header("X-API-KEY", &self.api_token)
-            .header("Content-Type", "application/json")
-            .json(body)
-            .send()
-            .await?;
+impl std::fmt::Display for HealthGrade {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.as_str())
+    }
+}
 
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let message = response.text().await.unwrap_or_default();
-            return Err(AnalyticsError::Api { status, message });
-        }
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct FleetHealth {
+    pub overall_score: f64,
+    pub component_scores: HashMap<String, f64>,
+    pub status: String,
+}
 
-        Ok(response.json().await?)
+impl FleetHealth {
+    /// Compute fleet health using the default component weights
+    pub fn compute(
+        anomalies: &AnomaliesResponse,
+        connectivity: &ConnectivityResponse,
+        insights: &InsightsResponse,
+        latency: &LatencyResponse,
+    ) -> Self {
+        Self::compute_with_weights(
+            anomalies,
+            connectivity,
+            insights,
+            latency,
+            &FleetHealthWeights::default(),
+        )
     }
 
-    /// Make a GET request
-    async fn get<R: for<'de> Deserialize<'de>>(
-        &self,
-        path: &str,
-        params: &[(&str, String)],
-    ) -> Result<R> {
-        let url = format!("{}{}", self.base_url, path);
-        let mut request = self
-            .client
-            .get(&url)
-            .header("X-API-KEY", &self.api_token);
+    /// Compute fleet health using custom component weights
+    pub fn compute_with_weights(
+        anomalies: &AnomaliesResponse,
+        connectivity: &ConnectivityResponse,
+        insights: &InsightsResponse,
+        latency: &LatencyResponse,
+        weights: &FleetHealthWeights,
+    ) -> Self {
+        let mut scores = HashMap::new();
 
-        for (key, value) in params {
-            if !value.is_empty() {
-                request = request.query(&[(key, value)]);
-            }
-        }
+        // Anomaly score
+        let total_devices = connectivity.summary.total_devices.max(1);
+        let anomaly_rate = anomalies.summary.total as f64 / total_devices as f64;
+        scores.insert(
+            "anomaly".to_string(),
+            (100.0 - anomaly_rate * 1000.0).max(0.0),
+        );
 
-        let response = request.send().await?;
+        // Connectivity score
+        let online_pct = connectivity.summary.online_count as f64 / total_devices as f64 * 100.0;
+        scores.insert("connectivity".to_string(), online_pct);
 
-        if !response.status().is_success() {
-            let status = response.status().as_u16();
-            let message = response.text().await.unwrap_or_default();
-            return Err(AnalyticsError::Api { status, message });
+        // Latency score
+        let latency_score = (100.0 - latency.summary.overall_p95_ms / 10.0).max(0.0);
+        scores.insert("latency".to_string(), latency_score);
+
+        // Insights severity score
+        let critical_count = insights.by_severity(Severity::Critical).len();
+        let warning_count = insights.by_severity(Severity::Warning).len();
+        let insights_score =
+            (100.0 - (critical_count as f64 * 20.0) - (warning_count as f64 * 5.0)).max(0.0);
+        scores.insert("insights".to_string(), insights_score);
+
+        // Calculate weighted overall
+        let overall = scores["anomaly"] * weights.anomaly
+            + scores["connectivity"] * weights.connectivity
+            + scores["latency"] * weights.latency
+            + scores["insights"] * weights.insights;
+
+        Self {
+            overall_score: (overall * 10.0).round() / 10.0,
+            component_scores: scores,
+            status: HealthGrade::from_score(overall).to_string(),
         }
+    }
 
-        Ok(response.json().await?)
+    /// Grade band of the overall score
+    pub fn grade(&self) -> HealthGrade {
+        HealthGrade::from_score(self.overall_score)
     }
+}
 
-    // --------------------------------------------------------
-    // Anomaly Detection APIs
-    // --------------------------------------------------------
+// ============================================================
+// Alerts
+// ============================================================
 
-    /// Get aggregated anomalies
-    /// Note: API uses GET method with query parameters
-    pub async fn get_anomalies(
-        &self,
-        _time_range: Option<TimeRange>,
-        severity_filter: Option<Vec<&str>>,
-        _device_ids: Option<Vec<&str>>,
-        limit: i64,
-        offset: i64,
-    ) -> Result<AnomaliesResponse> {
-        let mut params = vec![
-            ("limit", limit.to_string()),
-            ("offset", offset.to_string()),
-        ];
+/// Alert severity level, ordered from most to least severe
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum AlertLevel {
+    Critical,
+    Warning,
+}
 
-        if let Some(filter) = severity_filter {
-            for severity in filter {
-                params.push(("severity", severity.to_string()));
-            }
+impl std::fmt::Display for AlertLevel {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            AlertLevel::Critical => write!(f, "critical"),
+            AlertLevel::Warning => write!(f, "warning"),
         }
-
-        self.get("/anomalies", &params).await
     }
+}
 
-    /// Get anomaly timeline
-    pub async fn get_anomaly_timeline(
-        &self,
-        days: i64,
-        group_by: &str,
-    ) -> Result<TimelineResponse> {
-        let payload = serde_json::json!({
-            "days": days,
-            "group_by": group_by
-        });
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Alert {
+    pub level: AlertLevel,
+    pub alert_type: String,
+    pub title: String,
+    pub description: String,
+}
 
-        self.post("/analytics/anomalies/timeline", &payload).await
-    }
+/// Thresholds used when generating alerts from analytics data
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AlertThresholds {
+    /// Offline percentage above which a critical connectivity alert is raised
+    pub offline_percent: f64,
+    /// P95 latency (ms) above which a critical latency alert is raised
+    pub latency_p95_critical_ms: f64,
+    /// P95 latency (ms) above which a warning latency alert is raised
+    pub latency_p95_warning_ms: f64,
+    /// Number of poor-quality devices above which a quality alert is raised
+    pub poor_quality_count: i64,
+}
 
-    // --------------------------------------------------------
-    // Pattern Recognition APIs
-    // --------------------------------------------------------
+impl Default for AlertThresholds {
+    fn default() -> Self {
+        Self {
+            offline_percent: 20.0,
+            latency_p95_critical_ms: 1000.0,
+            latency_p95_warning_ms: 500.0,
+            poor_quality_count: 5,
+        }
+    }
+}
 
-    /// Get K-means clusters
-    pub async fn get_clusters(
+impl AlertThresholds {
+    /// Evaluate thresholds against current data, returning alerts sorted by level
+    pub fn evaluate(
         &self,
-        metric_name: &str,
-        n_clusters: i32,
-        time_range: Option<TimeRange>,
-        include_outliers: bool,
-    ) -> Result<ClustersResponse> {
-        let tr = time_range.unwrap_or_else(|| TimeRange::last_days(7));
+        anomalies: &AnomaliesResponse,
+        connectivity: &ConnectivityResponse,
+        latency: &LatencyResponse,
+        quality: &QualityResponse,
+    ) -> Vec<Alert> {
+        let mut alerts = Vec::new();
 
-        let payload = serde_json::json!({
-            "metric_name": metric_name,
-            "n_clusters": n_clusters,
-            "time_range": { "start": tr.start, "end": tr.end },
-            "include_outliers": include_outliers
-        });
+        // Critical anomaly alerts
+        let critical_count = anomalies.summary.by_severity.get("critical").unwrap_or(&0);
+        if *critical_count > 0 {
+            alerts.push(Alert {
+                level: AlertLevel::Critical,
+                alert_type: "anomaly".to_string(),
+                title: format!("{} Critical Anomalies Detected", critical_count),
+                description: "Immediate investigation recommended".to_string(),
+            });
+        }
 
-        self.post("/patterns/clusters", &payload).await
+        // Offline device alerts
+        let offline_pct = connectivity.summary.offline_count as f64
+            / connectivity.summary.total_devices.max(1) as f64
+            * 100.0;
+
+        if offline_pct > self.offline_percent {
+            alerts.push(Alert {
+                level: AlertLevel::Critical,
+                alert_type: "connectivity".to_string(),
+                title: format!(
+                    "{} Devices Offline ({:.0}%)",
+                    connectivity.summary.offline_count, offline_pct
+                ),
+                description: "Network connectivity issue detected".to_string(),
+            });
+        } else if connectivity.summary.offline_count > 0 {
+            alerts.push(Alert {
+                level: AlertLevel::Warning,
+                alert_type: "connectivity".to_string(),
+                title: format!("{} Device(s) Offline", connectivity.summary.offline_count),
+                description: "Some devices are not responding".to_string(),
+            });
+        }
+
+        // Latency alerts
+        let p95 = latency.summary.overall_p95_ms;
+        if p95 > self.latency_p95_critical_ms {
+            alerts.push(Alert {
+                level: AlertLevel::Critical,
+                alert_type: "latency".to_string(),
+                title: format!("High Latency: {:.0}ms P95", p95),
+                description: "Network performance severely degraded".to_string(),
+            });
+        } else if p95 > self.latency_p95_warning_ms {
+            alerts.push(Alert {
+                level: AlertLevel::Warning,
+                alert_type: "latency".to_string(),
+                title: format!("Elevated Latency: {:.0}ms P95", p95),
+                description: "Network performance degraded".to_string(),
+            });
+        }
+
+        // Quality alerts
+        let poor = quality.summary.distribution.poor;
+        if poor > self.poor_quality_count {
+            alerts.push(Alert {
+                level: AlertLevel::Warning,
+                alert_type: "quality".to_string(),
+                title: format!("{} Devices with Poor Connection Quality", poor),
+                description: "Review device connections and network path".to_string(),
+            });
+        }
+
+        alerts.sort_by_key(|a| a.level);
+        alerts
     }
+}
 
-    // --------------------------------------------------------
-    // Insights APIs
-    // --------------------------------------------------------
+/// Generate alerts using the default thresholds
+pub fn generate_alerts(
+    anomalies: &AnomaliesResponse,
+    connectivity: &ConnectivityResponse,
+    latency: &LatencyResponse,
+    quality: &QualityResponse,
+) -> Vec<Alert> {
+    AlertThresholds::default().evaluate(anomalies, connectivity, latency, quality)
+}
 
-    /// Get AI insights
-    pub async fn get_insights(
+// ============================================================
+// Snapshot
+// ============================================================
+
+/// Time windows and parameters used by [`AnalyticsClient::snapshot`]
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SnapshotOptions {
+    /// Severities included in the anomaly fetch (empty = all)
+    pub anomaly_severities: Vec<Severity>,
+    pub anomaly_limit: i64,
+    /// Metric to cluster devices on
+    pub cluster_metric: String,
+    pub n_clusters: i32,
+    /// Time window for clustering (default last 7 days)
+    pub cluster_range: Option<TimeRange>,
+    pub insight_days: i64,
+    pub min_confidence: f64,
+    /// Window for latency and throughput statistics
+    pub stats_hours: i64,
+}
+
+impl Default for SnapshotOptions {
+    fn default() -> Self {
+        Self {
+            anomaly_severities: vec![Severity::Critical, Severity::High, Severity::Medium],
+            anomaly_limit: DEFAULT_LIMIT,
+            cluster_metric: "temperature".to_string(),
+            n_clusters: 5,
+            cluster_range: None,
+            insight_days: 7,
+            min_confidence: 0.7,
+            stats_hours: 24,
+        }
+    }
+}
+
+/// Every analytics domain fetched at a single point in time
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct AnalyticsSnapshot {
+    pub fetched_at: DateTime<Utc>,
+    pub anomalies: AnomaliesResponse,
+    pub clusters: ClustersResponse,
+    pub insights: InsightsResponse,
+    pub connectivity: ConnectivityResponse,
+    pub latency: LatencyResponse,
+    pub throughput: ThroughputResponse,
+    pub quality: QualityResponse,
+}
+
+impl AnalyticsSnapshot {
+    /// Fleet health score computed with the default weights
+    pub fn fleet_health(&self) -> FleetHealth {
+        FleetHealth::compute(
+            &self.anomalies,
+            &self.connectivity,
+            &self.insights,
+            &self.latency,
+        )
+    }
+
+    /// Alerts generated with the default thresholds
+    pub fn alerts(&self) -> Vec<Alert> {
+        generate_alerts(
+            &self.anomalies,
+            &self.connectivity,
+            &self.latency,
+            &self.quality,
+        )
+    }
+
+    /// Number of critical alerts generated with the default thresholds
+    pub fn critical_alert_count(&self) -> usize {
+        self.alerts()
+            .iter()
+            .filter(|alert| alert.level == AlertLevel::Critical)
+            .count()
+    }
+
+    /// One-glance fleet status for a status page: the fleet health grade, or
+    /// `Critical` whenever a critical alert is active, regardless of the score
+    pub fn overall_status(&self) -> HealthGrade {
+        if self.critical_alert_count() > 0 {
+            HealthGrade::Critical
+        } else {
+            self.fleet_health().grade()
+        }
+    }
+}
+
+// ============================================================
+// Analytics Client
+// ============================================================
+
+/// Mask a secret token for display
+///
+/// Always returns `"***redacted***"`: no part of the token is kept, so the
+/// output is safe to log regardless of the token's length or format.
+pub fn redact_token(_token: &str) -> String {
+    "***redacted***".to_string()
+}
+
+/// Percent-encode a value for use as a single URL path segment
+fn encode_path_segment(segment: &str) -> String {
+    let mut encoded = String::with_capacity(segment.len());
+    for byte in segment.bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// Case-insensitive substring match on a device's name or id; `query` must
+/// already be lowercase
+fn device_matches(device: &DeviceStatus, query: &str) -> bool {
+    device.device_name.to_lowercase().contains(query)
+        || device.device_id.to_lowercase().contains(query)
+}
+
+/// Base URL of the default hosted deployment
+const DEFAULT_BASE_URL: &str = "https://admin.tesaiot.com/api/v1/bdh-ai";
+
+/// Base URL for a `TESAIOT_REGION` name. Only `us` has a confirmed
+/// endpoint; other deployments set `TESAIOT_API_URL` instead.
+fn region_base_url(region: &str) -> Result<&'static str> {
+    match region.trim().to_ascii_lowercase().as_str() {
+        "us" => Ok(DEFAULT_BASE_URL),
+        _ => Err(AnalyticsError::Config(format!(
+            "Unknown TESAIOT_REGION '{}': expected us (set TESAIOT_API_URL for other deployments)",
+            region
+        ))),
+    }
+}
+
+/// Read an environment variable, treating an empty value as unset
+fn env_var(name: &str) -> Option<String> {
+    std::env::var(name).ok().filter(|value| !value.is_empty())
+}
+
+/// Decode a JSON response body, reporting an empty one (e.g. 204 No Content)
+/// as `EmptyResponse` instead of a confusing EOF error
+async fn read_json<R: for<'de> Deserialize<'de>>(
+    response: reqwest::Response,
+    started: Instant,
+) -> Result<R> {
+    let status = response.status().as_u16();
+    let body = response
+        .bytes()
+        .await
+        .map_err(|e| map_reqwest_error(e, started))?;
+    if status == 204 || body.iter().all(u8::is_ascii_whitespace) {
+        return Err(AnalyticsError::EmptyResponse { status });
+    }
+    Ok(serde_json::from_slice(&body)?)
+}
+
+/// Treat an empty response as "nothing found" for list-style endpoints
+fn default_if_empty<R: Default>(result: Result<R>) -> Result<R> {
+    match result {
+        Err(AnalyticsError::EmptyResponse { .. }) => Ok(R::default()),
+        other => other,
+    }
+}
+
+/// Cap an anomaly page size at [`MAX_LIMIT`]
+fn clamp_limit(limit: i64) -> i64 {
+    if limit > MAX_LIMIT {
+        #[cfg(feature = "tracing")]
+        tracing::warn!(
+            limit,
+            max = MAX_LIMIT,
+            "anomaly limit above MAX_LIMIT, clamping"
+        );
+        MAX_LIMIT
+    } else {
+        limit
+    }
+}
+
+/// Add one `device_id` query parameter per id
+fn push_device_ids(params: &mut Vec<(&str, String)>, device_ids: Option<Vec<&str>>) {
+    for id in device_ids.into_iter().flatten() {
+        params.push(("device_id", id.to_string()));
+    }
+}
+
+/// Groupings accepted by the anomaly timeline endpoint
+const TIMELINE_GROUP_BY: [&str; 3] = ["day", "hour", "severity"];
+
+/// Groupings accepted by the insights timeline endpoint
+const INSIGHT_TIMELINE_GROUP_BY: [&str; 4] = ["day", "hour", "type", "severity"];
+
+/// Default anomaly page size, used by [`SnapshotOptions`] and
+/// [`AnalyticsClient::poll_anomalies`]
+pub const DEFAULT_LIMIT: i64 = 100;
+
+/// Largest `limit` sent by [`AnalyticsClient::get_anomalies`]; larger values
+/// are clamped, since huge pages can time out server-side
+pub const MAX_LIMIT: i64 = 1000;
+
+/// Default for [`AnalyticsClientBuilder::max_concurrency`]
+pub const DEFAULT_MAX_CONCURRENCY: usize = 8;
+
+/// Number of anomaly ids remembered by [`AnalyticsClient::poll_anomalies`]
+#[cfg(not(target_arch = "wasm32"))]
+pub const POLL_SEEN_CAPACITY: usize = 10_000;
+
+#[cfg(not(target_arch = "wasm32"))]
+struct PollState<'a> {
+    client: &'a AnalyticsClient,
+    ticker: tokio::time::Interval,
+    seen: SeenIds,
+    pending: std::collections::VecDeque<Anomaly>,
+}
+
+/// Set of recently seen ids that forgets the oldest once full
+#[cfg(not(target_arch = "wasm32"))]
+struct SeenIds {
+    ids: HashSet<String>,
+    order: std::collections::VecDeque<String>,
+    capacity: usize,
+}
+
+#[cfg(not(target_arch = "wasm32"))]
+impl SeenIds {
+    fn new(capacity: usize) -> Self {
+        Self {
+            ids: HashSet::new(),
+            order: std::collections::VecDeque::new(),
+            capacity,
+        }
+    }
+
+    /// Record an id, returning `true` if it wasn't already present
+    fn insert(&mut self, id: &str) -> bool {
+        if self.ids.contains(id) {
+            return false;
+        }
+        if self.order.len() >= self.capacity {
+            if let Some(oldest) = self.order.pop_front() {
+                self.ids.remove(&oldest);
+            }
+        }
+        self.ids.insert(id.to_string());
+        self.order.push_back(id.to_string());
+        true
+    }
+}
+
+/// Map a reqwest error to `Timeout` when the request ran out of time
+fn map_reqwest_error(err: reqwest::Error, started: Instant) -> AnalyticsError {
+    if err.is_timeout() {
+        AnalyticsError::Timeout {
+            elapsed: started.elapsed(),
+        }
+    } else {
+        AnalyticsError::Http(err)
+    }
+}
+
+/// How the API token is sent with each request
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `X-API-KEY: <token>` header
+    #[default]
+    ApiKey,
+    /// `Authorization: Bearer <token>` header
+    Bearer,
+}
+
+impl AuthScheme {
+    /// Parse "apikey" (or "api_key", "api-key") or "bearer", ignoring case
+    pub fn parse(name: &str) -> Result<Self> {
+        match name.trim().to_ascii_lowercase().as_str() {
+            "apikey" | "api_key" | "api-key" => Ok(AuthScheme::ApiKey),
+            "bearer" => Ok(AuthScheme::Bearer),
+            _ => Err(AnalyticsError::Config(format!(
+                "Unknown auth scheme '{}': expected \"apikey\" or \"bearer\"",
+                name
+            ))),
+        }
+    }
+}
+
+/// Boxed, sendable future returned by a [`TokenProvider`]
+pub type BoxFuture<'a, T> = std::pin::Pin<Box<dyn std::future::Future<Output = T> + Send + 'a>>;
+
+/// Async callback that fetches a fresh API token after a 401 response
+pub type TokenProvider =
+    std::sync::Arc<dyn Fn() -> BoxFuture<'static, Result<String>> + Send + Sync>;
+
+/// Callback invoked before each request with the HTTP method and API path
+pub type RequestHook = std::sync::Arc<dyn Fn(&str, &str) + Send + Sync>;
+
+/// Callback invoked after each request with the HTTP method, API path, response
+/// status (`None` if no response was received), and elapsed time
+pub type ResponseHook =
+    std::sync::Arc<dyn Fn(&str, &str, Option<u16>, std::time::Duration) + Send + Sync>;
+
+/// Builder for [`AnalyticsClient`] with optional settings
+pub struct AnalyticsClientBuilder {
+    base_url: String,
+    api_token: String,
+    timeout: std::time::Duration,
+    auth_scheme: AuthScheme,
+    token_provider: Option<TokenProvider>,
+    on_request: Option<RequestHook>,
+    on_response: Option<ResponseHook>,
+    circuit_breaker: Option<CircuitBreakerConfig>,
+    cache: Option<CacheConfig>,
+    max_concurrency: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    retry: Option<RetryConfig>,
+    #[cfg(not(target_arch = "wasm32"))]
+    overall_deadline: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_max_idle_per_host: usize,
+    #[cfg(not(target_arch = "wasm32"))]
+    pool_idle_timeout: Option<std::time::Duration>,
+    #[cfg(not(target_arch = "wasm32"))]
+    http2_prior_knowledge: bool,
+}
+
+impl AnalyticsClientBuilder {
+    /// Start a builder with the required base URL and API token.
+    ///
+    /// A single trailing `/` on the base URL is dropped so paths join cleanly.
+    pub fn new(base_url: &str, api_token: &str) -> Self {
+        Self {
+            base_url: base_url.strip_suffix('/').unwrap_or(base_url).to_string(),
+            api_token: api_token.to_string(),
+            timeout: std::time::Duration::from_secs(30),
+            auth_scheme: AuthScheme::default(),
+            token_provider: None,
+            on_request: None,
+            on_response: None,
+            circuit_breaker: None,
+            cache: None,
+            max_concurrency: DEFAULT_MAX_CONCURRENCY,
+            #[cfg(not(target_arch = "wasm32"))]
+            retry: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            overall_deadline: None,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool_max_idle_per_host: DEFAULT_MAX_CONCURRENCY,
+            #[cfg(not(target_arch = "wasm32"))]
+            pool_idle_timeout: Some(std::time::Duration::from_secs(90)),
+            #[cfg(not(target_arch = "wasm32"))]
+            http2_prior_knowledge: false,
+        }
+    }
+
+    /// Set how the API token is sent (default `X-API-KEY`)
+    pub fn auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    /// Register a callback that refreshes the token when a request returns 401.
+    ///
+    /// The client stores the new token and retries the failed request once.
+    pub fn token_provider(mut self, provider: TokenProvider) -> Self {
+        self.token_provider = Some(provider);
+        self
+    }
+
+    /// Call `hook` before every request is sent
+    pub fn on_request(mut self, hook: impl Fn(&str, &str) + Send + Sync + 'static) -> Self {
+        self.on_request = Some(std::sync::Arc::new(hook));
+        self
+    }
+
+    /// Call `hook` after every request completes or fails
+    pub fn on_response(
+        mut self,
+        hook: impl Fn(&str, &str, Option<u16>, std::time::Duration) + Send + Sync + 'static,
+    ) -> Self {
+        self.on_response = Some(std::sync::Arc::new(hook));
+        self
+    }
+
+    /// Fail fast with `CircuitOpen` after repeated failures (default off).
+    ///
+    /// Transport errors, timeouts, and 5xx responses count as failures.
+    pub fn circuit_breaker(mut self, config: CircuitBreakerConfig) -> Self {
+        self.circuit_breaker = Some(config);
+        self
+    }
+
+    /// Cache typed responses in memory (default off).
+    ///
+    /// Entries are keyed by method and arguments and expire after the
+    /// method's TTL. `submit_insight_feedback` and `post_raw` are never cached
+    /// and clear the whole cache, since they may change what reads return.
+    pub fn cache(mut self, config: CacheConfig) -> Self {
+        self.cache = Some(config);
+        self
+    }
+
+    /// Retry transport errors, timeouts, 429 and 5xx responses with
+    /// exponential backoff (default off)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn retry(mut self, config: RetryConfig) -> Self {
+        self.retry = Some(config);
+        self
+    }
+
+    /// Cap the total time a call may take across all attempts and backoff
+    /// delays (default none).
+    ///
+    /// Once the deadline passes the call fails with `Timeout`, even if retries
+    /// remain. Without retries it bounds the single attempt.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn overall_deadline(mut self, deadline: std::time::Duration) -> Self {
+        self.overall_deadline = Some(deadline);
+        self
+    }
+
+    /// Keep up to `max` idle connections per host for reuse (default 8, matching
+    /// the default `max_concurrency` so a `snapshot` can reuse every connection
+    /// the previous one opened)
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_max_idle_per_host(mut self, max: usize) -> Self {
+        self.pool_max_idle_per_host = max;
+        self
+    }
+
+    /// Close idle pooled connections after `timeout` (default 90 seconds);
+    /// `None` keeps them open indefinitely
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn pool_idle_timeout(mut self, timeout: Option<std::time::Duration>) -> Self {
+        self.pool_idle_timeout = timeout;
+        self
+    }
+
+    /// Speak HTTP/2 without negotiating it first (default off).
+    ///
+    /// Only enable this when the server is known to support HTTP/2: all of a
+    /// `snapshot`'s requests are then multiplexed over a single connection.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn http2_prior_knowledge(mut self, enabled: bool) -> Self {
+        self.http2_prior_knowledge = enabled;
+        self
+    }
+
+    /// Cap how many requests fan-out helpers such as `snapshot` keep in
+    /// flight at once (default 8).
+    ///
+    /// Lower values go easier on the gateway but make `snapshot` slower, since
+    /// its seven requests run in waves instead of all together. Single-call
+    /// methods are never limited.
+    pub fn max_concurrency(mut self, max_concurrency: usize) -> Self {
+        self.max_concurrency = max_concurrency;
+        self
+    }
+
+    /// Set the request timeout (default 30 seconds)
+    pub fn timeout(mut self, timeout: std::time::Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Build the client using the TLS backend selected by cargo features
+    pub fn build(self) -> Result<AnalyticsClient> {
+        if self.api_token.is_empty() {
+            return Err(AnalyticsError::Config(
+                "API token is required".to_string(),
+            ));
+        }
+        if self.max_concurrency == 0 {
+            return Err(AnalyticsError::Config(
+                "max_concurrency must be at least 1".to_string(),
+            ));
+        }
+
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = Client::builder()
+            .timeout(self.timeout)
+            .pool_max_idle_per_host(self.pool_max_idle_per_host)
+            .pool_idle_timeout(self.pool_idle_timeout)
+            .tcp_keepalive(std::time::Duration::from_secs(60));
+        #[cfg(not(target_arch = "wasm32"))]
+        let builder = if self.http2_prior_knowledge {
+            builder.http2_prior_knowledge()
+        } else {
+            builder
+        };
+        // The fetch backend has no client timeout, pool, TLS, or compression settings
+        #[cfg(target_arch = "wasm32")]
+        let builder = Client::builder();
+        #[cfg(all(feature = "rustls-tls", not(target_arch = "wasm32")))]
+        let builder = builder.use_rustls_tls();
+        #[cfg(all(feature = "native-tls", not(target_arch = "wasm32")))]
+        let builder = builder.use_native_tls();
+        // Sends `Accept-Encoding` and decodes compressed bodies
+        #[cfg(all(feature = "compression", not(target_arch = "wasm32")))]
+        let builder = builder.gzip(true).deflate(true).brotli(true);
+        let client = builder.build()?;
+
+        Ok(AnalyticsClient {
+            client,
+            base_url: self.base_url,
+            api_token: std::sync::RwLock::new(self.api_token),
+            auth_scheme: self.auth_scheme,
+            token_provider: self.token_provider,
+            on_request: self.on_request,
+            on_response: self.on_response,
+            circuit_breaker: self
+                .circuit_breaker
+                .map(circuit_breaker::CircuitBreaker::new),
+            cache: self.cache.map(cache::ResponseCache::new),
+            fan_out: tokio::sync::Semaphore::new(self.max_concurrency),
+            #[cfg(not(target_arch = "wasm32"))]
+            retry: self.retry,
+            #[cfg(not(target_arch = "wasm32"))]
+            overall_deadline: self.overall_deadline,
+        })
+    }
+}
+
+pub struct AnalyticsClient {
+    client: Client,
+    base_url: String,
+    api_token: std::sync::RwLock<String>,
+    auth_scheme: AuthScheme,
+    token_provider: Option<TokenProvider>,
+    on_request: Option<RequestHook>,
+    on_response: Option<ResponseHook>,
+    circuit_breaker: Option<circuit_breaker::CircuitBreaker>,
+    cache: Option<cache::ResponseCache>,
+    fan_out: tokio::sync::Semaphore,
+    #[cfg(not(target_arch = "wasm32"))]
+    retry: Option<RetryConfig>,
+    #[cfg(not(target_arch = "wasm32"))]
+    overall_deadline: Option<std::time::Duration>,
+}
+
+impl std::fmt::Debug for AnalyticsClient {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut debug = f.debug_struct("AnalyticsClient");
+        debug
+            .field("base_url", &self.base_url)
+            .field("api_token", &redact_token(&self.current_token()))
+            .field("auth_scheme", &self.auth_scheme)
+            .field("token_provider", &self.token_provider.is_some())
+            .field("on_request", &self.on_request.is_some())
+            .field("on_response", &self.on_response.is_some())
+            .field("circuit_breaker", &self.circuit_breaker)
+            .field("cache", &self.cache)
+            .field("fan_out", &self.fan_out);
+        #[cfg(not(target_arch = "wasm32"))]
+        debug
+            .field("retry", &self.retry)
+            .field("overall_deadline", &self.overall_deadline);
+        debug.finish()
+    }
+}
+
+impl AnalyticsClient {
+    /// Create a new Analytics client
+    pub fn new(base_url: &str, api_token: &str) -> Result<Self> {
+        Self::builder(base_url, api_token).build()
+    }
+
+    /// Create a builder for customizing the client
+    pub fn builder(base_url: &str, api_token: &str) -> AnalyticsClientBuilder {
+        AnalyticsClientBuilder::new(base_url, api_token)
+    }
+
+    /// Create client from environment variables.
+    ///
+    /// - `TESAIOT_API_TOKEN` (required)
+    /// - `TESAIOT_API_URL`: base URL; takes precedence over `TESAIOT_REGION`
+    /// - `TESAIOT_REGION`: `us`, used when no URL is set
+    /// - `TESAIOT_AUTH_SCHEME`: `apikey` (default) or `bearer`
+    /// - `TESAIOT_TIMEOUT_SECS`: request timeout in seconds (default 30)
+    pub fn from_env() -> Result<Self> {
+        let api_token = std::env::var("TESAIOT_API_TOKEN")
+            .map_err(|_| AnalyticsError::Config("TESAIOT_API_TOKEN not set".to_string()))?;
+        let base_url = match (env_var("TESAIOT_API_URL"), env_var("TESAIOT_REGION")) {
+            (Some(url), _) => url,
+            (None, Some(region)) => region_base_url(&region)?.to_string(),
+            (None, None) => DEFAULT_BASE_URL.to_string(),
+        };
+
+        let mut builder = Self::builder(&base_url, &api_token);
+        if let Some(scheme) = env_var("TESAIOT_AUTH_SCHEME") {
+            builder = builder.auth_scheme(AuthScheme::parse(&scheme)?);
+        }
+        if let Some(secs) = env_var("TESAIOT_TIMEOUT_SECS") {
+            let secs: u64 = secs.trim().parse().map_err(|_| {
+                AnalyticsError::Config(format!(
+                    "TESAIOT_TIMEOUT_SECS must be a whole number of seconds, got '{}'",
+                    secs
+                ))
+            })?;
+            builder = builder.timeout(std::time::Duration::from_secs(secs));
+        }
+        builder.build()
+    }
+
+    /// Make a POST request
+    async fn post<T: Serialize, R: for<'de> Deserialize<'de>>(
         &self,
-        days: i64,
-        insight_types: Option<Vec<&str>>,
-        min_confidence: f64,
-    ) -> Result<InsightsResponse> {
-        let mut payload = serde_json::json!({
-            "analysis_period_days": days,
-            "min_confidence": min_confidence
-        });
+        path: &str,
+        body: &T,
+    ) -> Result<R> {
+        self.post_with_timeout(path, body, None).await
+    }
 
-        if let Some(types) = insight_types {
-            payload["insight_types"] = serde_json::json!(types);
+    /// Make a POST request, overriding the client timeout when `timeout` is set
+    async fn post_with_timeout<T: Serialize, R: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        body: &T,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<R> {
+        let started = Instant::now();
+        let response = self.send_post(path, body, timeout).await?;
+        read_json(response, started).await
+    }
+
+    /// Make a POST request whose response body is ignored (e.g. 204 No Content)
+    async fn post_no_content<T: Serialize>(&self, path: &str, body: &T) -> Result<()> {
+        self.send_post(path, body, None).await?;
+        Ok(())
+    }
+
+    /// Send a POST request and check the response status
+    async fn send_post<T: Serialize>(
+        &self,
+        path: &str,
+        body: &T,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<reqwest::Response> {
+        let url = self.url(path);
+        #[cfg_attr(target_arch = "wasm32", allow(unused_mut))]
+        let mut request = self
+            .client
+            .post(&url)
+            .header("Content-Type", "application/json")
+            .json(body);
+
+        // Per-request timeouts aren't supported by the fetch backend
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(timeout) = timeout {
+            request = request.timeout(timeout);
         }
+        #[cfg(target_arch = "wasm32")]
+        let _ = timeout;
 
-        self.post("/insights", &payload).await
+        self.execute("POST", path, request).await
     }
 
-    // --------------------------------------------------------
-    // Connectivity APIs
-    // --------------------------------------------------------
+    /// Make a GET request
+    async fn get<R: for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<R> {
+        let started = Instant::now();
+        let request = self.get_request(path, params);
+        let response = self.execute("GET", path, request).await?;
+        read_json(response, started).await
+    }
 
-    /// Get connectivity status
-    pub async fn get_connectivity_status(
+    /// Make a GET request, reading an empty response as `R::default()`
+    async fn get_or_default<R: Default + for<'de> Deserialize<'de>>(
         &self,
-        status_filter: Option<&str>,
-    ) -> Result<ConnectivityResponse> {
-        let params = vec![("status", status_filter.unwrap_or("").to_string())];
-        self.get("/connectivity/status", &params).await
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<R> {
+        default_if_empty(self.get(path, params).await)
     }
 
-    /// Get latency statistics
-    pub async fn get_latency_stats(&self, hours: i64) -> Result<LatencyResponse> {
-        let params = vec![("hours", hours.to_string())];
-        self.get("/connectivity/latency", &params).await
+    /// Make a POST request, reading an empty response as `R::default()`
+    async fn post_or_default<T: Serialize, R: Default + for<'de> Deserialize<'de>>(
+        &self,
+        path: &str,
+        body: &T,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<R> {
+        default_if_empty(self.post_with_timeout(path, body, timeout).await)
     }
 
-    /// Get throughput statistics
-    pub async fn get_throughput_stats(&self, hours: i64) -> Result<ThroughputResponse> {
-        let params = vec![("hours", hours.to_string())];
-        self.get("/connectivity/throughput", &params).await
+    /// Build a GET request, leaving out params with empty values
+    fn get_request(&self, path: &str, params: &[(&str, String)]) -> reqwest::RequestBuilder {
+        let mut request = self.client.get(self.url(path));
+
+        for (key, value) in params {
+            if !value.is_empty() {
+                request = request.query(&[(key, value)]);
+            }
+        }
+        request
     }
 
-    /// Get connection quality
-    pub async fn get_connection_quality(&self) -> Result<QualityResponse> {
-        self.get("/connectivity/quality", &[]).await
+    /// Serve `fetch` from the response cache when caching is on for `method`.
+    ///
+    /// `args` identifies the call; it is formatted before any defaults (such
+    /// as a time range ending now) are applied, so repeated calls share a key.
+    async fn cached<R: Serialize + for<'de> Deserialize<'de>>(
+        &self,
+        method: &str,
+        args: String,
+        fetch: impl std::future::Future<Output = Result<R>>,
+    ) -> Result<R> {
+        let Some(cache) = self.cache.as_ref().filter(|c| c.enabled_for(method)) else {
+            return fetch.await;
+        };
+
+        let key = format!("{} {}", method, args);
+        if let Some(value) = cache.get(&key) {
+            return Ok(serde_json::from_value(value)?);
+        }
+        let response = fetch.await?;
+        cache.insert(method, key, serde_json::to_value(&response)?);
+        Ok(response)
+    }
+
+    /// Drop every cached response after a call that may have changed them
+    fn invalidate_cache(&self) {
+        if let Some(cache) = &self.cache {
+            cache.clear();
+        }
+    }
+
+    /// Join the base URL and an API path, adding the leading `/` if missing
+    fn url(&self, path: &str) -> String {
+        if path.starts_with('/') {
+            format!("{}{}", self.base_url, path)
+        } else {
+            format!("{}/{}", self.base_url, path)
+        }
+    }
+
+    /// Current API token (may have been replaced by the token provider)
+    fn current_token(&self) -> String {
+        self.api_token
+            .read()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+            .clone()
+    }
+
+    /// Attach the API token using the configured auth scheme
+    fn authorize(&self, request: reqwest::RequestBuilder) -> reqwest::RequestBuilder {
+        let token = self.current_token();
+        match self.auth_scheme {
+            AuthScheme::ApiKey => request.header("X-API-KEY", token),
+            AuthScheme::Bearer => request.bearer_auth(token),
+        }
+    }
+
+    /// Fetch a new token from the provider and store it for later requests
+    async fn refresh_token(&self, provider: &TokenProvider) -> Result<()> {
+        let token = provider().await?;
+        *self
+            .api_token
+            .write()
+            .unwrap_or_else(|poisoned| poisoned.into_inner()) = token;
+        Ok(())
+    }
+
+    /// Send a request through the retry policy, circuit breaker and hooks,
+    /// mapping non-2xx responses to errors
+    #[cfg_attr(
+        feature = "tracing",
+        tracing::instrument(
+            name = "analytics_request",
+            skip(self, request),
+            fields(status = tracing::field::Empty, elapsed_ms = tracing::field::Empty)
+        )
+    )]
+    async fn execute(
+        &self,
+        method: &str,
+        path: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        #[cfg(not(target_arch = "wasm32"))]
+        if let Some(deadline) = self.overall_deadline {
+            let started = Instant::now();
+            let attempts = self.execute_with_retries(method, path, request);
+            return tokio::time::timeout(deadline, attempts)
+                .await
+                .unwrap_or_else(|_| {
+                    Err(AnalyticsError::Timeout {
+                        elapsed: started.elapsed(),
+                    })
+                });
+        }
+        #[cfg(not(target_arch = "wasm32"))]
+        return self.execute_with_retries(method, path, request).await;
+        #[cfg(target_arch = "wasm32")]
+        self.execute_guarded(method, path, request).await
+    }
+
+    /// Retry transient failures with backoff when a retry policy is set
+    #[cfg(not(target_arch = "wasm32"))]
+    async fn execute_with_retries(
+        &self,
+        method: &str,
+        path: &str,
+        mut request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let Some(config) = &self.retry else {
+            return self.execute_guarded(method, path, request).await;
+        };
+        let mut retries = 0;
+
+        loop {
+            // Requests whose body can't be cloned are sent once
+            let next = if retries < config.max_retries {
+                request.try_clone()
+            } else {
+                None
+            };
+
+            let err = match self.execute_guarded(method, path, request).await {
+                Ok(response) => return Ok(response),
+                Err(err) => err,
+            };
+            let Some(next) = next else {
+                return Err(err);
+            };
+            if !retry::is_retryable(&err) {
+                return Err(err);
+            }
+
+            let delay = config.delay(retries);
+            #[cfg(feature = "tracing")]
+            tracing::warn!(error = %err, retry = retries + 1, ?delay, "retrying request");
+
+            tokio::time::sleep(delay).await;
+            retries += 1;
+            request = next;
+        }
+    }
+
+    /// Send a single attempt through the circuit breaker
+    async fn execute_guarded(
+        &self,
+        method: &str,
+        path: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        let Some(breaker) = &self.circuit_breaker else {
+            return self.execute_unguarded(method, path, request).await;
+        };
+
+        breaker
+            .acquire()
+            .map_err(|retry_after| AnalyticsError::CircuitOpen { retry_after })?;
+
+        let result = self.execute_unguarded(method, path, request).await;
+        match &result {
+            Err(AnalyticsError::Http(_)) | Err(AnalyticsError::Timeout { .. }) => {
+                breaker.record_failure()
+            }
+            Err(AnalyticsError::Api { status, .. }) if *status >= 500 => breaker.record_failure(),
+            _ => breaker.record_success(),
+        }
+        result
+    }
+
+    async fn execute_unguarded(
+        &self,
+        method: &str,
+        path: &str,
+        request: reqwest::RequestBuilder,
+    ) -> Result<reqwest::Response> {
+        if let Some(hook) = &self.on_request {
+            hook(method, path);
+        }
+
+        let started = Instant::now();
+        let result = self.send_authorized(request, started).await;
+
+        if let Some(hook) = &self.on_response {
+            let status = result.as_ref().ok().map(|r| r.status().as_u16());
+            hook(method, path, status, started.elapsed());
+        }
+
+        let response = result?;
+
+        #[cfg(feature = "tracing")]
+        {
+            let span = tracing::Span::current();
+            span.record("status", response.status().as_u16());
+            span.record("elapsed_ms", started.elapsed().as_millis() as u64);
+            if !response.status().is_success() {
+                tracing::warn!(status = response.status().as_u16(), "request failed");
+            }
+        }
+
+        if !response.status().is_success() {
+            let status = response.status().as_u16();
+            let message = response.text().await.unwrap_or_default();
+            return Err(match status {
+                401 => AnalyticsError::Unauthorized { message },
+                403 => AnalyticsError::Forbidden { message },
+                _ => AnalyticsError::Api { status, message },
+            });
+        }
+
+        Ok(response)
+    }
+
+    /// Authorize and send a request.
+    ///
+    /// On 401, a configured token provider is asked for a new token and the
+    /// request is retried once.
+    async fn send_authorized(
+        &self,
+        request: reqwest::RequestBuilder,
+        started: Instant,
+    ) -> Result<reqwest::Response> {
+        let retry = match self.token_provider {
+            Some(_) => request.try_clone(),
+            None => None,
+        };
+        let response = self
+            .authorize(request)
+            .send()
+            .await
+            .map_err(|e| map_reqwest_error(e, started))?;
+
+        if response.status() == reqwest::StatusCode::UNAUTHORIZED {
+            if let (Some(provider), Some(retry)) = (&self.token_provider, retry) {
+                self.refresh_token(provider).await?;
+                return self
+                    .authorize(retry)
+                    .send()
+                    .await
+                    .map_err(|e| map_reqwest_error(e, started));
+            }
+        }
+
+        Ok(response)
+    }
+
+    // --------------------------------------------------------
+    // Anomaly Detection APIs
+    // --------------------------------------------------------
+
+    /// Get aggregated anomalies
+    /// Note: API uses GET method with query parameters
+    /// See [`AnomalyQuery`] for the filters; its `limit` is capped at
+    /// [`MAX_LIMIT`], so page with `offset` for more.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_anomalies(&self, query: AnomalyQuery) -> Result<AnomaliesResponse> {
+        let params = query.params(clamp_limit(query.limit));
+        let args = format!("{:?}", params);
+        self.cached(
+            "get_anomalies",
+            args,
+            self.get_or_default("/anomalies", &params),
+        )
+        .await
+    }
+
+    /// Stream anomalies one at a time instead of buffering the whole page.
+    ///
+    /// Takes the same query as [`get_anomalies`](Self::get_anomalies). Each
+    /// anomaly is parsed as soon as its bytes arrive, so memory stays bounded
+    /// by a single record even with a large `limit`, which is not capped
+    /// here. The `summary` object is skipped; use `get_anomalies` when you
+    /// need it.
+    ///
+    /// Request errors are returned before the stream starts. A transport or
+    /// parse error mid-body is yielded as an `Err` and ends the stream.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn stream_anomalies_raw(
+        &self,
+        query: AnomalyQuery,
+    ) -> Result<impl futures_util::Stream<Item = Result<Anomaly>>> {
+        let params = query.params(query.limit);
+        let started = Instant::now();
+        let request = self.get_request("/anomalies", &params);
+        let response = self.execute("GET", "/anomalies", request).await?;
+        Ok(stream::array_field(
+            response.bytes_stream(),
+            "anomalies",
+            started,
+        ))
+    }
+
+    /// Get anomalies for a single metric (e.g. "temperature")
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_metric_anomalies(
+        &self,
+        metric: &str,
+        limit: i64,
+        offset: i64,
+    ) -> Result<AnomaliesResponse> {
+        self.get_anomalies(
+            AnomalyQuery::new()
+                .metrics([metric])
+                .limit(limit)
+                .offset(offset),
+        )
+        .await
+    }
+
+    /// Get a single anomaly by id. A missing id surfaces as `Api { status: 404 }`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_anomaly(&self, id: &str) -> Result<Anomaly> {
+        let path = format!("/anomalies/{}", encode_path_segment(id));
+        self.cached("get_anomaly", format!("{:?}", id), self.get(&path, &[]))
+            .await
+    }
+
+    /// Get a single anomaly by id, or `None` if it doesn't exist
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_anomaly_opt(&self, id: &str) -> Result<Option<Anomaly>> {
+        match self.get_anomaly(id).await {
+            Ok(anomaly) => Ok(Some(anomaly)),
+            Err(AnalyticsError::Api { status: 404, .. }) => Ok(None),
+            Err(e) => Err(e),
+        }
+    }
+
+    /// Get anomaly timeline
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_anomaly_timeline(
+        &self,
+        days: i64,
+        group_by: &str,
+    ) -> Result<TimelineResponse> {
+        self.anomaly_timeline(days, group_by, None).await
+    }
+
+    async fn anomaly_timeline(
+        &self,
+        days: i64,
+        group_by: &str,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<TimelineResponse> {
+        let payload = serde_json::json!({
+            "days": days,
+            "group_by": group_by
+        });
+
+        self.cached(
+            "get_anomaly_timeline",
+            payload.to_string(),
+            self.post_or_default("/analytics/anomalies/timeline", &payload, timeout),
+        )
+        .await
+    }
+
+    /// Get anomaly timeline for a specific window instead of the last N days
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_anomaly_timeline_range(
+        &self,
+        range: TimeRange,
+        group_by: &str,
+    ) -> Result<TimelineResponse> {
+        if !TIMELINE_GROUP_BY.contains(&group_by) {
+            return Err(AnalyticsError::Config(format!(
+                "group_by must be one of {:?}, got {:?}",
+                TIMELINE_GROUP_BY, group_by
+            )));
+        }
+        range.validate()?;
+
+        let payload = serde_json::json!({
+            "time_range": { "start": range.start, "end": range.end },
+            "group_by": group_by
+        });
+
+        self.cached(
+            "get_anomaly_timeline",
+            payload.to_string(),
+            self.post_or_default("/analytics/anomalies/timeline", &payload, None),
+        )
+        .await
+    }
+
+    // --------------------------------------------------------
+    // Pattern Recognition APIs
+    // --------------------------------------------------------
+
+    /// Get K-means clusters
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_clusters(
+        &self,
+        metric_name: &str,
+        n_clusters: i32,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.get_clusters_with(
+            metric_name,
+            ClusterAlgorithm::KMeans { n_clusters },
+            time_range,
+            include_outliers,
+        )
+        .await
+    }
+
+    /// Get clusters using the given algorithm
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_clusters_with(
+        &self,
+        metric_name: &str,
+        algorithm: ClusterAlgorithm,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.clusters(metric_name, algorithm, time_range, include_outliers, None)
+            .await
+    }
+
+    async fn clusters(
+        &self,
+        metric_name: &str,
+        algorithm: ClusterAlgorithm,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<ClustersResponse> {
+        if metric_name.trim().is_empty() {
+            return Err(AnalyticsError::Config(
+                "metric_name must not be empty".to_string(),
+            ));
+        }
+        algorithm.validate()?;
+        let args = format!(
+            "{:?}",
+            (metric_name, &algorithm, &time_range, include_outliers)
+        );
+        let tr = time_range.unwrap_or_else(|| TimeRange::last_days(7));
+        tr.validate()?;
+
+        let mut payload = serde_json::json!({
+            "metric_name": metric_name,
+            "time_range": { "start": tr.start, "end": tr.end },
+            "include_outliers": include_outliers
+        });
+
+        if let serde_json::Value::Object(params) = serde_json::to_value(&algorithm)? {
+            for (key, value) in params {
+                payload[key] = value;
+            }
+        }
+
+        self.cached(
+            "get_clusters",
+            args,
+            self.post_or_default("/patterns/clusters", &payload, timeout),
+        )
+        .await
+    }
+
+    /// Get the cluster a single device belongs to, or `None` if it isn't clustered
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_device_cluster(
+        &self,
+        metric_name: &str,
+        device_id: &str,
+    ) -> Result<Option<Cluster>> {
+        let path = format!(
+            "/patterns/devices/{}/cluster",
+            encode_path_segment(device_id)
+        );
+        let params = vec![("metric_name", metric_name.to_string())];
+        let fetch = async {
+            match self
+                .get_or_default::<DeviceClusterResponse>(&path, &params)
+                .await
+            {
+                Ok(response) => Ok(response.cluster),
+                Err(AnalyticsError::Api { status: 404, .. }) => Ok(None),
+                Err(e) => Err(e),
+            }
+        };
+
+        self.cached(
+            "get_device_cluster",
+            format!("{:?}", (metric_name, device_id)),
+            fetch,
+        )
+        .await
+    }
+
+    /// Get outlier devices without running clustering
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_outliers(
+        &self,
+        metric_name: &str,
+        time_range: Option<TimeRange>,
+        threshold: f64,
+    ) -> Result<Vec<Outlier>> {
+        let args = format!("{:?}", (metric_name, &time_range, threshold));
+        let tr = time_range.unwrap_or_else(|| TimeRange::last_days(7));
+        tr.validate()?;
+
+        let payload = serde_json::json!({
+            "metric_name": metric_name,
+            "time_range": { "start": tr.start, "end": tr.end },
+            "threshold": threshold
+        });
+
+        let fetch = async {
+            let response: OutliersResponse = self
+                .post_or_default("/patterns/outliers", &payload, None)
+                .await?;
+            Ok(response.outliers)
+        };
+        self.cached("get_outliers", args, fetch).await
+    }
+
+    // --------------------------------------------------------
+    // Insights APIs
+    // --------------------------------------------------------
+
+    /// Get AI insights
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_insights(
+        &self,
+        days: i64,
+        insight_types: Option<Vec<&str>>,
+        min_confidence: f64,
+    ) -> Result<InsightsResponse> {
+        self.insights(days, insight_types, min_confidence, None)
+            .await
+    }
+
+    async fn insights(
+        &self,
+        days: i64,
+        insight_types: Option<Vec<&str>>,
+        min_confidence: f64,
+        timeout: Option<std::time::Duration>,
+    ) -> Result<InsightsResponse> {
+        if days <= 0 {
+            return Err(AnalyticsError::Config(format!(
+                "days must be positive, got {}",
+                days
+            )));
+        }
+        if !(0.0..=1.0).contains(&min_confidence) {
+            return Err(AnalyticsError::Config(format!(
+                "min_confidence must be within 0.0..=1.0, got {}",
+                min_confidence
+            )));
+        }
+
+        let mut payload = serde_json::json!({
+            "analysis_period_days": days,
+            "min_confidence": min_confidence
+        });
+
+        if let Some(types) = insight_types {
+            payload["insight_types"] = serde_json::json!(types);
+        }
+
+        self.cached(
+            "get_insights",
+            payload.to_string(),
+            self.post_or_default("/insights", &payload, timeout),
+        )
+        .await
+    }
+
+    /// Get insight counts over the last `days`, grouped by `group_by`
+    /// ("day", "hour", "type" or "severity").
+    ///
+    /// Returns the same shape as `get_anomaly_timeline`, so both can feed the
+    /// same charts.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_insights_timeline(
+        &self,
+        days: i64,
+        group_by: &str,
+    ) -> Result<TimelineResponse> {
+        if days <= 0 {
+            return Err(AnalyticsError::Config(format!(
+                "days must be positive, got {}",
+                days
+            )));
+        }
+        if !INSIGHT_TIMELINE_GROUP_BY.contains(&group_by) {
+            return Err(AnalyticsError::Config(format!(
+                "group_by must be one of {:?}, got {:?}",
+                INSIGHT_TIMELINE_GROUP_BY, group_by
+            )));
+        }
+
+        let payload = serde_json::json!({
+            "days": days,
+            "group_by": group_by
+        });
+
+        self.cached(
+            "get_insights_timeline",
+            payload.to_string(),
+            self.post_or_default("/insights/timeline", &payload, None),
+        )
+        .await
+    }
+
+    /// Submit feedback on whether an insight's recommendation was useful
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn submit_insight_feedback(
+        &self,
+        insight_id: &str,
+        useful: bool,
+        note: Option<&str>,
+    ) -> Result<()> {
+        let path = format!("/insights/{}/feedback", encode_path_segment(insight_id));
+        let payload = serde_json::json!({
+            "useful": useful,
+            "note": note
+        });
+
+        let result = self.post_no_content(&path, &payload).await;
+        self.invalidate_cache();
+        result
+    }
+
+    // --------------------------------------------------------
+    // Connectivity APIs
+    // --------------------------------------------------------
+
+    /// Get connectivity status
+    /// `name_contains` is sent as `q` for server-side search and also applied
+    /// locally with [`ConnectivityResponse::search`], for servers that ignore
+    /// it. The summary counts are left as the server reported them.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_connectivity_status(
+        &self,
+        status_filter: Option<ConnectionStatus>,
+        device_ids: Option<Vec<&str>>,
+        name_contains: Option<&str>,
+    ) -> Result<ConnectivityResponse> {
+        let status = status_filter.map(|s| s.to_string()).unwrap_or_default();
+        let mut params = vec![("status", status)];
+        push_device_ids(&mut params, device_ids);
+        if let Some(query) = name_contains {
+            params.push(("q", query.to_string()));
+        }
+        let args = format!("{:?}", params);
+        let mut response: ConnectivityResponse = self
+            .cached(
+                "get_connectivity_status",
+                args,
+                self.get_or_default("/connectivity/status", &params),
+            )
+            .await?;
+
+        if let Some(query) = name_contains {
+            let query = query.to_lowercase();
+            response
+                .devices
+                .retain(|device| device_matches(device, &query));
+        }
+        Ok(response)
+    }
+
+    /// Get latency statistics
+    /// `device_ids` limits the stats to those devices when `Some`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_latency_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<LatencyResponse> {
+        let mut params = vec![("hours", hours.to_string())];
+        push_device_ids(&mut params, device_ids);
+        let args = format!("{:?}", params);
+        self.cached(
+            "get_latency_stats",
+            args,
+            self.get_or_default("/connectivity/latency", &params),
+        )
+        .await
+    }
+
+    /// Get throughput statistics
+    /// `device_ids` limits the stats to those devices when `Some`.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_throughput_stats(
+        &self,
+        hours: i64,
+        device_ids: Option<Vec<&str>>,
+    ) -> Result<ThroughputResponse> {
+        let mut params = vec![("hours", hours.to_string())];
+        push_device_ids(&mut params, device_ids);
+        let args = format!("{:?}", params);
+        self.cached(
+            "get_throughput_stats",
+            args,
+            self.get_or_default("/connectivity/throughput", &params),
+        )
+        .await
+    }
+
+    /// Get connection quality
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_connection_quality(&self) -> Result<QualityResponse> {
+        self.cached(
+            "get_connection_quality",
+            String::new(),
+            self.get_or_default("/connectivity/quality", &[]),
+        )
+        .await
+    }
+
+    // --------------------------------------------------------
+    // Device Health APIs
+    // --------------------------------------------------------
+
+    /// Get a single device's health rolled up across all analytics domains
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_device_health(
+        &self,
+        device_id: &str,
+        time_range: Option<TimeRange>,
+    ) -> Result<DeviceHealth> {
+        let args = format!("{:?}", (device_id, &time_range));
+        let tr = time_range.unwrap_or_else(|| TimeRange::last_days(7));
+        tr.validate()?;
+
+        let path = format!("/devices/{}/health", encode_path_segment(device_id));
+        let payload = serde_json::json!({
+            "time_range": { "start": tr.start, "end": tr.end }
+        });
+
+        self.cached("get_device_health", args, self.post(&path, &payload))
+            .await
+    }
+
+    // --------------------------------------------------------
+    // Raw Access
+    // --------------------------------------------------------
+
+    /// GET an endpoint the typed API doesn't cover yet and return untyped JSON.
+    ///
+    /// Authentication, token refresh, and status/timeout errors work as for the
+    /// typed methods, but the response bypasses model validation entirely.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn get_raw(
+        &self,
+        path: &str,
+        params: &[(&str, String)],
+    ) -> Result<serde_json::Value> {
+        self.get(path, params).await
+    }
+
+    /// POST to an endpoint the typed API doesn't cover yet and return untyped JSON.
+    ///
+    /// Like [`get_raw`](Self::get_raw), this skips model validation.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self, body)))]
+    pub async fn post_raw(
+        &self,
+        path: &str,
+        body: &serde_json::Value,
+    ) -> Result<serde_json::Value> {
+        let result = self.post(path, body).await;
+        // The endpoint may mutate state, so cached reads could be stale
+        self.invalidate_cache();
+        result
+    }
+
+    // --------------------------------------------------------
+    // Polling
+    // --------------------------------------------------------
+
+    /// Poll `get_anomalies` every `interval` and yield anomalies whose id hasn't
+    /// been seen before, oldest first.
+    ///
+    /// Delivery is at-least-once: the first poll yields everything on the
+    /// current page, and only the most recent [`POLL_SEEN_CAPACITY`] ids are
+    /// remembered, so an anomaly that stays on the page after its id is evicted
+    /// is yielded again. Consumers that need exactly-once should dedupe on
+    /// `Anomaly::id`. A failed poll yields an `Err` and polling continues.
+    ///
+    /// Not available on wasm32, which has no tokio timer.
+    #[cfg(not(target_arch = "wasm32"))]
+    pub fn poll_anomalies(
+        &self,
+        interval: std::time::Duration,
+    ) -> impl futures_util::Stream<Item = Result<Anomaly>> + '_ {
+        let mut ticker = tokio::time::interval(interval);
+        ticker.set_missed_tick_behavior(tokio::time::MissedTickBehavior::Delay);
+
+        let state = PollState {
+            client: self,
+            ticker,
+            seen: SeenIds::new(POLL_SEEN_CAPACITY),
+            pending: std::collections::VecDeque::new(),
+        };
+
+        futures_util::stream::unfold(state, |mut state| async move {
+            loop {
+                if let Some(anomaly) = state.pending.pop_front() {
+                    return Some((Ok(anomaly), state));
+                }
+
+                state.ticker.tick().await;
+                let page = state
+                    .client
+                    .get_anomalies(AnomalyQuery::new().sort(AnomalySort::TimestampDesc))
+                    .await;
+
+                match page {
+                    // Newest first from the server; queue oldest first
+                    Ok(page) => {
+                        for anomaly in page.anomalies.into_iter().rev() {
+                            if state.seen.insert(&anomaly.id) {
+                                state.pending.push_back(anomaly);
+                            }
+                        }
+                    }
+                    Err(e) => return Some((Err(e), state)),
+                }
+            }
+        })
+    }
+
+    // --------------------------------------------------------
+    // Per-call timeouts
+    // --------------------------------------------------------
+
+    /// Run the heavier calls with `timeout` instead of the client timeout.
+    ///
+    /// Clustering and insights can legitimately take longer than the quick
+    /// lookups the client timeout is tuned for:
+    ///
+    /// ```no_run
+    /// # async fn run(client: tesaiot_analytics::AnalyticsClient) -> tesaiot_analytics::Result<()> {
+    /// use std::time::Duration;
+    ///
+    /// let clusters = client
+    ///     .with_timeout(Duration::from_secs(120))
+    ///     .get_clusters("temperature", 5, None, true)
+    ///     .await?;
+    /// # Ok(())
+    /// # }
+    /// ```
+    pub fn with_timeout(&self, timeout: std::time::Duration) -> WithTimeout<'_> {
+        WithTimeout {
+            client: self,
+            timeout,
+        }
+    }
+
+    // --------------------------------------------------------
+    // Snapshot
+    // --------------------------------------------------------
+
+    /// Fetch anomalies, clusters, insights, connectivity, latency, throughput,
+    /// and quality concurrently, at most `max_concurrency` at a time. Fails if
+    /// any of the calls fails.
+    #[cfg_attr(feature = "tracing", tracing::instrument(skip(self)))]
+    pub async fn snapshot(&self, opts: SnapshotOptions) -> Result<AnalyticsSnapshot> {
+        AnalyticsApi::snapshot(self, opts).await
+    }
+}
+
+/// Heavier client calls with a per-call timeout, from
+/// [`AnalyticsClient::with_timeout`]
+///
+/// A call that runs past the timeout fails with [`AnalyticsError::Timeout`].
+#[derive(Debug, Clone, Copy)]
+pub struct WithTimeout<'a> {
+    client: &'a AnalyticsClient,
+    timeout: std::time::Duration,
+}
+
+impl WithTimeout<'_> {
+    /// [`AnalyticsClient::get_anomaly_timeline`] with this timeout
+    pub async fn get_anomaly_timeline(
+        &self,
+        days: i64,
+        group_by: &str,
+    ) -> Result<TimelineResponse> {
+        self.client
+            .anomaly_timeline(days, group_by, Some(self.timeout))
+            .await
+    }
+
+    /// [`AnalyticsClient::get_clusters`] with this timeout
+    pub async fn get_clusters(
+        &self,
+        metric_name: &str,
+        n_clusters: i32,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.get_clusters_with(
+            metric_name,
+            ClusterAlgorithm::KMeans { n_clusters },
+            time_range,
+            include_outliers,
+        )
+        .await
+    }
+
+    /// [`AnalyticsClient::get_clusters_with`] with this timeout
+    pub async fn get_clusters_with(
+        &self,
+        metric_name: &str,
+        algorithm: ClusterAlgorithm,
+        time_range: Option<TimeRange>,
+        include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.client
+            .clusters(
+                metric_name,
+                algorithm,
+                time_range,
+                include_outliers,
+                Some(self.timeout),
+            )
+            .await
+    }
+
+    /// [`AnalyticsClient::get_insights`] with this timeout
+    pub async fn get_insights(
+        &self,
+        days: i64,
+        insight_types: Option<Vec<&str>>,
+        min_confidence: f64,
+    ) -> Result<InsightsResponse> {
+        self.client
+            .insights(days, insight_types, min_confidence, Some(self.timeout))
+            .await
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_server::{Reply, TestServer};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Arc;
+
+    const EMPTY_ANOMALIES: &str = r#"{"anomalies": [], "summary": {"total": 0}}"#;
+
+    #[tokio::test]
+    async fn metric_filter_sends_each_metric() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        client
+            .get_anomalies(AnomalyQuery::new().metrics(["temperature", "humidity"]))
+            .await
+            .unwrap();
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].method, "GET");
+        assert!(requests[0].target.starts_with("/api/anomalies?"));
+        assert!(requests[0].target.contains("metric=temperature"));
+        assert!(requests[0].target.contains("metric=humidity"));
+    }
+
+    #[test]
+    fn device_latency_accepts_aliases_and_missing_fields() {
+        let response: LatencyResponse = serde_json::from_str(
+            r#"{
+                "summary": {"overall_avg_ms": 40, "overall_p95_ms": 90, "overall_p99_ms": 120},
+                "devices": [
+                    {"device_id": "d1", "avg_latency_ms": 35.5, "p95_latency_ms": 80,
+                     "p99_latency_ms": 110, "samples": 42},
+                    {"device_id": "d2", "avg_ms": 12, "p95_ms": 20},
+                    {}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        let d1 = &response.devices[0];
+        assert_eq!(d1.device_id, "d1");
+        assert_eq!(d1.avg_ms, 35.5);
+        assert_eq!(d1.p95_ms, 80.0);
+        assert_eq!(d1.p99_ms, 110.0);
+        assert_eq!(d1.sample_count, 42);
+
+        let d2 = &response.devices[1];
+        assert_eq!(d2.avg_ms, 12.0);
+        assert_eq!(d2.p95_ms, 20.0);
+        assert_eq!(d2.sample_count, 0);
+
+        assert_eq!(response.devices[2].device_id, "");
+        assert_eq!(response.devices[2].avg_ms, 0.0);
+    }
+
+    #[test]
+    fn quality_issues_deserialize_into_typed_form() {
+        let response: QualityResponse = serde_json::from_str(
+            r#"{
+                "summary": {
+                    "average_quality_score": 81.5,
+                    "distribution": {"excellent": 10, "good": 5, "fair": 2, "poor": 1}
+                },
+                "issues": [
+                    {"device_id": "d1", "issue_type": "packet_loss", "severity": "high",
+                     "description": "12% packet loss", "detected_at": "2024-01-15T10:00:00Z"},
+                    {"device_id": "d2", "type": "jitter", "message": "unstable latency",
+                     "timestamp": "2024-01-15T11:00:00Z"}
+                ]
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(response.issues.len(), 2);
+        let first = &response.issues[0];
+        assert_eq!(first.device_id, "d1");
+        assert_eq!(first.issue_type, "packet_loss");
+        assert_eq!(first.severity, "high");
+        assert_eq!(first.description, "12% packet loss");
+        assert_eq!(first.detected_at, "2024-01-15T10:00:00Z");
+
+        let second = &response.issues[1];
+        assert_eq!(second.issue_type, "jitter");
+        assert_eq!(second.severity, "");
+        assert_eq!(second.description, "unstable latency");
+        assert_eq!(second.detected_at, "2024-01-15T11:00:00Z");
+    }
+
+    #[test]
+    fn quality_issues_default_to_empty() {
+        let response: QualityResponse = serde_json::from_str(
+            r#"{"summary": {"average_quality_score": 0, "distribution":
+                {"excellent": 0, "good": 0, "fair": 0, "poor": 0}}}"#,
+        )
+        .unwrap();
+        assert!(response.issues.is_empty());
+    }
+
+    fn insight(id: &str, severity: &str, confidence: f64, actionable: bool) -> Insight {
+        Insight {
+            id: id.to_string(),
+            insight_type: InsightType::Trend,
+            severity: severity.to_string(),
+            title: format!("Insight {}", id),
+            description: String::new(),
+            confidence,
+            actionable,
+            recommended_actions: Vec::new(),
+        }
+    }
+
+    fn connectivity(total: i64, online: i64, offline: i64) -> ConnectivityResponse {
+        ConnectivityResponse {
+            devices: Vec::new(),
+            summary: ConnectivitySummary {
+                total_devices: total,
+                online_count: online,
+                offline_count: offline,
+                ..Default::default()
+            },
+        }
+    }
+
+    fn latency_p95(p95: f64) -> LatencyResponse {
+        LatencyResponse {
+            summary: LatencySummary {
+                overall_p95_ms: p95,
+                ..Default::default()
+            },
+            devices: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn fleet_health_weights_each_component() {
+        let insights = InsightsResponse {
+            insights: vec![
+                insight("i1", "critical", 0.9, true),
+                insight("i2", "warning", 0.8, false),
+                insight("i3", "warning", 0.7, false),
+            ],
+            ..Default::default()
+        };
+
+        let health = FleetHealth::compute(
+            &AnomaliesResponse::default(),
+            &connectivity(10, 9, 1),
+            &insights,
+            &latency_p95(200.0),
+        );
+
+        assert_eq!(health.component_scores["anomaly"], 100.0);
+        assert_eq!(health.component_scores["connectivity"], 90.0);
+        assert_eq!(health.component_scores["latency"], 80.0);
+        assert_eq!(health.component_scores["insights"], 70.0);
+        // 100 * 0.3 + 90 * 0.3 + 80 * 0.2 + 70 * 0.2
+        assert_eq!(health.overall_score, 87.0);
+        assert_eq!(health.grade(), HealthGrade::Good);
+        assert_eq!(health.status, "GOOD");
+    }
+
+    #[test]
+    fn fleet_health_custom_weights() {
+        let weights = FleetHealthWeights {
+            anomaly: 0.0,
+            connectivity: 1.0,
+            latency: 0.0,
+            insights: 0.0,
+        };
+
+        let health = FleetHealth::compute_with_weights(
+            &AnomaliesResponse::default(),
+            &connectivity(4, 1, 3),
+            &InsightsResponse::default(),
+            &latency_p95(0.0),
+            &weights,
+        );
+
+        assert_eq!(health.overall_score, 25.0);
+        assert_eq!(health.grade(), HealthGrade::Critical);
+    }
+
+    #[test]
+    fn fleet_health_clamps_scores_at_zero() {
+        let anomalies = AnomaliesResponse {
+            summary: AnomalySummary {
+                total: 5,
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        // No devices reported: the rate is taken over one device
+        let health = FleetHealth::compute(
+            &anomalies,
+            &connectivity(0, 0, 0),
+            &InsightsResponse::default(),
+            &latency_p95(5000.0),
+        );
+
+        assert_eq!(health.component_scores["anomaly"], 0.0);
+        assert_eq!(health.component_scores["connectivity"], 0.0);
+        assert_eq!(health.component_scores["latency"], 0.0);
+        assert_eq!(health.component_scores["insights"], 100.0);
+        assert_eq!(health.overall_score, 20.0);
+    }
+
+    fn quality_poor(poor: i64) -> QualityResponse {
+        QualityResponse {
+            summary: QualitySummary {
+                distribution: QualityDistribution {
+                    poor,
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            issues: Vec::new(),
+        }
+    }
+
+    fn alerts_for(
+        connectivity: &ConnectivityResponse,
+        p95: f64,
+        poor: i64,
+    ) -> Vec<(AlertLevel, String)> {
+        generate_alerts(
+            &AnomaliesResponse::default(),
+            connectivity,
+            &latency_p95(p95),
+            &quality_poor(poor),
+        )
+        .into_iter()
+        .map(|alert| (alert.level, alert.alert_type))
+        .collect()
+    }
+
+    #[test]
+    fn exactly_twenty_percent_offline_is_a_warning() {
+        let alerts = alerts_for(&connectivity(10, 8, 2), 0.0, 0);
+        assert_eq!(alerts, vec![(AlertLevel::Warning, "connectivity".into())]);
+
+        let alerts = alerts_for(&connectivity(100, 79, 21), 0.0, 0);
+        assert_eq!(alerts, vec![(AlertLevel::Critical, "connectivity".into())]);
+    }
+
+    #[test]
+    fn latency_alert_boundaries() {
+        let online = connectivity(10, 10, 0);
+        assert!(alerts_for(&online, 500.0, 0).is_empty());
+        assert_eq!(
+            alerts_for(&online, 500.5, 0),
+            vec![(AlertLevel::Warning, "latency".into())]
+        );
+        assert_eq!(
+            alerts_for(&online, 1000.0, 0),
+            vec![(AlertLevel::Warning, "latency".into())]
+        );
+        assert_eq!(
+            alerts_for(&online, 1000.5, 0),
+            vec![(AlertLevel::Critical, "latency".into())]
+        );
+    }
+
+    #[test]
+    fn poor_quality_alert_boundary() {
+        let online = connectivity(10, 10, 0);
+        assert!(alerts_for(&online, 0.0, 5).is_empty());
+        assert_eq!(
+            alerts_for(&online, 0.0, 6),
+            vec![(AlertLevel::Warning, "quality".into())]
+        );
+    }
+
+    #[test]
+    fn alerts_are_sorted_critical_first() {
+        let thresholds = AlertThresholds {
+            poor_quality_count: 0,
+            ..Default::default()
+        };
+        let anomalies = AnomaliesResponse {
+            summary: AnomalySummary {
+                total: 1,
+                by_severity: HashMap::from([("critical".to_string(), 1)]),
+                ..Default::default()
+            },
+            ..Default::default()
+        };
+
+        let levels: Vec<AlertLevel> = thresholds
+            .evaluate(
+                &anomalies,
+                &connectivity(10, 9, 1),
+                &latency_p95(2000.0),
+                &quality_poor(1),
+            )
+            .into_iter()
+            .map(|alert| alert.level)
+            .collect();
+
+        assert_eq!(
+            levels,
+            vec![
+                AlertLevel::Critical,
+                AlertLevel::Critical,
+                AlertLevel::Warning,
+                AlertLevel::Warning
+            ]
+        );
+    }
+
+    fn utc(rfc3339: &str) -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339(rfc3339)
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    #[test]
+    fn time_range_validate_rejects_inverted_and_empty_ranges() {
+        let start = utc("2024-01-15T10:00:00Z");
+        let end = utc("2024-01-15T12:00:00Z");
+
+        assert!(TimeRange::custom(start, end).validate().is_ok());
+        assert!(matches!(
+            TimeRange::custom(end, start).validate(),
+            Err(AnalyticsError::Config(_))
+        ));
+        assert!(matches!(
+            TimeRange::custom(start, start).validate(),
+            Err(AnalyticsError::Config(_))
+        ));
+    }
+
+    #[test]
+    fn time_range_validate_rejects_malformed_endpoints() {
+        let malformed = TimeRange {
+            start: "yesterday".to_string(),
+            end: "2024-01-15T12:00:00Z".to_string(),
+        };
+        match malformed.validate() {
+            Err(AnalyticsError::Config(message)) => assert!(message.contains("start")),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+
+        let malformed = TimeRange {
+            start: "2024-01-15T10:00:00Z".to_string(),
+            end: "2024-01-15 12:00".to_string(),
+        };
+        match malformed.validate() {
+            Err(AnalyticsError::Config(message)) => assert!(message.contains("end")),
+            other => panic!("expected a config error, got {:?}", other),
+        }
+    }
+
+    #[test]
+    fn time_range_duration() {
+        let range = TimeRange::custom(utc("2024-01-15T10:00:00Z"), utc("2024-01-15T12:30:00Z"));
+        assert_eq!(range.duration().unwrap(), Duration::minutes(150));
+
+        let inverted = TimeRange::custom(utc("2024-01-15T12:00:00Z"), utc("2024-01-15T10:00:00Z"));
+        assert!(inverted.duration().is_err());
+    }
+
+    #[tokio::test]
+    async fn get_clusters_validates_range_before_sending() {
+        let server = TestServer::start(vec![Reply::json(200, "{}")]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+        let inverted = TimeRange::custom(utc("2024-01-15T12:00:00Z"), utc("2024-01-15T10:00:00Z"));
+
+        let result = client
+            .get_clusters("temperature", 3, Some(inverted), false)
+            .await;
+
+        assert!(matches!(result, Err(AnalyticsError::Config(_))));
+        assert!(server.requests().is_empty());
+    }
+
+    /// Clock pinned to one instant
+    struct At(DateTime<Utc>);
+
+    impl Clock for At {
+        fn now(&self) -> DateTime<Utc> {
+            self.0
+        }
+    }
+
+    #[test]
+    fn calendar_ranges_snap_to_utc_boundaries() {
+        // A Wednesday afternoon
+        let clock = At(utc("2024-01-17T15:30:00Z"));
+
+        let today = TimeRange::today_with(&clock);
+        assert_eq!(today.start_dt().unwrap(), utc("2024-01-17T00:00:00Z"));
+        assert_eq!(today.end_dt().unwrap(), clock.0);
+
+        let week = TimeRange::this_week_with(&clock);
+        assert_eq!(week.start_dt().unwrap(), utc("2024-01-15T00:00:00Z"));
+        assert_eq!(week.end_dt().unwrap(), clock.0);
+
+        let month = TimeRange::this_month_with(&clock);
+        assert_eq!(month.start_dt().unwrap(), utc("2024-01-01T00:00:00Z"));
+        assert_eq!(month.end_dt().unwrap(), clock.0);
+    }
+
+    #[test]
+    fn this_week_starts_on_monday() {
+        let sunday = At(utc("2024-01-21T23:59:59Z"));
+        assert_eq!(
+            TimeRange::this_week_with(&sunday).start_dt().unwrap(),
+            utc("2024-01-15T00:00:00Z")
+        );
+
+        // Across a month boundary
+        let wednesday = At(utc("2024-05-01T08:00:00Z"));
+        assert_eq!(
+            TimeRange::this_week_with(&wednesday).start_dt().unwrap(),
+            utc("2024-04-29T00:00:00Z")
+        );
+    }
+
+    fn cluster(id: i32, devices: &[&str]) -> Cluster {
+        Cluster {
+            cluster_id: id,
+            cluster_name: format!("cluster-{}", id),
+            device_count: devices.len() as i64,
+            characteristics: HashMap::new(),
+            devices: devices.iter().map(|d| d.to_string()).collect(),
+        }
+    }
+
+    #[test]
+    fn cluster_for_device_finds_member_and_skips_outlier() {
+        let clusters = ClustersResponse {
+            clusters: vec![cluster(0, &["d1", "d2"]), cluster(1, &["d3"])],
+            silhouette_score: 0.6,
+            outliers: vec![Outlier {
+                device_id: "d9".to_string(),
+                outlier_score: 3.2,
+                reason: None,
+            }],
+        };
+
+        assert_eq!(clusters.cluster_for_device("d3").unwrap().cluster_id, 1);
+        assert_eq!(clusters.cluster_for_device("d2").unwrap().cluster_id, 0);
+        assert!(clusters.cluster_for_device("d9").is_none());
+    }
+
+    #[tokio::test]
+    async fn get_device_cluster_found_and_missing() {
+        let server = TestServer::start(vec![
+            Reply::json(
+                200,
+                r#"{"cluster": {"cluster_id": 2, "cluster_name": "warm", "device_count": 1,
+                    "characteristics": {"mean": 31.5}, "devices": ["d1"]}}"#,
+            ),
+            Reply::json(404, r#"{"detail": "device is not clustered"}"#),
+        ])
+        .await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        let found = client
+            .get_device_cluster("temperature", "d1")
+            .await
+            .unwrap();
+        assert_eq!(found.unwrap().cluster_id, 2);
+
+        let missing = client
+            .get_device_cluster("temperature", "d9")
+            .await
+            .unwrap();
+        assert!(missing.is_none());
+
+        let requests = server.requests();
+        assert_eq!(
+            requests[0].target,
+            "/api/patterns/devices/d1/cluster?metric_name=temperature"
+        );
+    }
+
+    fn insights_fixture() -> InsightsResponse {
+        InsightsResponse {
+            insights: vec![
+                insight("a", "warning", 0.7, false),
+                insight("b", "critical", 0.9, true),
+                insight("c", "Critical", 0.7, true),
+                insight("d", "info", 0.9, false),
+            ],
+            ..Default::default()
+        }
+    }
+
+    fn ids(insights: &[&Insight]) -> Vec<String> {
+        insights.iter().map(|i| i.id.clone()).collect()
+    }
+
+    #[test]
+    fn insights_actionable_and_by_severity() {
+        let response = insights_fixture();
+        assert_eq!(ids(&response.actionable()), ["b", "c"]);
+        assert_eq!(ids(&response.by_severity(Severity::Critical)), ["b", "c"]);
+        assert_eq!(ids(&response.by_severity(Severity::Warning)), ["a"]);
+        assert!(response.by_severity(Severity::Low).is_empty());
+    }
+
+    #[test]
+    fn insights_sorted_by_confidence_keeps_ties_in_order() {
+        let response = insights_fixture();
+        assert_eq!(ids(&response.sorted_by_confidence()), ["b", "d", "a", "c"]);
+    }
+
+    #[tokio::test]
+    async fn get_insights_accepts_confidence_bounds() {
+        let server = TestServer::start(vec![Reply::json(
+            200,
+            r#"{"insights": [], "fleet_summary": {"total_devices": 0,
+                "active_devices": 0, "anomaly_rate": 0, "health_score": 100}}"#,
+        )])
+        .await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        client.get_insights(7, None, 0.0).await.unwrap();
+        client.get_insights(7, None, 1.0).await.unwrap();
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn get_insights_rejects_out_of_range_input() {
+        let server = TestServer::start(vec![Reply::json(200, "{}")]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        for (days, min_confidence) in [(7, 1.5), (7, -0.2), (7, f64::NAN), (0, 0.5), (-1, 0.5)] {
+            let result = client.get_insights(days, None, min_confidence).await;
+            assert!(
+                matches!(result, Err(AnalyticsError::Config(_))),
+                "days={} min_confidence={} gave {:?}",
+                days,
+                min_confidence,
+                result
+            );
+        }
+        assert!(server.requests().is_empty());
+    }
+
+    fn anomaly(id: &str, device_id: &str, metric: &str, score: f64) -> Anomaly {
+        Anomaly {
+            id: id.to_string(),
+            device_id: device_id.to_string(),
+            device_name: format!("Device {}", device_id),
+            metric: metric.to_string(),
+            value: 42.5,
+            severity: "high".to_string(),
+            score,
+            timestamp: "2024-01-15T10:00:00Z".to_string(),
+            acknowledged: false,
+            resolved: false,
+        }
+    }
+
+    #[test]
+    fn csv_export_round_trips_through_a_csv_parser() {
+        let mut tricky = anomaly("a2", "d2", "humidity", 0.5);
+        tricky.device_name = "Boiler, \"north\" wing\nfloor 2".to_string();
+        tricky.acknowledged = true;
+        let response = AnomaliesResponse {
+            anomalies: vec![anomaly("a1", "d1", "temperature", 0.9), tricky],
+            ..Default::default()
+        };
+
+        let mut out = Vec::new();
+        response.to_csv(&mut out).unwrap();
+
+        let mut reader = csv::Reader::from_reader(out.as_slice());
+        assert_eq!(
+            reader.headers().unwrap(),
+            vec![
+                "id",
+                "device_id",
+                "device_name",
+                "metric",
+                "value",
+                "severity",
+                "score",
+                "timestamp",
+                "acknowledged",
+                "resolved"
+            ]
+        );
+
+        let rows: Vec<csv::StringRecord> = reader.records().map(|r| r.unwrap()).collect();
+        assert_eq!(rows.len(), 2);
+        for (row, anomaly) in rows.iter().zip(&response.anomalies) {
+            assert_eq!(&row[0], anomaly.id);
+            assert_eq!(&row[1], anomaly.device_id);
+            assert_eq!(&row[2], anomaly.device_name);
+            assert_eq!(&row[3], anomaly.metric);
+            assert_eq!(row[4].parse::<f64>().unwrap(), anomaly.value);
+            assert_eq!(&row[5], anomaly.severity);
+            assert_eq!(row[6].parse::<f64>().unwrap(), anomaly.score);
+            assert_eq!(&row[7], anomaly.timestamp);
+            assert_eq!(row[8].parse::<bool>().unwrap(), anomaly.acknowledged);
+            assert_eq!(row[9].parse::<bool>().unwrap(), anomaly.resolved);
+        }
+    }
+
+    #[test]
+    fn redact_token_hides_the_whole_token() {
+        for token in ["", "short", "eyJhbGciOiJIUzI1NiJ9.payload.signature"] {
+            assert_eq!(redact_token(token), "***redacted***");
+        }
+    }
+
+    #[test]
+    fn debug_output_does_not_leak_the_token() {
+        let client = AnalyticsClient::new("https://example.com/api", "secret-token-1234").unwrap();
+        let debug = format!("{:?}", client);
+        assert!(debug.contains("***redacted***"));
+        assert!(!debug.contains("1234"));
+        assert!(debug.contains("https://example.com/api"));
+    }
+
+    #[tokio::test]
+    async fn api_key_scheme_sends_x_api_key() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "key-123").unwrap();
+
+        client
+            .get_metric_anomalies("temperature", 10, 0)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("x-api-key"), Some("key-123"));
+        assert_eq!(request.header("authorization"), None);
+    }
+
+    #[tokio::test]
+    async fn bearer_scheme_sends_authorization() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::builder(&server.url(), "jwt-abc")
+            .auth_scheme(AuthScheme::Bearer)
+            .build()
+            .unwrap();
+
+        client
+            .get_metric_anomalies("temperature", 10, 0)
+            .await
+            .unwrap();
+
+        let request = &server.requests()[0];
+        assert_eq!(request.header("authorization"), Some("Bearer jwt-abc"));
+        assert_eq!(request.header("x-api-key"), None);
+    }
+
+    #[test]
+    fn auth_scheme_parse() {
+        assert_eq!(AuthScheme::parse("API-Key").unwrap(), AuthScheme::ApiKey);
+        assert_eq!(AuthScheme::parse(" bearer ").unwrap(), AuthScheme::Bearer);
+        assert!(AuthScheme::parse("basic").is_err());
+    }
+
+    /// Token provider that hands out "fresh-1", "fresh-2", ... and counts calls
+    fn counting_provider() -> (TokenProvider, Arc<AtomicUsize>) {
+        let calls = Arc::new(AtomicUsize::new(0));
+        let counter = Arc::clone(&calls);
+        let provider: TokenProvider = Arc::new(move || {
+            let n = counter.fetch_add(1, Ordering::SeqCst) + 1;
+            Box::pin(async move { Ok(format!("fresh-{}", n)) })
+        });
+        (provider, calls)
+    }
+
+    #[tokio::test]
+    async fn refreshes_token_once_after_401_and_retries() {
+        let server = TestServer::start(vec![
+            Reply::json(401, r#"{"detail": "token expired"}"#),
+            Reply::json(200, EMPTY_ANOMALIES),
+        ])
+        .await;
+        let (provider, calls) = counting_provider();
+        let client = AnalyticsClient::builder(&server.url(), "stale")
+            .token_provider(provider)
+            .build()
+            .unwrap();
+
+        client
+            .get_metric_anomalies("temperature", 10, 0)
+            .await
+            .unwrap();
+
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        let requests = server.requests();
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].header("x-api-key"), Some("stale"));
+        assert_eq!(requests[1].header("x-api-key"), Some("fresh-1"));
+    }
+
+    #[tokio::test]
+    async fn repeated_401_is_not_retried_again() {
+        let server = TestServer::start(vec![Reply::json(401, r#"{"detail": "revoked"}"#)]).await;
+        let (provider, calls) = counting_provider();
+        let client = AnalyticsClient::builder(&server.url(), "stale")
+            .token_provider(provider)
+            .build()
+            .unwrap();
+
+        let result = client.get_metric_anomalies("temperature", 10, 0).await;
+
+        assert!(matches!(result, Err(AnalyticsError::Unauthorized { .. })));
+        assert_eq!(calls.load(Ordering::SeqCst), 1);
+        assert_eq!(server.requests().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn unauthorized_without_provider_is_returned_as_is() {
+        let server = TestServer::start(vec![Reply::json(401, r#"{"detail": "nope"}"#)]).await;
+        let client = AnalyticsClient::new(&server.url(), "stale").unwrap();
+
+        let result = client.get_metric_anomalies("temperature", 10, 0).await;
+
+        assert!(matches!(result, Err(AnalyticsError::Unauthorized { .. })));
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn trailing_slash_in_base_url_is_trimmed() {
+        let plain = AnalyticsClient::new("https://example.com/api/v1", "t").unwrap();
+        let slashed = AnalyticsClient::new("https://example.com/api/v1/", "t").unwrap();
+
+        for path in ["/anomalies", "anomalies"] {
+            assert_eq!(plain.url(path), "https://example.com/api/v1/anomalies");
+            assert_eq!(slashed.url(path), plain.url(path));
+        }
+    }
+
+    #[tokio::test]
+    async fn trailing_slash_base_url_requests_single_slash_paths() {
+        let server = TestServer::start(vec![Reply::json(200, "{}")]).await;
+        let client = AnalyticsClient::new(&format!("{}/", server.url()), "t").unwrap();
+
+        client.get_raw("/custom/endpoint", &[]).await.unwrap();
+        client.get_raw("custom/endpoint", &[]).await.unwrap();
+
+        let targets: Vec<String> = server.requests().into_iter().map(|r| r.target).collect();
+        assert_eq!(targets, ["/api/custom/endpoint", "/api/custom/endpoint"]);
+    }
+
+    const ANOMALY_JSON: &str = r#"{"id": "a/1", "device_id": "d1", "device_name": "Boiler",
+        "metric": "temperature", "value": 98.6, "severity": "critical", "score": 0.97,
+        "timestamp": "2024-01-15T10:00:00Z"}"#;
+
+    #[tokio::test]
+    async fn get_anomaly_found_and_not_found() {
+        let server = TestServer::start(vec![
+            Reply::json(200, ANOMALY_JSON),
+            Reply::json(404, r#"{"detail": "not found"}"#),
+        ])
+        .await;
+        let client = AnalyticsClient::new(&server.url(), "t").unwrap();
+
+        let anomaly = client.get_anomaly("a/1").await.unwrap();
+        assert_eq!(anomaly.id, "a/1");
+        assert_eq!(anomaly.severity, "critical");
+
+        let missing = client.get_anomaly("missing").await;
+        assert!(matches!(
+            missing,
+            Err(AnalyticsError::Api { status: 404, .. })
+        ));
+
+        let targets: Vec<String> = server.requests().into_iter().map(|r| r.target).collect();
+        assert_eq!(targets, ["/api/anomalies/a%2F1", "/api/anomalies/missing"]);
+    }
+
+    #[tokio::test]
+    async fn get_anomaly_opt_maps_404_to_none() {
+        let server = TestServer::start(vec![
+            Reply::json(200, ANOMALY_JSON),
+            Reply::json(404, r#"{"detail": "not found"}"#),
+            Reply::json(500, r#"{"detail": "boom"}"#),
+        ])
+        .await;
+        let client = AnalyticsClient::new(&server.url(), "t").unwrap();
+
+        assert!(client.get_anomaly_opt("a/1").await.unwrap().is_some());
+        assert!(client.get_anomaly_opt("missing").await.unwrap().is_none());
+        assert!(matches!(
+            client.get_anomaly_opt("other").await,
+            Err(AnalyticsError::Api { status: 500, .. })
+        ));
+    }
+
+    fn scored(scores: &[f64]) -> AnomaliesResponse {
+        AnomaliesResponse {
+            anomalies: scores
+                .iter()
+                .enumerate()
+                .map(|(i, score)| anomaly(&format!("a{}", i), "d1", "temperature", *score))
+                .collect(),
+            ..Default::default()
+        }
+    }
+
+    fn anomaly_ids(anomalies: &[&Anomaly]) -> Vec<String> {
+        anomalies.iter().map(|a| a.id.clone()).collect()
+    }
+
+    #[test]
+    fn top_by_score_puts_nan_last() {
+        let response = scored(&[0.2, f64::NAN, 0.9, 0.5]);
+        assert_eq!(
+            anomaly_ids(&response.top_by_score(4)),
+            ["a2", "a3", "a0", "a1"]
+        );
+        assert_eq!(anomaly_ids(&response.top_by_score(2)), ["a2", "a3"]);
+        assert_eq!(response.top_by_score(10).len(), 4);
+    }
+
+    #[test]
+    fn top_by_score_keeps_equal_scores_in_order() {
+        let response = scored(&[0.5, 0.8, 0.5, 0.8]);
+        assert_eq!(
+            anomaly_ids(&response.top_by_score(4)),
+            ["a1", "a3", "a0", "a2"]
+        );
+    }
+
+    #[test]
+    fn score_helpers_on_empty_list() {
+        let response = AnomaliesResponse::default();
+        assert!(response.top_by_score(5).is_empty());
+        assert!(response.filter_min_score(0.0).is_empty());
+    }
+
+    #[test]
+    fn filter_min_score_is_inclusive_and_skips_nan() {
+        let response = scored(&[0.2, f64::NAN, 0.9, 0.5]);
+        assert_eq!(anomaly_ids(&response.filter_min_score(0.5)), ["a2", "a3"]);
+    }
+
+    #[tokio::test]
+    async fn open_filter_sends_acknowledged_and_resolved() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        client
+            .get_anomalies(AnomalyQuery::new().acknowledged(false).resolved(false))
+            .await
+            .unwrap();
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+
+        let requests = server.requests();
+        assert!(requests[0]
+            .target
+            .contains("acknowledged=false&resolved=false"));
+        assert!(!requests[1].target.contains("acknowledged="));
+        assert!(!requests[1].target.contains("resolved="));
+    }
+
+    #[test]
+    fn group_by_device_keeps_order_across_metrics() {
+        let response = AnomaliesResponse {
+            anomalies: vec![
+                anomaly("a1", "d1", "temperature", 0.9),
+                anomaly("a2", "d2", "temperature", 0.8),
+                anomaly("a3", "d1", "humidity", 0.7),
+                anomaly("a4", "d1", "temperature", 0.6),
+            ],
+            ..Default::default()
+        };
+
+        let by_device = response.group_by_device();
+        assert_eq!(by_device.len(), 2);
+        assert_eq!(anomaly_ids(&by_device["d1"]), ["a1", "a3", "a4"]);
+        assert_eq!(anomaly_ids(&by_device["d2"]), ["a2"]);
+
+        let by_metric = response.group_by_metric();
+        assert_eq!(by_metric.len(), 2);
+        assert_eq!(anomaly_ids(&by_metric["temperature"]), ["a1", "a2", "a4"]);
+        assert_eq!(anomaly_ids(&by_metric["humidity"]), ["a3"]);
+    }
+
+    #[test]
+    fn group_by_on_empty_list() {
+        let response = AnomaliesResponse::default();
+        assert!(response.group_by_device().is_empty());
+        assert!(response.group_by_metric().is_empty());
+    }
+
+    #[tokio::test]
+    async fn slow_response_is_a_timeout_error() {
+        let slow = Reply::json(200, EMPTY_ANOMALIES).delay(std::time::Duration::from_secs(2));
+        let server = TestServer::start(vec![slow]).await;
+        let client = AnalyticsClientBuilder::new(&server.url(), "token")
+            .timeout(std::time::Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let err = client.get_anomalies(AnomalyQuery::new()).await.unwrap_err();
+        match err {
+            AnalyticsError::Timeout { elapsed } => {
+                assert!(elapsed < std::time::Duration::from_secs(2))
+            }
+            other => panic!("expected Timeout, got {:?}", other),
+        }
+    }
+
+    #[tokio::test]
+    async fn with_timeout_overrides_the_client_timeout() {
+        let body = serde_json::to_string(&insights_fixture()).unwrap();
+        let slow = Reply::json(200, &body).delay(std::time::Duration::from_millis(300));
+        let server = TestServer::start(vec![slow]).await;
+        let client = AnalyticsClientBuilder::new(&server.url(), "token")
+            .timeout(std::time::Duration::from_secs(10))
+            .build()
+            .unwrap();
+
+        let err = client
+            .with_timeout(std::time::Duration::from_millis(50))
+            .get_insights(7, None, 0.5)
+            .await
+            .unwrap_err();
+        assert!(matches!(err, AnalyticsError::Timeout { .. }), "{:?}", err);
+
+        client.get_insights(7, None, 0.5).await.unwrap();
+    }
+
+    #[test]
+    fn quality_percentages_of_an_empty_distribution_are_zero() {
+        let distribution = QualityDistribution::default();
+        assert_eq!(distribution.total(), 0);
+        let percentages = distribution.percentages();
+        assert_eq!(percentages.len(), 4);
+        assert!(percentages.values().all(|pct| *pct == 0.0));
+    }
+
+    #[test]
+    fn quality_percentages_split_the_total() {
+        let distribution = QualityDistribution {
+            excellent: 5,
+            good: 3,
+            fair: 1,
+            poor: 1,
+        };
+        assert_eq!(distribution.total(), 10);
+        let percentages = distribution.percentages();
+        assert_eq!(percentages["excellent"], 50.0);
+        assert_eq!(percentages["good"], 30.0);
+        assert_eq!(percentages["fair"], 10.0);
+        assert_eq!(percentages["poor"], 10.0);
+    }
+
+    #[test]
+    fn poor_device_ids_only_counts_critical_and_high_issues() {
+        let response: QualityResponse = serde_json::from_str(
+            r#"{
+                "summary": {"average_quality_score": 60,
+                            "distribution": {"excellent": 0, "good": 1, "fair": 1, "poor": 2}},
+                "issues": [
+                    {"device_id": "d1", "issue_type": "packet_loss", "severity": "high"},
+                    {"device_id": "d2", "issue_type": "jitter", "severity": "medium"},
+                    {"device_id": "d3", "issue_type": "disconnects", "severity": "Critical"},
+                    {"device_id": "d1", "issue_type": "jitter", "severity": "critical"},
+                    {"device_id": "d4", "issue_type": "jitter", "severity": "low"},
+                    {"device_id": "d5", "issue_type": "jitter"}
+                ]
+            }"#,
+        )
+        .unwrap();
+        assert_eq!(response.poor_device_ids(), ["d1", "d3"]);
+    }
+
+    #[test]
+    fn dedup_by_id_keeps_first_occurrence_and_fixes_total() {
+        let mut first = anomaly("a1", "d1", "temperature", 0.9);
+        first.value = 1.0;
+        let mut duplicate = anomaly("a1", "d1", "temperature", 0.9);
+        duplicate.value = 2.0;
+        let mut response = AnomaliesResponse {
+            anomalies: vec![first, anomaly("a2", "d2", "humidity", 0.5), duplicate],
+            summary: AnomalySummary {
+                total: 3,
+                ..Default::default()
+            },
+        };
+
+        response.dedup_by_id();
+
+        assert_eq!(
+            response
+                .anomalies
+                .iter()
+                .map(|a| a.id.as_str())
+                .collect::<Vec<_>>(),
+            ["a1", "a2"]
+        );
+        assert_eq!(response.anomalies[0].value, 1.0);
+        assert_eq!(response.summary.total, 2);
+    }
+
+    #[test]
+    fn format_bytes_unit_boundaries() {
+        assert_eq!(format_bytes(0), "0 B");
+        assert_eq!(format_bytes(1023), "1023 B");
+        assert_eq!(format_bytes(1024), "1.0 KiB");
+        assert_eq!(format_bytes(1_048_575), "1.0 MiB");
+        assert_eq!(format_bytes(1_048_576), "1.0 MiB");
+        assert_eq!(format_bytes(1_503_238_554), "1.4 GiB");
+        assert_eq!(format_bytes(-5), "0 B");
+    }
+
+    #[test]
+    fn throughput_summary_formats_bytes_in_and_out() {
+        let summary = ThroughputSummary {
+            total_bytes_in: 1024,
+            total_bytes_out: -1,
+            ..Default::default()
+        };
+        assert_eq!(summary.bytes_in_human(), "1.0 KiB");
+        assert_eq!(summary.bytes_out_human(), "0 B");
+    }
+
+    fn latency_devices(threshold: f64, p95s: &[f64]) -> LatencyResponse {
+        LatencyResponse {
+            summary: LatencySummary {
+                high_latency_threshold_ms: threshold,
+                ..Default::default()
+            },
+            devices: p95s
+                .iter()
+                .enumerate()
+                .map(|(i, p95)| DeviceLatency {
+                    device_id: format!("d{}", i),
+                    p95_ms: *p95,
+                    ..Default::default()
+                })
+                .collect(),
+        }
+    }
+
+    fn latency_ids(devices: &[&DeviceLatency]) -> Vec<String> {
+        devices.iter().map(|d| d.device_id.clone()).collect()
+    }
+
+    #[test]
+    fn high_latency_devices_exceed_the_reported_threshold() {
+        let response = latency_devices(200.0, &[199.9, 200.0, 200.1, 900.0]);
+        assert_eq!(latency_ids(&response.high_latency_devices()), ["d2", "d3"]);
+    }
+
+    #[test]
+    fn high_latency_devices_fall_back_to_the_default_threshold() {
+        let p95s = [
+            DEFAULT_HIGH_LATENCY_THRESHOLD_MS,
+            DEFAULT_HIGH_LATENCY_THRESHOLD_MS + 1.0,
+        ];
+        for threshold in [0.0, -1.0] {
+            let response = latency_devices(threshold, &p95s);
+            assert_eq!(latency_ids(&response.high_latency_devices()), ["d1"]);
+        }
+        assert!(latency_devices(0.0, &[]).high_latency_devices().is_empty());
+    }
+
+    #[cfg(feature = "compression")]
+    #[tokio::test]
+    async fn gzipped_response_is_decompressed() {
+        use std::io::Write;
+
+        let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+        encoder.write_all(ANOMALY_JSON.as_bytes()).unwrap();
+        let gzipped = encoder.finish().unwrap();
+
+        let reply = Reply::json(200, "").encoded(("content-encoding", "gzip"), gzipped);
+        let server = TestServer::start(vec![reply]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        let anomaly = client.get_anomaly("a/1").await.unwrap();
+        assert_eq!(anomaly.id, "a/1");
+
+        let accept = server.requests()[0]
+            .header("accept-encoding")
+            .unwrap_or_default()
+            .to_string();
+        assert!(accept.contains("gzip"), "{}", accept);
+    }
+
+    fn device(id: &str, last_seen: &str, uptime_percent: f64) -> DeviceStatus {
+        DeviceStatus {
+            device_id: id.to_string(),
+            device_name: format!("Device {}", id),
+            status: ConnectionStatus::Online,
+            last_seen: last_seen.to_string(),
+            uptime_percent,
+        }
+    }
+
+    fn device_ids(devices: &[&DeviceStatus]) -> Vec<String> {
+        devices.iter().map(|d| d.device_id.clone()).collect()
+    }
+
+    #[test]
+    fn stale_devices_against_a_one_hour_threshold() {
+        let mut response = connectivity(4, 4, 0);
+        response.devices = vec![
+            device("two-hours", "2024-01-15T10:00:00Z", 99.0),
+            device("two-minutes", "2024-01-15T11:58:00Z", 99.0),
+            device("exactly-one-hour", "2024-01-15T11:00:00Z", 99.0),
+            device("garbled", "yesterday", 99.0),
+        ];
+        let clock = At(utc("2024-01-15T12:00:00Z"));
+
+        let stale = response.stale_devices_with(Duration::hours(1), &clock);
+        assert_eq!(device_ids(&stale), ["two-hours", "garbled"]);
+        assert_eq!(device_ids(&response.unparseable_last_seen()), ["garbled"]);
+    }
+
+    #[test]
+    fn uptime_ordering_breaks_ties_by_device_id() {
+        let mut response = connectivity(5, 5, 0);
+        response.devices = vec![
+            device("d3", "2024-01-15T10:00:00Z", 80.0),
+            device("d1", "2024-01-15T10:00:00Z", 99.5),
+            device("d5", "2024-01-15T10:00:00Z", 42.0),
+            device("d2", "2024-01-15T10:00:00Z", 80.0),
+            device("d4", "2024-01-15T10:00:00Z", 42.0),
+        ];
+
+        assert_eq!(
+            device_ids(&response.sorted_by_uptime()),
+            ["d4", "d5", "d2", "d3", "d1"]
+        );
+        assert_eq!(device_ids(&response.lowest_uptime(3)), ["d4", "d5", "d2"]);
+        assert_eq!(response.lowest_uptime(10).len(), 5);
+        assert!(connectivity(0, 0, 0).lowest_uptime(3).is_empty());
+    }
+
+    #[tokio::test]
+    async fn snapshot_caps_requests_in_flight() {
+        let slow = Reply::json(200, "").delay(std::time::Duration::from_millis(100));
+        let server = TestServer::start(vec![slow]).await;
+        let client = AnalyticsClientBuilder::new(&server.url(), "token")
+            .max_concurrency(2)
+            .build()
+            .unwrap();
+
+        client.snapshot(SnapshotOptions::default()).await.unwrap();
+
+        assert_eq!(server.requests().len(), 7);
+        assert_eq!(server.max_in_flight(), 2);
+    }
+
+    #[test]
+    fn zero_max_concurrency_is_rejected() {
+        let err = AnalyticsClientBuilder::new("http://localhost/api", "token")
+            .max_concurrency(0)
+            .build()
+            .unwrap_err();
+        assert!(matches!(err, AnalyticsError::Config(_)), "{:?}", err);
+    }
+
+    #[tokio::test]
+    async fn get_clusters_rejects_bad_inputs_before_sending() {
+        let server = TestServer::start(vec![Reply::json(200, "")]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        for n_clusters in [i32::MIN, -3, 0, 1] {
+            let err = client
+                .get_clusters("temperature", n_clusters, None, false)
+                .await
+                .unwrap_err();
+            match err {
+                AnalyticsError::Config(message) => assert!(message.contains("n_clusters")),
+                other => panic!("expected Config, got {:?}", other),
+            }
+        }
+        for metric_name in ["", "   "] {
+            let err = client
+                .get_clusters(metric_name, 3, None, false)
+                .await
+                .unwrap_err();
+            assert!(matches!(err, AnalyticsError::Config(_)), "{:?}", err);
+        }
+        assert!(server.requests().is_empty());
+
+        client
+            .get_clusters("temperature", 2, None, false)
+            .await
+            .unwrap();
+        assert_eq!(server.requests().len(), 1);
+    }
+
+    #[test]
+    fn clustering_quality_band_boundaries() {
+        let cases = [
+            (1.0, ClusteringQuality::Strong),
+            (0.7, ClusteringQuality::Strong),
+            (0.699, ClusteringQuality::Reasonable),
+            (0.5, ClusteringQuality::Reasonable),
+            (0.499, ClusteringQuality::Weak),
+            (0.25, ClusteringQuality::Weak),
+            (0.249, ClusteringQuality::Poor),
+            (-1.0, ClusteringQuality::Poor),
+            (f64::NAN, ClusteringQuality::Poor),
+        ];
+        for (score, expected) in cases {
+            assert_eq!(
+                ClusteringQuality::from_silhouette(score),
+                expected,
+                "{}",
+                score
+            );
+        }
+
+        let response = ClustersResponse {
+            silhouette_score: 0.5,
+            ..Default::default()
+        };
+        assert_eq!(response.clustering_quality(), ClusteringQuality::Reasonable);
+        assert_eq!(response.clustering_quality().to_string(), "reasonable");
+        assert!(!ClusteringQuality::Weak.explanation().is_empty());
+    }
+
+    #[tokio::test]
+    async fn overall_deadline_cuts_off_the_third_retry() {
+        let server = TestServer::start(vec![Reply::json(503, "{}")]).await;
+        let delay = std::time::Duration::from_millis(200);
+        let client = AnalyticsClientBuilder::new(&server.url(), "token")
+            .retry(RetryConfig {
+                max_retries: 5,
+                base_delay: delay,
+                max_delay: delay,
+            })
+            // Retries go out at ~200ms and ~400ms; the third would be at ~600ms
+            .overall_deadline(std::time::Duration::from_millis(500))
+            .build()
+            .unwrap();
+
+        let err = client.get_anomalies(AnomalyQuery::new()).await.unwrap_err();
+
+        assert!(matches!(err, AnalyticsError::Timeout { .. }), "{:?}", err);
+        assert_eq!(server.requests().len(), 3);
+    }
+
+    #[test]
+    fn region_names() {
+        assert_eq!(region_base_url(" US ").unwrap(), DEFAULT_BASE_URL);
+        for name in ["eu", "apac", ""] {
+            assert!(matches!(
+                region_base_url(name),
+                Err(AnalyticsError::Config(_))
+            ));
+        }
+    }
+
+    #[tokio::test]
+    async fn device_ids_are_sent_as_repeated_params() {
+        let server = TestServer::start(vec![Reply::json(200, "")]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+        let devices = Some(vec!["d1", "d 2"]);
+
+        client
+            .get_connectivity_status(None, devices.clone(), None)
+            .await
+            .unwrap();
+        client.get_latency_stats(24, devices.clone()).await.unwrap();
+        client.get_throughput_stats(24, devices).await.unwrap();
+        client.get_latency_stats(24, None).await.unwrap();
+
+        let requests = server.requests();
+        for request in &requests[..3] {
+            assert!(
+                request.target.contains("device_id=d1&device_id=d+2"),
+                "{}",
+                request.target
+            );
+        }
+        assert!(!requests[3].target.contains("device_id"));
+    }
+
+    #[tokio::test]
+    async fn no_content_maps_to_default_or_empty_response() {
+        let server = TestServer::start(vec![Reply::json(204, "")]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        let response = client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+        assert!(response.anomalies.is_empty());
+        assert_eq!(response.summary.total, 0);
+
+        let err = client.get_anomaly("a1").await.unwrap_err();
+        assert!(
+            matches!(err, AnalyticsError::EmptyResponse { status: 204 }),
+            "{:?}",
+            err
+        );
+    }
+
+    #[tokio::test]
+    async fn sequential_calls_reuse_the_pooled_connection() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+
+        assert_eq!(server.requests().len(), 2);
+        assert_eq!(server.connections(), 1);
+    }
+
+    #[tokio::test]
+    async fn disabling_the_idle_pool_opens_a_connection_per_call() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClientBuilder::new(&server.url(), "token")
+            .pool_max_idle_per_host(0)
+            .build()
+            .unwrap();
+
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+
+        assert_eq!(server.connections(), 2);
+    }
+
+    fn snapshot_fixture(
+        critical_anomalies: i64,
+        connectivity: ConnectivityResponse,
+        p95: f64,
+    ) -> AnalyticsSnapshot {
+        AnalyticsSnapshot {
+            fetched_at: Utc::now(),
+            anomalies: AnomaliesResponse {
+                summary: AnomalySummary {
+                    total: critical_anomalies,
+                    by_severity: HashMap::from([("critical".to_string(), critical_anomalies)]),
+                    ..Default::default()
+                },
+                ..Default::default()
+            },
+            clusters: ClustersResponse::default(),
+            insights: InsightsResponse::default(),
+            connectivity,
+            latency: latency_p95(p95),
+            throughput: ThroughputResponse::default(),
+            quality: QualityResponse::default(),
+        }
+    }
+
+    #[test]
+    fn overall_status_follows_the_health_grade_without_critical_alerts() {
+        let healthy = snapshot_fixture(0, connectivity(1000, 1000, 0), 0.0);
+        assert_eq!(healthy.critical_alert_count(), 0);
+        assert_eq!(healthy.overall_status(), HealthGrade::Excellent);
+
+        // Warnings alone don't override the grade
+        let warned = snapshot_fixture(0, connectivity(10, 9, 1), 600.0);
+        assert_eq!(warned.alerts().len(), 2);
+        assert_eq!(warned.critical_alert_count(), 0);
+        assert_eq!(warned.overall_status(), warned.fleet_health().grade());
+        assert_eq!(warned.overall_status(), HealthGrade::Good);
+    }
+
+    #[test]
+    fn overall_status_is_critical_with_a_critical_alert_despite_a_high_score() {
+        let snapshot = snapshot_fixture(1, connectivity(1000, 1000, 0), 0.0);
+        assert_eq!(snapshot.fleet_health().grade(), HealthGrade::Excellent);
+        assert_eq!(snapshot.critical_alert_count(), 1);
+        assert_eq!(snapshot.overall_status(), HealthGrade::Critical);
+
+        let slow = snapshot_fixture(0, connectivity(1000, 1000, 0), 1000.5);
+        assert_eq!(slow.critical_alert_count(), 1);
+        assert_eq!(slow.overall_status(), HealthGrade::Critical);
+
+        let both = snapshot_fixture(2, connectivity(1000, 1000, 0), 1500.0);
+        assert_eq!(both.critical_alert_count(), 2);
+        assert_eq!(both.overall_status(), HealthGrade::Critical);
+    }
+
+    /// Value of the `limit` query parameter sent with each recorded request
+    fn sent_limits(server: &TestServer) -> Vec<String> {
+        server
+            .requests()
+            .iter()
+            .filter_map(|request| {
+                let (_, query) = request.target.split_once('?')?;
+                query
+                    .split('&')
+                    .find_map(|pair| pair.strip_prefix("limit="))
+                    .map(str::to_string)
+            })
+            .collect()
+    }
+
+    #[tokio::test]
+    async fn anomaly_limit_defaults_and_is_clamped_in_the_query() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        for query in [
+            AnomalyQuery::new(),
+            AnomalyQuery::new().limit(250),
+            AnomalyQuery::new().limit(MAX_LIMIT),
+            AnomalyQuery::new().limit(MAX_LIMIT + 1),
+            AnomalyQuery::new().limit(100_000),
+        ] {
+            client.get_anomalies(query).await.unwrap();
+        }
+
+        let max = MAX_LIMIT.to_string();
+        assert_eq!(
+            sent_limits(&server),
+            [DEFAULT_LIMIT.to_string().as_str(), "250", &max, &max, &max]
+        );
+    }
+
+    #[test]
+    fn outlier_reason_classifies_known_names_and_aliases() {
+        let cases = [
+            ("high_variance", OutlierReason::HighVariance),
+            ("High Variance", OutlierReason::HighVariance),
+            (" high-variance ", OutlierReason::HighVariance),
+            ("variance", OutlierReason::HighVariance),
+            ("spike", OutlierReason::Spike),
+            ("SPIKES", OutlierReason::Spike),
+            ("flatline", OutlierReason::Flatline),
+            ("flat-line", OutlierReason::Flatline),
+            ("Flat line", OutlierReason::Flatline),
+            ("flat", OutlierReason::Flatline),
+        ];
+        for (raw, expected) in cases {
+            assert_eq!(OutlierReason::classify(raw), expected, "{:?}", raw);
+        }
+    }
+
+    #[test]
+    fn outlier_reason_needs_an_exact_match() {
+        for raw in [
+            "low_variance",
+            "no_spike",
+            "variance spike",
+            "not flatline",
+            "Sudden spike in temperature",
+            "",
+        ] {
+            assert_eq!(
+                OutlierReason::classify(raw),
+                OutlierReason::Other(raw.to_string()),
+                "{:?}",
+                raw
+            );
+        }
+    }
+
+    #[test]
+    fn outlier_reason_round_trips_through_serde() {
+        for raw in [
+            "low_variance",
+            "Sudden Spike in temperature",
+            " odd  spacing ",
+        ] {
+            let reason: OutlierReason = serde_json::from_value(serde_json::json!(raw)).unwrap();
+            assert_eq!(reason, OutlierReason::Other(raw.to_string()));
+            assert_eq!(
+                serde_json::to_value(&reason).unwrap(),
+                serde_json::json!(raw)
+            );
+            assert_eq!(reason.to_string(), raw);
+        }
+
+        let reason: OutlierReason =
+            serde_json::from_value(serde_json::json!("High variance")).unwrap();
+        assert_eq!(reason, OutlierReason::HighVariance);
+        assert_eq!(
+            serde_json::to_value(&reason).unwrap(),
+            serde_json::json!("high_variance")
+        );
+    }
+
+    #[test]
+    fn outlier_reason_category_keeps_the_raw_reason() {
+        let outlier: Outlier = serde_json::from_value(
+            serde_json::json!({"device_id": "d1", "outlier_score": 3.2, "reason": "Flat line"}),
+        )
+        .unwrap();
+        assert_eq!(outlier.reason_category(), Some(OutlierReason::Flatline));
+        assert_eq!(outlier.reason.as_deref(), Some("Flat line"));
+
+        let blank = Outlier {
+            reason: Some("  ".to_string()),
+            ..outlier.clone()
+        };
+        assert_eq!(blank.reason_category(), None);
+        let missing = Outlier {
+            reason: None,
+            ..outlier
+        };
+        assert_eq!(missing.reason_category(), None);
+    }
+
+    #[tokio::test]
+    async fn anomaly_window_and_device_filters_are_sent() {
+        let server = TestServer::start(vec![Reply::json(200, EMPTY_ANOMALIES)]).await;
+        let client = AnalyticsClient::new(&server.url(), "token").unwrap();
+
+        client
+            .get_anomalies(
+                AnomalyQuery::new()
+                    .hours(6)
+                    .device_ids(["sensor-001", "gateway 2"]),
+            )
+            .await
+            .unwrap();
+        client.get_anomalies(AnomalyQuery::new()).await.unwrap();
+
+        let requests = server.requests();
+        assert!(requests[0].target.contains("hours=6"));
+        assert!(requests[0].target.contains("device_id=sensor-001"));
+        assert!(requests[0].target.contains("device_id=gateway+2"));
+        assert!(!requests[1].target.contains("hours="));
+        assert!(!requests[1].target.contains("device_id="));
+    }
+
+    /// Client with caching on, in front of a server that answers every
+    /// request with 204, which typed reads map to an empty response
+    async fn cached_client(config: CacheConfig) -> (TestServer, AnalyticsClient) {
+        let server = TestServer::start(vec![Reply::json(204, "")]).await;
+        let client = AnalyticsClient::builder(&server.url(), "token")
+            .cache(config)
+            .build()
+            .unwrap();
+        (server, client)
+    }
+
+    /// Requests whose target starts with `/api{path}`
+    fn requests_to(server: &TestServer, path: &str) -> usize {
+        let prefix = format!("/api{}", path);
+        server
+            .requests()
+            .iter()
+            .filter(|request| request.target.starts_with(&prefix))
+            .count()
+    }
+
+    #[tokio::test]
+    async fn cache_serves_repeated_calls_until_the_ttl() {
+        let config = CacheConfig::default().with_ttl(
+            "get_connection_quality",
+            std::time::Duration::from_millis(100),
+        );
+        let (server, client) = cached_client(config).await;
+
+        client.get_connection_quality().await.unwrap();
+        client.get_connection_quality().await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 1);
+
+        // Different arguments are a different entry
+        client.get_latency_stats(1, None).await.unwrap();
+        client.get_latency_stats(1, None).await.unwrap();
+        client.get_latency_stats(2, None).await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/latency"), 2);
+
+        tokio::time::sleep(std::time::Duration::from_millis(150)).await;
+        client.get_connection_quality().await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 2);
+    }
+
+    #[tokio::test]
+    async fn zero_ttl_disables_caching_for_that_method() {
+        let config =
+            CacheConfig::default().with_ttl("get_connection_quality", std::time::Duration::ZERO);
+        let (server, client) = cached_client(config).await;
+
+        for _ in 0..3 {
+            client.get_connection_quality().await.unwrap();
+            client.get_latency_stats(1, None).await.unwrap();
+        }
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 3);
+        assert_eq!(requests_to(&server, "/connectivity/latency"), 1);
+    }
+
+    #[tokio::test]
+    async fn cache_evicts_the_entry_closest_to_expiry() {
+        let config = CacheConfig {
+            max_entries: 2,
+            ..Default::default()
+        }
+        .with_ttl("get_latency_stats", std::time::Duration::from_secs(10));
+        let (server, client) = cached_client(config).await;
+
+        client.get_latency_stats(1, None).await.unwrap();
+        client.get_connection_quality().await.unwrap();
+        // Full: the latency entry expires first, so it makes room
+        client.get_throughput_stats(1, None).await.unwrap();
+
+        client.get_connection_quality().await.unwrap();
+        client.get_throughput_stats(1, None).await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 1);
+        assert_eq!(requests_to(&server, "/connectivity/throughput"), 1);
+
+        client.get_latency_stats(1, None).await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/latency"), 2);
+    }
+
+    #[tokio::test]
+    async fn mutating_calls_clear_the_cache() {
+        let (server, client) = cached_client(CacheConfig::default()).await;
+
+        client.get_connection_quality().await.unwrap();
+        // The 204 has no JSON for post_raw to return, but the cache is
+        // cleared whether or not the call succeeds
+        client
+            .post_raw("/forecast/run", &serde_json::json!({}))
+            .await
+            .unwrap_err();
+        client.get_connection_quality().await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 2);
+
+        client
+            .submit_insight_feedback("i1", true, None)
+            .await
+            .unwrap();
+        client.get_connection_quality().await.unwrap();
+        client.get_connection_quality().await.unwrap();
+        assert_eq!(requests_to(&server, "/connectivity/quality"), 3);
     }
 }
@@ -0,0 +1,281 @@
+//! In-memory [`AnalyticsApi`] implementation for tests (requires `test-util`)
+//!
+//! Set canned responses for the calls your code makes; any call without one
+//! fails with [`AnalyticsError::Config`] naming the missing response.
+
+use std::collections::HashMap;
+use std::sync::Mutex;
+
+use async_trait::async_trait;
+
+use crate::api::AnalyticsApi;
+use crate::{
+    AnalyticsError, AnomaliesResponse, Anomaly, AnomalyQuery, Cluster, ClusterAlgorithm,
+    ClustersResponse, ConnectionStatus, ConnectivityResponse, DeviceHealth, InsightsResponse,
+    LatencyResponse, Outlier, QualityResponse, Result, ThroughputResponse, TimeRange,
+    TimelineResponse,
+};
+
+#[derive(Debug, Default)]
+struct Canned {
+    anomalies: Option<AnomaliesResponse>,
+    timeline: Option<TimelineResponse>,
+    clusters: Option<ClustersResponse>,
+    insights: Option<InsightsResponse>,
+    insights_timeline: Option<TimelineResponse>,
+    connectivity: Option<ConnectivityResponse>,
+    latency: Option<LatencyResponse>,
+    throughput: Option<ThroughputResponse>,
+    quality: Option<QualityResponse>,
+    device_health: HashMap<String, DeviceHealth>,
+    raw: HashMap<String, serde_json::Value>,
+    feedback: Vec<InsightFeedback>,
+}
+
+/// Feedback recorded by [`MockAnalyticsClient::submit_insight_feedback`]
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InsightFeedback {
+    pub insight_id: String,
+    pub useful: bool,
+    pub note: Option<String>,
+}
+
+/// Fake client that returns canned responses instead of calling the API.
+///
+/// Filters and paging arguments are ignored; every call returns the response
+/// set for that endpoint. Single-record lookups (`get_anomaly`,
+/// `get_device_cluster`, `get_outliers`) are answered from the canned
+/// anomalies and clusters. `get_raw` and `post_raw` return the JSON set for
+/// their path with [`set_raw`](Self::set_raw).
+#[derive(Debug, Default)]
+pub struct MockAnalyticsClient {
+    canned: Mutex<Canned>,
+}
+
+impl MockAnalyticsClient {
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    pub fn set_anomalies(&self, response: AnomaliesResponse) {
+        self.canned().anomalies = Some(response);
+    }
+
+    pub fn set_timeline(&self, response: TimelineResponse) {
+        self.canned().timeline = Some(response);
+    }
+
+    pub fn set_clusters(&self, response: ClustersResponse) {
+        self.canned().clusters = Some(response);
+    }
+
+    pub fn set_insights(&self, response: InsightsResponse) {
+        self.canned().insights = Some(response);
+    }
+
+    pub fn set_insights_timeline(&self, response: TimelineResponse) {
+        self.canned().insights_timeline = Some(response);
+    }
+
+    pub fn set_connectivity(&self, response: ConnectivityResponse) {
+        self.canned().connectivity = Some(response);
+    }
+
+    pub fn set_latency(&self, response: LatencyResponse) {
+        self.canned().latency = Some(response);
+    }
+
+    pub fn set_throughput(&self, response: ThroughputResponse) {
+        self.canned().throughput = Some(response);
+    }
+
+    pub fn set_quality(&self, response: QualityResponse) {
+        self.canned().quality = Some(response);
+    }
+
+    pub fn set_device_health(&self, health: DeviceHealth) {
+        self.canned()
+            .device_health
+            .insert(health.device_id.clone(), health);
+    }
+
+    /// JSON returned by `get_raw` and `post_raw` for `path`
+    pub fn set_raw(&self, path: &str, response: serde_json::Value) {
+        self.canned().raw.insert(path.to_string(), response);
+    }
+
+    /// Feedback submitted so far, oldest first
+    pub fn feedback(&self) -> Vec<InsightFeedback> {
+        self.canned().feedback.clone()
+    }
+
+    fn canned(&self) -> std::sync::MutexGuard<'_, Canned> {
+        self.canned
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    fn raw(&self, name: &str, path: &str) -> Result<serde_json::Value> {
+        self.canned()
+            .raw
+            .get(path)
+            .cloned()
+            .ok_or_else(|| missing(&format!("{} {}", name, path)))
+    }
+
+    fn take<T: Clone>(&self, name: &str, pick: impl Fn(&Canned) -> &Option<T>) -> Result<T> {
+        pick(&self.canned()).clone().ok_or_else(|| missing(name))
+    }
+}
+
+fn missing(name: &str) -> AnalyticsError {
+    AnalyticsError::Config(format!(
+        "MockAnalyticsClient: no canned response for {}",
+        name
+    ))
+}
+
+fn not_found(what: &str) -> AnalyticsError {
+    AnalyticsError::Api {
+        status: 404,
+        message: format!("{} not found", what),
+    }
+}
+
+#[cfg_attr(not(target_arch = "wasm32"), async_trait)]
+#[cfg_attr(target_arch = "wasm32", async_trait(?Send))]
+impl AnalyticsApi for MockAnalyticsClient {
+    async fn get_anomalies(&self, _query: AnomalyQuery) -> Result<AnomaliesResponse> {
+        self.take("get_anomalies", |c| &c.anomalies)
+    }
+
+    async fn get_anomaly(&self, id: &str) -> Result<Anomaly> {
+        let anomalies = self.take("get_anomaly", |c| &c.anomalies)?;
+        anomalies
+            .anomalies
+            .into_iter()
+            .find(|a| a.id == id)
+            .ok_or_else(|| not_found(&format!("anomaly {}", id)))
+    }
+
+    async fn get_anomaly_timeline(&self, _days: i64, _group_by: &str) -> Result<TimelineResponse> {
+        self.take("get_anomaly_timeline", |c| &c.timeline)
+    }
+
+    async fn get_anomaly_timeline_range(
+        &self,
+        _range: TimeRange,
+        _group_by: &str,
+    ) -> Result<TimelineResponse> {
+        self.take("get_anomaly_timeline_range", |c| &c.timeline)
+    }
+
+    async fn get_clusters_with(
+        &self,
+        _metric_name: &str,
+        _algorithm: ClusterAlgorithm,
+        _time_range: Option<TimeRange>,
+        _include_outliers: bool,
+    ) -> Result<ClustersResponse> {
+        self.take("get_clusters", |c| &c.clusters)
+    }
+
+    async fn get_device_cluster(
+        &self,
+        _metric_name: &str,
+        device_id: &str,
+    ) -> Result<Option<Cluster>> {
+        let clusters = self.take("get_device_cluster", |c| &c.clusters)?;
+        Ok(clusters.cluster_for_device(device_id).cloned())
+    }
+
+    async fn get_outliers(
+        &self,
+        _metric_name: &str,
+        _time_range: Option<TimeRange>,
+        threshold: f64,
+    ) -> Result<Vec<Outlier>> {
+        let clusters = self.take("get_outliers", |c| &c.clusters)?;
+        Ok(clusters
+            .outliers
+            .into_iter()
+            .filter(|o| o.outlier_score >= threshold)
+            .collect())
+    }
+
+    async fn get_insights(
+        &self,
+        _days: i64,
+        _insight_types: Option<Vec<&str>>,
+        _min_confidence: f64,
+    ) -> Result<InsightsResponse> {
+        self.take("get_insights", |c| &c.insights)
+    }
+
+    async fn get_insights_timeline(&self, _days: i64, _group_by: &str) -> Result<TimelineResponse> {
+        self.take("get_insights_timeline", |c| &c.insights_timeline)
+    }
+
+    async fn submit_insight_feedback(
+        &self,
+        insight_id: &str,
+        useful: bool,
+        note: Option<&str>,
+    ) -> Result<()> {
+        self.canned().feedback.push(InsightFeedback {
+            insight_id: insight_id.to_string(),
+            useful,
+            note: note.map(str::to_string),
+        });
+        Ok(())
+    }
+
+    async fn get_connectivity_status(
+        &self,
+        _status_filter: Option<ConnectionStatus>,
+        _device_ids: Option<Vec<&str>>,
+        _name_contains: Option<&str>,
+    ) -> Result<ConnectivityResponse> {
+        self.take("get_connectivity_status", |c| &c.connectivity)
+    }
+
+    async fn get_latency_stats(
+        &self,
+        _hours: i64,
+        _device_ids: Option<Vec<&str>>,
+    ) -> Result<LatencyResponse> {
+        self.take("get_latency_stats", |c| &c.latency)
+    }
+
+    async fn get_throughput_stats(
+        &self,
+        _hours: i64,
+        _device_ids: Option<Vec<&str>>,
+    ) -> Result<ThroughputResponse> {
+        self.take("get_throughput_stats", |c| &c.throughput)
+    }
+
+    async fn get_connection_quality(&self) -> Result<QualityResponse> {
+        self.take("get_connection_quality", |c| &c.quality)
+    }
+
+    async fn get_device_health(
+        &self,
+        device_id: &str,
+        _time_range: Option<TimeRange>,
+    ) -> Result<DeviceHealth> {
+        self.canned()
+            .device_health
+            .get(device_id)
+            .cloned()
+            .ok_or_else(|| not_found(&format!("device {}", device_id)))
+    }
+
+    async fn get_raw(&self, path: &str, _params: &[(&str, String)]) -> Result<serde_json::Value> {
+        self.raw("get_raw", path)
+    }
+
+    async fn post_raw(&self, path: &str, _body: &serde_json::Value) -> Result<serde_json::Value> {
+        self.raw("post_raw", path)
+    }
+}
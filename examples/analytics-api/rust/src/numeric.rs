@@ -0,0 +1,210 @@
+//! Lenient number parsing for summary fields
+//!
+//! Some backend deployments encode counts and averages as strings, e.g.
+//! `"total": "42"`. These `deserialize_with` helpers accept either a JSON
+//! number or a string holding one; serialization still emits plain numbers.
+
+use std::collections::HashMap;
+use std::fmt;
+
+use serde::de::{self, Deserializer, Visitor};
+use serde::Deserialize;
+
+/// `i64` from a number or numeric string; integral floats such as `42.0` are
+/// accepted too
+pub(crate) fn int<'de, D: Deserializer<'de>>(deserializer: D) -> Result<i64, D::Error> {
+    Int::deserialize(deserializer).map(|Int(value)| value)
+}
+
+/// `f64` from a number or numeric string; `"NaN"` and infinities are rejected
+pub(crate) fn float<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f64, D::Error> {
+    Float::deserialize(deserializer).map(|Float(value)| value)
+}
+
+/// Map of counts whose values may each be a number or numeric string
+pub(crate) fn int_map<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<HashMap<String, i64>, D::Error> {
+    let map = HashMap::<String, Int>::deserialize(deserializer)?;
+    Ok(map
+        .into_iter()
+        .map(|(key, Int(value))| (key, value))
+        .collect())
+}
+
+struct Int(i64);
+
+impl<'de> Deserialize<'de> for Int {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(IntVisitor).map(Int)
+    }
+}
+
+struct IntVisitor;
+
+impl Visitor<'_> for IntVisitor {
+    type Value = i64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("an integer or a string containing one")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<i64, E> {
+        Ok(value)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<i64, E> {
+        i64::try_from(value).map_err(|_| E::invalid_value(de::Unexpected::Unsigned(value), &self))
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<i64, E> {
+        if value.fract() == 0.0 && value.abs() < i64::MAX as f64 {
+            Ok(value as i64)
+        } else {
+            Err(E::invalid_value(de::Unexpected::Float(value), &self))
+        }
+    }
+
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<i64, E> {
+        let trimmed = value.trim();
+        match trimmed.parse::<i64>() {
+            Ok(parsed) => Ok(parsed),
+            Err(_) => match trimmed.parse::<f64>() {
+                Ok(parsed) => self.visit_f64(parsed),
+                Err(_) => Err(E::invalid_value(de::Unexpected::Str(value), &self)),
+            },
+        }
+    }
+}
+
+struct Float(f64);
+
+impl<'de> Deserialize<'de> for Float {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        deserializer.deserialize_any(FloatVisitor).map(Float)
+    }
+}
+
+struct FloatVisitor;
+
+impl Visitor<'_> for FloatVisitor {
+    type Value = f64;
+
+    fn expecting(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("a number or a string containing one")
+    }
+
+    fn visit_i64<E: de::Error>(self, value: i64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_u64<E: de::Error>(self, value: u64) -> Result<f64, E> {
+        Ok(value as f64)
+    }
+
+    fn visit_f64<E: de::Error>(self, value: f64) -> Result<f64, E> {
+        Ok(value)
+    }
+
+    /// `NaN` and `inf` parse as `f64` but are never valid JSON numbers, so
+    /// only finite values are accepted
+    fn visit_str<E: de::Error>(self, value: &str) -> Result<f64, E> {
+        value
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|parsed| parsed.is_finite())
+            .ok_or_else(|| E::invalid_value(de::Unexpected::Str(value), &self))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    use crate::{AnomalySummary, FleetSummary};
+
+    #[derive(Debug, Deserialize)]
+    struct Fields {
+        #[serde(deserialize_with = "int")]
+        int: i64,
+        #[serde(deserialize_with = "float")]
+        float: f64,
+    }
+
+    fn parse(int: &str, float: &str) -> Result<Fields, serde_json::Error> {
+        serde_json::from_str(&format!(r#"{{"int": {}, "float": {}}}"#, int, float))
+    }
+
+    #[test]
+    fn numbers_and_numeric_strings_parse_alike() {
+        let cases = [
+            ("42", "12.5"),
+            (r#""42""#, r#""12.5""#),
+            (r#"" 42 ""#, r#"" 12.5 ""#),
+            ("42.0", r#""1.25e1""#),
+            (r#""42.0""#, "12.5"),
+        ];
+        for (int, float) in cases {
+            let fields = parse(int, float).unwrap();
+            assert_eq!((fields.int, fields.float), (42, 12.5), "{} {}", int, float);
+        }
+        let fields = parse(r#"" 3 ""#, r#"" 3 ""#).unwrap();
+        assert_eq!((fields.int, fields.float), (3, 3.0));
+    }
+
+    #[test]
+    fn non_numeric_strings_are_rejected() {
+        for value in [
+            r#""""#,
+            r#"" ""#,
+            r#""NaN""#,
+            r#""inf""#,
+            r#""-infinity""#,
+            r#""12abc""#,
+            r#""forty""#,
+            "null",
+            "true",
+        ] {
+            assert!(parse("1", value).is_err(), "float {}", value);
+            assert!(parse(value, "1").is_err(), "int {}", value);
+        }
+    }
+
+    #[test]
+    fn fractional_and_out_of_range_ints_are_rejected() {
+        for value in ["12.5", r#""12.5""#, "18446744073709551615", "1e300"] {
+            assert!(parse(value, "1").is_err(), "{}", value);
+        }
+        assert_eq!(parse("-7", "1").unwrap().int, -7);
+    }
+
+    #[test]
+    fn summaries_accept_string_encoded_fields() {
+        let numeric: AnomalySummary =
+            serde_json::from_str(r#"{"total": 42, "by_severity": {"critical": 2, "low": 40}}"#)
+                .unwrap();
+        let strings: AnomalySummary = serde_json::from_str(
+            r#"{"total": "42", "by_severity": {"critical": "2", "low": " 40 "}}"#,
+        )
+        .unwrap();
+        assert_eq!(strings.total, numeric.total);
+        assert_eq!(strings.by_severity, numeric.by_severity);
+
+        let fleet: FleetSummary = serde_json::from_str(
+            r#"{"total_devices": "120", "active_devices": 110,
+                "anomaly_rate": "0.05", "health_score": "87.5"}"#,
+        )
+        .unwrap();
+        assert_eq!(fleet.total_devices, 120);
+        assert_eq!(fleet.active_devices, 110);
+        assert_eq!(fleet.anomaly_rate, 0.05);
+        assert_eq!(fleet.health_score, 87.5);
+
+        // Serialization still emits plain numbers
+        let json = serde_json::to_value(&strings).unwrap();
+        assert_eq!(json["total"], 42);
+        assert_eq!(json["by_severity"]["low"], 40);
+        assert_eq!(serde_json::to_value(&fleet).unwrap()["health_score"], 87.5);
+    }
+}
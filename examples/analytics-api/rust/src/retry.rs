@@ -0,0 +1,48 @@
+//! Retries for transient failures
+//!
+//! Transport errors, timeouts, 429 and 5xx responses are retried with
+//! exponential backoff. Anything else (4xx, an open circuit, bad JSON) is
+//! returned straight away since sending the request again won't change it.
+
+use std::time::Duration;
+
+use crate::AnalyticsError;
+
+/// Settings for [`AnalyticsClientBuilder::retry`](crate::AnalyticsClientBuilder::retry)
+#[derive(Debug, Clone)]
+pub struct RetryConfig {
+    /// Attempts after the first one
+    pub max_retries: u32,
+    /// Delay before the first retry; doubles for each retry after that
+    pub base_delay: Duration,
+    /// Upper bound on a single delay
+    pub max_delay: Duration,
+}
+
+impl Default for RetryConfig {
+    fn default() -> Self {
+        Self {
+            max_retries: 2,
+            base_delay: Duration::from_millis(200),
+            max_delay: Duration::from_secs(5),
+        }
+    }
+}
+
+impl RetryConfig {
+    /// Backoff before retry number `retry` (0 for the first retry)
+    pub(crate) fn delay(&self, retry: u32) -> Duration {
+        self.base_delay
+            .saturating_mul(2u32.saturating_pow(retry))
+            .min(self.max_delay)
+    }
+}
+
+/// Whether sending the request again might succeed
+pub(crate) fn is_retryable(err: &AnalyticsError) -> bool {
+    match err {
+        AnalyticsError::Http(_) | AnalyticsError::Timeout { .. } => true,
+        AnalyticsError::Api { status, .. } => *status == 429 || *status >= 500,
+        _ => false,
+    }
+}
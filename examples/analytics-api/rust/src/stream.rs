@@ -0,0 +1,361 @@
+//! Incremental parsing of one array field in a JSON response
+//!
+//! A body like `{"anomalies": [...], "summary": {...}}` is read chunk by
+//! chunk. Each element of the named array is deserialized as soon as its bytes
+//! have arrived and then dropped from the buffer, so memory is bounded by the
+//! largest single element instead of the whole body. Other fields are skipped.
+
+use std::marker::PhantomData;
+
+use futures_util::{Stream, StreamExt};
+use serde::de::{DeserializeOwned, Error as _};
+
+use crate::{map_reqwest_error, Instant, Result};
+
+/// Yield the elements of `field` from a streamed JSON object body
+pub(crate) fn array_field<T, S, B>(
+    body: S,
+    field: &'static str,
+    started: Instant,
+) -> impl Stream<Item = Result<T>>
+where
+    T: DeserializeOwned,
+    S: Stream<Item = reqwest::Result<B>>,
+    B: AsRef<[u8]>,
+{
+    let state = (Box::pin(body), ArrayFieldReader::<T>::new(field), false);
+    futures_util::stream::unfold(state, move |(mut body, mut reader, mut eof)| async move {
+        loop {
+            match reader.next_step(eof) {
+                Ok(Step::Item(item)) => return Some((Ok(item), (body, reader, eof))),
+                Ok(Step::End) => return None,
+                Ok(Step::NeedMore) => match body.next().await {
+                    Some(Ok(chunk)) => reader.feed(chunk.as_ref()),
+                    Some(Err(e)) => {
+                        reader.state = State::Done;
+                        return Some((Err(map_reqwest_error(e, started)), (body, reader, eof)));
+                    }
+                    None => eof = true,
+                },
+                Err(e) => {
+                    reader.state = State::Done;
+                    return Some((Err(e), (body, reader, eof)));
+                }
+            }
+        }
+    })
+}
+
+/// Outcome of one parsing step
+enum Step<T> {
+    Item(T),
+    NeedMore,
+    End,
+}
+
+/// Where the reader is in the top-level object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    /// Expecting `{`
+    Start,
+    /// After `{`: expecting a key or `}`
+    FirstKey,
+    /// After `,`: expecting a key
+    Key,
+    /// After a key: expecting `:`
+    Colon { target: bool },
+    /// After `:`: expecting the field's value
+    Value { target: bool },
+    /// Inside a field value that is being skipped
+    SkipValue,
+    /// After `[` of the target array: expecting an element or `]`
+    FirstElement,
+    /// After `,` inside the target array: expecting an element
+    Element,
+    /// After an element: expecting `,` or `]`
+    AfterElement,
+    /// After a field's value: expecting `,` or `}`
+    AfterValue,
+    /// After the closing `}` (or a fatal error)
+    Done,
+}
+
+struct ArrayFieldReader<T> {
+    field: &'static str,
+    buf: Vec<u8>,
+    state: State,
+    /// Progress through the value at the start of `buf`
+    scan: Scan,
+    _item: PhantomData<fn() -> T>,
+}
+
+impl<T: DeserializeOwned> ArrayFieldReader<T> {
+    fn new(field: &'static str) -> Self {
+        Self {
+            field,
+            buf: Vec::new(),
+            state: State::Start,
+            scan: Scan::default(),
+            _item: PhantomData,
+        }
+    }
+
+    fn feed(&mut self, chunk: &[u8]) {
+        self.buf.extend_from_slice(chunk);
+    }
+
+    /// Advance as far as the buffered bytes allow. `eof` means no more bytes
+    /// will arrive, so an incomplete value is an error rather than `NeedMore`.
+    fn next_step(&mut self, eof: bool) -> Result<Step<T>> {
+        loop {
+            let skipped = self
+                .buf
+                .iter()
+                .take_while(|b| b.is_ascii_whitespace())
+                .count();
+            self.buf.drain(..skipped);
+
+            if self.state == State::Done {
+                if self.buf.is_empty() {
+                    return Ok(Step::End);
+                }
+                return Err(invalid("trailing data after the response object"));
+            }
+            let Some(&next) = self.buf.first() else {
+                // An empty body (e.g. 204 No Content) has no elements
+                if eof && self.state == State::Start {
+                    return Ok(Step::End);
+                }
+                return if eof {
+                    Err(invalid("response body ended early"))
+                } else {
+                    Ok(Step::NeedMore)
+                };
+            };
+
+            match (self.state, next) {
+                (State::Start, b'{') => self.advance(1, State::FirstKey),
+                (State::FirstKey, b'}') | (State::AfterValue, b'}') => self.advance(1, State::Done),
+                (State::FirstKey | State::Key, b'"') => {
+                    let Some(len) = self.scan.resume(&self.buf, eof)? else {
+                        return Ok(Step::NeedMore);
+                    };
+                    let key: String = serde_json::from_slice(&self.buf[..len])?;
+                    let target = key == self.field;
+                    self.advance(len, State::Colon { target });
+                }
+                (State::Colon { target }, b':') => self.advance(1, State::Value { target }),
+                (State::Value { target: true }, b'[') => self.advance(1, State::FirstElement),
+                (State::Value { .. }, _) => self.state = State::SkipValue,
+                (State::SkipValue, _) => {
+                    let done = self.scan.resume(&self.buf, eof)?;
+                    // Skipped bytes are never read again, so drop them now
+                    // rather than holding a large unrelated field in memory
+                    let scanned = done.unwrap_or(self.scan.pos);
+                    self.buf.drain(..scanned);
+                    self.scan.pos = 0;
+                    if done.is_none() {
+                        return Ok(Step::NeedMore);
+                    }
+                    self.advance(0, State::AfterValue);
+                }
+                (State::FirstElement, b']') | (State::AfterElement, b']') => {
+                    self.advance(1, State::AfterValue)
+                }
+                (State::FirstElement | State::Element, _) => {
+                    let Some(len) = self.scan.resume(&self.buf, eof)? else {
+                        return Ok(Step::NeedMore);
+                    };
+                    let item = serde_json::from_slice(&self.buf[..len])?;
+                    self.advance(len, State::AfterElement);
+                    return Ok(Step::Item(item));
+                }
+                (State::AfterElement, b',') => self.advance(1, State::Element),
+                (State::AfterValue, b',') => self.advance(1, State::Key),
+                (state, byte) => {
+                    return Err(invalid(&format!(
+                        "unexpected '{}' while parsing the response ({:?})",
+                        byte as char, state
+                    )))
+                }
+            }
+        }
+    }
+
+    fn advance(&mut self, len: usize, state: State) {
+        self.buf.drain(..len);
+        self.state = state;
+        self.scan = Scan::default();
+    }
+}
+
+/// Scanner that finds the end of the JSON value at the start of a buffer.
+///
+/// State is kept between calls, so when a value spans several chunks each
+/// byte is only looked at once. It only delimits the value; `serde_json`
+/// validates it.
+#[derive(Debug, Default)]
+struct Scan {
+    /// Bytes of the value scanned so far
+    pos: usize,
+    started: bool,
+    /// Number, `true`, `false` or `null`: runs until the next delimiter
+    scalar: bool,
+    depth: usize,
+    in_string: bool,
+    escaped: bool,
+}
+
+impl Scan {
+    /// Continue from `pos`, returning the value's length once it is complete
+    /// or `None` if more bytes are needed
+    fn resume(&mut self, buf: &[u8], eof: bool) -> Result<Option<usize>> {
+        if !self.started {
+            match buf.first() {
+                Some(b'"') | Some(b'{') | Some(b'[') => {}
+                Some(_) => self.scalar = true,
+                None => return incomplete(eof),
+            }
+            self.started = true;
+        }
+
+        while let Some(&b) = buf.get(self.pos) {
+            if self.scalar {
+                if matches!(b, b',' | b'}' | b']') || b.is_ascii_whitespace() {
+                    return Ok(Some(self.pos));
+                }
+                self.pos += 1;
+                continue;
+            }
+
+            self.pos += 1;
+            if self.in_string {
+                if self.escaped {
+                    self.escaped = false;
+                } else if b == b'\\' {
+                    self.escaped = true;
+                } else if b == b'"' {
+                    self.in_string = false;
+                    if self.depth == 0 {
+                        return Ok(Some(self.pos));
+                    }
+                }
+                continue;
+            }
+            match b {
+                b'"' => self.in_string = true,
+                b'{' | b'[' => self.depth += 1,
+                b'}' | b']' => {
+                    self.depth -= 1;
+                    if self.depth == 0 {
+                        return Ok(Some(self.pos));
+                    }
+                }
+                _ => {}
+            }
+        }
+
+        if self.scalar && eof {
+            return Ok(Some(self.pos));
+        }
+        incomplete(eof)
+    }
+}
+
+/// `None` (need more bytes) unless the body has ended
+fn incomplete(eof: bool) -> Result<Option<usize>> {
+    if eof {
+        Err(invalid("response body ended early"))
+    } else {
+        Ok(None)
+    }
+}
+
+fn invalid(message: &str) -> crate::AnalyticsError {
+    serde_json::Error::custom(message).into()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Feed `body` in chunks of `size` bytes, collecting items until the end
+    fn read_all(body: &str, size: usize) -> Result<Vec<serde_json::Value>> {
+        let mut reader = ArrayFieldReader::new("anomalies");
+        let mut chunks = body.as_bytes().chunks(size);
+        let mut items = Vec::new();
+        let mut eof = false;
+        loop {
+            match reader.next_step(eof)? {
+                Step::Item(item) => items.push(item),
+                Step::End => return Ok(items),
+                Step::NeedMore => match chunks.next() {
+                    Some(chunk) => reader.feed(chunk),
+                    None => eof = true,
+                },
+            }
+        }
+    }
+
+    const BODY: &str = r#"{"summary": {"total": 3, "note": "a \"quoted\" ] }"},
+        "anomalies": [{"id": "a1", "tags": ["x", {"y": "}"}]}, 42, "s\\"],
+        "after": [true, null]}"#;
+
+    #[test]
+    fn same_items_for_any_chunk_size() {
+        let expected = read_all(BODY, BODY.len()).unwrap();
+        assert_eq!(expected.len(), 3);
+        assert_eq!(expected[0]["id"], "a1");
+        assert_eq!(expected[1], 42);
+        assert_eq!(expected[2], "s\\");
+
+        for size in 1..16 {
+            assert_eq!(
+                read_all(BODY, size).unwrap(),
+                expected,
+                "chunk size {}",
+                size
+            );
+        }
+    }
+
+    #[test]
+    fn skipped_fields_are_not_buffered() {
+        let mut reader = ArrayFieldReader::<serde_json::Value>::new("anomalies");
+        reader.feed(br#"{"padding": ""#);
+        for _ in 0..1000 {
+            reader.feed(&[b'x'; 64]);
+            assert!(matches!(reader.next_step(false), Ok(Step::NeedMore)));
+            assert!(
+                reader.buf.len() <= 64,
+                "{} bytes buffered",
+                reader.buf.len()
+            );
+        }
+        reader.feed(br#"", "anomalies": [1]}"#);
+        assert!(matches!(reader.next_step(false), Ok(Step::Item(_))));
+        assert!(matches!(reader.next_step(true), Ok(Step::End)));
+    }
+
+    #[test]
+    fn scan_resumes_where_the_last_chunk_ended() {
+        let mut reader = ArrayFieldReader::<serde_json::Value>::new("anomalies");
+        reader.feed(br#"{"anomalies": [{"id": "#);
+        assert!(matches!(reader.next_step(false), Ok(Step::NeedMore)));
+        assert_eq!(reader.scan.pos, reader.buf.len());
+
+        reader.feed(br#""a1"}]}"#);
+        match reader.next_step(true) {
+            Ok(Step::Item(item)) => assert_eq!(item["id"], "a1"),
+            _ => panic!("expected an item"),
+        }
+    }
+
+    #[test]
+    fn truncated_and_trailing_bodies_are_errors() {
+        assert!(read_all(r#"{"anomalies": [{"id": "a1"#, 4).is_err());
+        assert!(read_all(r#"{"skip": "abc"#, 4).is_err());
+        assert!(read_all(r#"{"anomalies": []} x"#, 4).is_err());
+        assert!(read_all("", 4).unwrap().is_empty());
+    }
+}
@@ -0,0 +1,196 @@
+//! Minimal HTTP/1.1 server for unit tests
+//!
+//! Serves queued replies in order (the last one repeats once the queue is
+//! down to it) and records every request so tests can assert on the query
+//! string, headers, and body that actually went over the wire.
+
+use std::collections::VecDeque;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+
+use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Canned response
+#[derive(Debug, Clone)]
+pub(crate) struct Reply {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+    delay: Duration,
+}
+
+impl Reply {
+    /// JSON body with the given status
+    pub(crate) fn json(status: u16, body: &str) -> Self {
+        Self {
+            status,
+            headers: vec![("content-type".to_string(), "application/json".to_string())],
+            body: body.as_bytes().to_vec(),
+            delay: Duration::ZERO,
+        }
+    }
+
+    /// Replace the body with raw bytes, adding a header that describes them
+    /// (e.g. `content-encoding: gzip`)
+    #[cfg(feature = "compression")]
+    pub(crate) fn encoded(mut self, header: (&str, &str), body: Vec<u8>) -> Self {
+        self.headers
+            .push((header.0.to_string(), header.1.to_string()));
+        self.body = body;
+        self
+    }
+
+    /// Wait this long before sending the response
+    pub(crate) fn delay(mut self, delay: Duration) -> Self {
+        self.delay = delay;
+        self
+    }
+}
+
+/// A request as received by the server
+#[derive(Debug, Clone)]
+pub(crate) struct Recorded {
+    pub(crate) method: String,
+    /// Path and query string, e.g. `/api/anomalies?limit=100`
+    pub(crate) target: String,
+    pub(crate) headers: Vec<(String, String)>,
+}
+
+impl Recorded {
+    /// First value of a header, matched case-insensitively
+    pub(crate) fn header(&self, name: &str) -> Option<&str> {
+        self.headers
+            .iter()
+            .find(|(key, _)| key.eq_ignore_ascii_case(name))
+            .map(|(_, value)| value.as_str())
+    }
+}
+
+#[derive(Debug, Default)]
+struct Shared {
+    replies: Mutex<VecDeque<Reply>>,
+    requests: Mutex<Vec<Recorded>>,
+    connections: AtomicUsize,
+    in_flight: AtomicUsize,
+    max_in_flight: AtomicUsize,
+}
+
+pub(crate) struct TestServer {
+    addr: std::net::SocketAddr,
+    shared: Arc<Shared>,
+}
+
+impl TestServer {
+    /// Start serving `replies` on a random local port
+    pub(crate) async fn start(replies: Vec<Reply>) -> Self {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        let shared = Arc::new(Shared {
+            replies: Mutex::new(replies.into()),
+            ..Default::default()
+        });
+
+        let accept_shared = Arc::clone(&shared);
+        tokio::spawn(async move {
+            while let Ok((stream, _)) = listener.accept().await {
+                accept_shared.connections.fetch_add(1, Ordering::SeqCst);
+                tokio::spawn(serve(stream, Arc::clone(&accept_shared)));
+            }
+        });
+
+        Self { addr, shared }
+    }
+
+    /// Base URL to hand to the client
+    pub(crate) fn url(&self) -> String {
+        format!("http://{}/api", self.addr)
+    }
+
+    pub(crate) fn requests(&self) -> Vec<Recorded> {
+        self.shared.requests.lock().unwrap().clone()
+    }
+
+    /// TCP connections accepted so far
+    pub(crate) fn connections(&self) -> usize {
+        self.shared.connections.load(Ordering::SeqCst)
+    }
+
+    /// Most requests that were being handled at the same time
+    pub(crate) fn max_in_flight(&self) -> usize {
+        self.shared.max_in_flight.load(Ordering::SeqCst)
+    }
+}
+
+async fn serve(stream: TcpStream, shared: Arc<Shared>) {
+    let mut reader = BufReader::new(stream);
+    loop {
+        let Some(request) = read_request(&mut reader).await else {
+            return;
+        };
+        shared.requests.lock().unwrap().push(request);
+        let in_flight = shared.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+        shared.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+
+        let reply = {
+            let mut replies = shared.replies.lock().unwrap();
+            if replies.len() > 1 {
+                replies.pop_front()
+            } else {
+                replies.front().cloned()
+            }
+        }
+        .unwrap_or_else(|| Reply::json(404, "{}"));
+        tokio::time::sleep(reply.delay).await;
+
+        let mut head = format!("HTTP/1.1 {} Test\r\n", reply.status);
+        for (name, value) in &reply.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("content-length: {}\r\n\r\n", reply.body.len()));
+
+        let stream = reader.get_mut();
+        let written = stream.write_all(head.as_bytes()).await.is_ok()
+            && stream.write_all(&reply.body).await.is_ok();
+        shared.in_flight.fetch_sub(1, Ordering::SeqCst);
+        if !written {
+            return;
+        }
+    }
+}
+
+async fn read_request(reader: &mut BufReader<TcpStream>) -> Option<Recorded> {
+    let mut line = String::new();
+    if reader.read_line(&mut line).await.ok()? == 0 {
+        return None;
+    }
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let target = parts.next()?.to_string();
+
+    let mut headers = Vec::new();
+    let mut length = 0;
+    loop {
+        line.clear();
+        reader.read_line(&mut line).await.ok()?;
+        let trimmed = line.trim_end();
+        if trimmed.is_empty() {
+            break;
+        }
+        let (name, value) = trimmed.split_once(':')?;
+        let (name, value) = (name.trim(), value.trim());
+        if name.eq_ignore_ascii_case("content-length") {
+            length = value.parse().ok()?;
+        }
+        headers.push((name.to_string(), value.to_string()));
+    }
+    let mut body = vec![0; length];
+    reader.read_exact(&mut body).await.ok()?;
+
+    Some(Recorded {
+        method,
+        target,
+        headers,
+    })
+}
@@ -0,0 +1,31 @@
+//! Compile check for the summary fields `examples/dashboard.rs` reads
+//!
+//! The dashboard once referenced field names the models no longer had; this
+//! test touches the same fields so a rename fails `cargo test`.
+
+use tesaiot_analytics::{ConnectivityResponse, LatencyResponse, ThroughputResponse};
+
+#[test]
+fn dashboard_summary_fields_exist() {
+    let connectivity: ConnectivityResponse = serde_json::from_str(
+        r#"{"summary": {"total_devices": 10, "online_count": 8, "offline_count": 2},
+            "devices": []}"#,
+    )
+    .unwrap();
+    let latency: LatencyResponse = serde_json::from_str(
+        r#"{"summary": {"overall_avg_ms": 42.5, "overall_p95_ms": 120, "overall_p99_ms": 200}}"#,
+    )
+    .unwrap();
+    let throughput: ThroughputResponse = serde_json::from_str(
+        r#"{"summary": {"total_messages_in": 5000, "avg_messages_per_minute": 3.5}}"#,
+    )
+    .unwrap();
+
+    assert_eq!(connectivity.summary.total_devices, 10);
+    assert_eq!(connectivity.summary.online_count, 8);
+    assert_eq!(connectivity.summary.offline_count, 2);
+    assert_eq!(latency.summary.overall_avg_ms, 42.5);
+    assert_eq!(latency.summary.overall_p95_ms, 120.0);
+    assert_eq!(throughput.summary.total_messages_in, 5000);
+    assert_eq!(throughput.summary.avg_messages_per_minute, 3.5);
+}
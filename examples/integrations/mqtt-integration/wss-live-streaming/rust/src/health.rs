@@ -0,0 +1,129 @@
+//! Readiness probe for orchestrators (`metrics` feature)
+//!
+//! A minimal HTTP server on `MQTT_HEALTH_PORT` that answers every request
+//! with `200 OK` while the client is connected and messages are flowing, and
+//! `503 Service Unavailable` otherwise, so Kubernetes can restart a wedged
+//! client.
+
+use std::io;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::{TcpListener, TcpStream};
+
+/// Pause after a failed `accept()`, so errors such as running out of file
+/// descriptors don't spin the loop
+const ACCEPT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// Connection and message timestamps shared with the probe server
+pub struct Health {
+    max_silence: Duration,
+    state: Mutex<State>,
+}
+
+#[derive(Default)]
+struct State {
+    /// Last ConnAck, cleared when the connection drops
+    last_connack: Option<Instant>,
+    last_message: Option<Instant>,
+}
+
+impl Health {
+    fn new(max_silence: Duration) -> Self {
+        Health {
+            max_silence,
+            state: Mutex::new(State::default()),
+        }
+    }
+
+    pub fn connected(&self) {
+        self.state().last_connack = Some(Instant::now());
+    }
+
+    pub fn disconnected(&self) {
+        self.state().last_connack = None;
+    }
+
+    pub fn message_received(&self) {
+        self.state().last_message = Some(Instant::now());
+    }
+
+    /// Connected, with a message (or the ConnAck itself, right after
+    /// connecting) within the last `max_silence`
+    fn is_ready(&self, state: &State) -> bool {
+        let Some(connack) = state.last_connack else {
+            return false;
+        };
+        let last_activity = state.last_message.map_or(connack, |msg| msg.max(connack));
+        last_activity.elapsed() <= self.max_silence
+    }
+
+    fn state(&self) -> std::sync::MutexGuard<'_, State> {
+        self.state
+            .lock()
+            .unwrap_or_else(|poisoned| poisoned.into_inner())
+    }
+
+    /// Status code and JSON body for a probe request
+    fn probe(&self) -> (&'static str, String) {
+        let state = self.state();
+        let ready = self.is_ready(&state);
+        let age = |at: Option<Instant>| {
+            at.map_or("null".to_string(), |at| at.elapsed().as_secs().to_string())
+        };
+        let body = format!(
+            "{{\"ready\":{},\"last_connack_secs\":{},\"last_message_secs\":{}}}",
+            ready,
+            age(state.last_connack),
+            age(state.last_message)
+        );
+        let status = if ready {
+            "200 OK"
+        } else {
+            "503 Service Unavailable"
+        };
+        (status, body)
+    }
+}
+
+/// Bind the probe server on `port` and serve it from a background task
+pub async fn spawn(port: u16, max_silence: Duration) -> io::Result<Arc<Health>> {
+    let listener = TcpListener::bind(("0.0.0.0", port)).await?;
+    let health = Arc::new(Health::new(max_silence));
+
+    let server_health = Arc::clone(&health);
+    tokio::spawn(async move {
+        loop {
+            match listener.accept().await {
+                Ok((stream, _)) => {
+                    tokio::spawn(respond(stream, Arc::clone(&server_health)));
+                }
+                Err(e) => {
+                    let fields = serde_json::json!({"error": e.to_string()});
+                    crate::log_event("warn", "health_probe_error", fields, || {
+                        eprintln!("⚠️  Health probe accept failed: {}", e)
+                    });
+                    tokio::time::sleep(ACCEPT_RETRY_DELAY).await;
+                }
+            }
+        }
+    });
+    Ok(health)
+}
+
+/// Answer one request; the path and method are ignored
+async fn respond(mut stream: TcpStream, health: Arc<Health>) {
+    // Read (part of) the request so the client sees a normal exchange
+    let mut request = [0u8; 1024];
+    let _ = tokio::time::timeout(Duration::from_secs(2), stream.read(&mut request)).await;
+
+    let (status, body) = health.probe();
+    let response = format!(
+        "HTTP/1.1 {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n{}",
+        status,
+        body.len(),
+        body
+    );
+    let _ = stream.write_all(response.as_bytes()).await;
+    let _ = stream.shutdown().await;
+}
@@ -16,23 +16,60 @@
 //!
 //! See: <https://github.com/tesaiot/developer-hub>
 
-use chrono::Utc;
-use rumqttc::{AsyncClient, Event, Incoming, MqttOptions, QoS, Transport};
-use serde_json::Value;
+#[cfg(feature = "metrics")]
+mod health;
+
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use rumqttc::tokio_rustls::rustls::{ClientConfig, RootCertStore};
+use rumqttc::{
+    AsyncClient, ClientError, ConnectReturnCode, ConnectionError, Event, EventLoop, Incoming,
+    MqttOptions, Outgoing, Publish, QoS, TlsConfiguration, Transport,
+};
+use serde_json::{json, Value};
+use sha2::Sha256;
+use std::collections::{HashMap, HashSet};
 use std::env;
 use std::error::Error;
-use std::time::Duration;
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, BufRead, BufReader, BufWriter, Write};
+use std::sync::{Arc, OnceLock};
+use std::time::{Duration, Instant};
 use tokio::signal;
+use tokio::sync::mpsc::{self, error::SendTimeoutError, error::TrySendError};
+use tokio::task::JoinHandle;
 use url::Url;
 
 /// Application configuration
 struct Config {
     token: String,
     broker_url: String,
-    topic: String,
+    topics: Vec<String>,
     client_id: String,
+    scheme: BrokerScheme,
     host: String,
     port: u16,
+    device_filter: HashSet<String>,
+    topic_pattern: TopicPattern,
+    output_file: Option<String>,
+    webhook_url: Option<String>,
+    webhook_secret: Option<String>,
+    alert_rules: Vec<AlertRule>,
+    qos: QoS,
+    clean_session: bool,
+    stats_interval: Option<Duration>,
+    ca_cert: Option<String>,
+    drain_timeout: Duration,
+    max_reconnects: Option<u32>,
+    log_format: LogFormat,
+    overflow_policy: OverflowPolicy,
+    replay_file: Option<String>,
+    replay_speed: f64,
+    #[cfg(feature = "metrics")]
+    health_port: Option<u16>,
+    #[cfg(feature = "metrics")]
+    health_max_silence: Duration,
 }
 
 impl Config {
@@ -41,56 +78,1000 @@ impl Config {
         let token = env::var("MQTT_API_TOKEN").unwrap_or_default();
         let broker_url = env::var("MQTT_BROKER_URL")
             .unwrap_or_else(|_| "wss://mqtt.tesaiot.com:8085/mqtt".to_string());
-        let topic =
-            env::var("MQTT_TOPIC").unwrap_or_else(|_| "device/+/telemetry/#".to_string());
+        // MQTT_TOPICS (or MQTT_TOPIC) may hold several comma-separated topic filters
+        let topics: Vec<String> = split_list(
+            &env::var("MQTT_TOPICS")
+                .or_else(|_| env::var("MQTT_TOPIC"))
+                .unwrap_or_else(|_| "device/+/telemetry/#".to_string()),
+        )
+        .collect();
+        if topics.is_empty() {
+            return Err("MQTT_TOPIC must contain at least one topic".into());
+        }
         let client_id = env::var("MQTT_CLIENT_ID")
             .unwrap_or_else(|_| format!("tesaiot-rust-{}", Utc::now().timestamp()));
 
+        // Optional comma-separated device allowlist (empty = all devices)
+        let device_filter =
+            split_list(&env::var("MQTT_DEVICE_FILTER").unwrap_or_default()).collect();
+
+        // Where the device ID and sensor type sit in received topics
+        let topic_pattern = TopicPattern::parse(
+            &env::var("MQTT_TOPIC_PATTERN").unwrap_or_else(|_| DEFAULT_TOPIC_PATTERN.to_string()),
+        )
+        .map_err(|e| format!("Invalid MQTT_TOPIC_PATTERN: {}", e))?;
+
+        // Optional NDJSON capture file
+        let output_file = env::var("MQTT_OUTPUT_FILE").ok().filter(|p| !p.is_empty());
+
+        // Optional HTTP webhook for forwarding telemetry
+        let webhook_url = env::var("MQTT_WEBHOOK_URL").ok().filter(|u| !u.is_empty());
+
+        // Optional shared secret for signing webhook requests
+        let webhook_secret = env::var("MQTT_WEBHOOK_SECRET")
+            .ok()
+            .filter(|s| !s.is_empty());
+
+        // Optional threshold alerts, e.g. "temperature>80,humidity<10"
+        let alert_rules = split_list(&env::var("MQTT_ALERT_RULES").unwrap_or_default())
+            .map(|rule| AlertRule::parse(&rule))
+            .collect::<Result<Vec<_>, _>>()
+            .map_err(|e| format!("Invalid MQTT_ALERT_RULES: {}", e))?;
+
+        // Subscription QoS (0/1/2) and session persistence
+        let qos = Self::parse_qos(&env::var("MQTT_QOS").unwrap_or_else(|_| "1".to_string()))?;
+        let clean_session = match env::var("MQTT_CLEAN_SESSION") {
+            Ok(value) => value.trim().parse::<bool>().map_err(|_| {
+                format!("Invalid MQTT_CLEAN_SESSION '{}': use true or false", value)
+            })?,
+            Err(_) => true,
+        };
+
+        // Message-rate statistics interval in seconds (0 or unset disables)
+        let stats_secs: u64 = match env::var("MQTT_STATS_INTERVAL") {
+            Ok(value) => value.trim().parse().map_err(|_| {
+                format!("Invalid MQTT_STATS_INTERVAL '{}': expected seconds", value)
+            })?,
+            Err(_) => 0,
+        };
+        let stats_interval = (stats_secs > 0).then(|| Duration::from_secs(stats_secs));
+
+        // Optional PEM CA certificate for private/on-prem brokers
+        let ca_cert = env::var("MQTT_CA_CERT").ok().filter(|p| !p.is_empty());
+
+        // Grace period for handling in-flight messages on shutdown (0 disables)
+        let drain_secs: u64 = match env::var("MQTT_DRAIN_TIMEOUT") {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .map_err(|_| format!("Invalid MQTT_DRAIN_TIMEOUT '{}': expected seconds", value))?,
+            Err(_) => 5,
+        };
+        let drain_timeout = Duration::from_secs(drain_secs);
+
+        // Give up after this many failed reconnects in a row (unset = retry forever)
+        let max_reconnects = match env::var("MQTT_MAX_RECONNECTS") {
+            Ok(value) => Some(value.trim().parse().map_err(|_| {
+                format!("Invalid MQTT_MAX_RECONNECTS '{}': expected a count", value)
+            })?),
+            Err(_) => None,
+        };
+
+        // Console output: human-readable (default) or one JSON object per line
+        let log_format = match env::var("MQTT_LOG_FORMAT") {
+            Ok(value) => match value.trim().to_lowercase().as_str() {
+                "pretty" => LogFormat::Pretty,
+                "json" => LogFormat::Json,
+                _ => {
+                    return Err(
+                        format!("Invalid MQTT_LOG_FORMAT '{}': use pretty or json", value).into(),
+                    )
+                }
+            },
+            Err(_) => LogFormat::Pretty,
+        };
+
+        // What to do when messages arrive faster than they can be processed
+        let overflow_policy = match env::var("MQTT_OVERFLOW_POLICY") {
+            Ok(value) => match value.trim().to_lowercase().replace('_', "-").as_str() {
+                "block" => OverflowPolicy::Block,
+                "drop-oldest" => OverflowPolicy::DropOldest,
+                _ => {
+                    return Err(format!(
+                        "Invalid MQTT_OVERFLOW_POLICY '{}': use block or drop-oldest",
+                        value
+                    )
+                    .into())
+                }
+            },
+            Err(_) => OverflowPolicy::Block,
+        };
+
+        // Replay a capture file instead of connecting, optionally paced
+        // (0 = as fast as possible, 1 = recorded timing, 10 = ten times faster)
+        let replay_file = env::var("MQTT_REPLAY_FILE").ok().filter(|p| !p.is_empty());
+        let replay_speed: f64 = match env::var("MQTT_REPLAY_SPEED") {
+            Ok(value) => value
+                .trim()
+                .parse()
+                .ok()
+                .filter(|speed: &f64| speed.is_finite() && *speed >= 0.0)
+                .ok_or_else(|| {
+                    format!(
+                        "Invalid MQTT_REPLAY_SPEED '{}': expected a non-negative multiplier",
+                        value
+                    )
+                })?,
+            Err(_) => 0.0,
+        };
+
+        // Readiness probe port and how long without messages counts as wedged
+        #[cfg(feature = "metrics")]
+        let health_port = match env::var("MQTT_HEALTH_PORT") {
+            Ok(value) => Some(
+                value
+                    .trim()
+                    .parse()
+                    .map_err(|_| format!("Invalid MQTT_HEALTH_PORT '{}'", value))?,
+            ),
+            Err(_) => None,
+        };
+        #[cfg(feature = "metrics")]
+        let health_max_silence = Duration::from_secs(match env::var("MQTT_HEALTH_MAX_SILENCE") {
+            Ok(value) => value.trim().parse().map_err(|_| {
+                format!(
+                    "Invalid MQTT_HEALTH_MAX_SILENCE '{}': expected seconds",
+                    value
+                )
+            })?,
+            Err(_) => 300,
+        });
+
         // Parse broker URL
-        let (host, port) = Self::parse_broker_url(&broker_url)?;
+        let (scheme, host, port) = Self::parse_broker_url(&broker_url)?;
 
         Ok(Config {
             token,
             broker_url,
-            topic,
+            topics,
             client_id,
+            scheme,
             host,
             port,
+            device_filter,
+            topic_pattern,
+            output_file,
+            webhook_url,
+            webhook_secret,
+            alert_rules,
+            qos,
+            clean_session,
+            stats_interval,
+            ca_cert,
+            drain_timeout,
+            max_reconnects,
+            log_format,
+            overflow_policy,
+            replay_file,
+            replay_speed,
+            #[cfg(feature = "metrics")]
+            health_port,
+            #[cfg(feature = "metrics")]
+            health_max_silence,
         })
     }
 
-    /// Parse WSS URL into host and port
-    fn parse_broker_url(url_str: &str) -> Result<(String, u16), Box<dyn Error>> {
+    /// Parse an MQTT QoS level (0, 1 or 2)
+    fn parse_qos(value: &str) -> Result<QoS, Box<dyn Error>> {
+        match value.trim() {
+            "0" => Ok(QoS::AtMostOnce),
+            "1" => Ok(QoS::AtLeastOnce),
+            "2" => Ok(QoS::ExactlyOnce),
+            other => Err(format!("Invalid MQTT_QOS '{}': must be 0, 1 or 2", other).into()),
+        }
+    }
+
+    /// Check whether messages from a device should be processed
+    fn allows_device(&self, device_id: &str) -> bool {
+        self.device_filter.is_empty() || self.device_filter.contains(device_id)
+    }
+
+    /// Parse a `wss://`, `mqtts://` or `mqtt://` URL into scheme, host and port
+    fn parse_broker_url(url_str: &str) -> Result<(BrokerScheme, String, u16), Box<dyn Error>> {
         let url = Url::parse(url_str)?;
+        let scheme = BrokerScheme::parse(url.scheme()).ok_or_else(|| {
+            format!(
+                "Unsupported MQTT_BROKER_URL scheme '{}': use wss://, mqtts:// or mqtt://",
+                url.scheme()
+            )
+        })?;
         let host = url.host_str().ok_or("Invalid host in URL")?.to_string();
-        let port = url.port().unwrap_or(8085);
-        Ok((host, port))
+        let port = url.port().unwrap_or(scheme.default_port());
+        Ok((scheme, host, port))
+    }
+
+    /// Broker address for `MqttOptions`: the host, or for WebSockets the full
+    /// URL (rumqttc reads the host, port and path from it)
+    fn broker_address(&self) -> Result<String, Box<dyn Error>> {
+        match self.scheme {
+            BrokerScheme::Wss => {
+                let mut url = Url::parse(&self.broker_url)?;
+                // Spell out the default port; rumqttc would otherwise assume 443
+                url.set_port(Some(self.port))
+                    .map_err(|_| "Invalid port in MQTT_BROKER_URL")?;
+                Ok(url.to_string())
+            }
+            BrokerScheme::Mqtts | BrokerScheme::Mqtt => Ok(self.host.clone()),
+        }
+    }
+
+    /// Transport for the URL scheme; TLS ones trust `MQTT_CA_CERT` as well
+    fn transport(&self) -> Result<Transport, Box<dyn Error>> {
+        Ok(match self.scheme {
+            BrokerScheme::Wss => Transport::Wss(build_tls_config(self.ca_cert.as_deref())?),
+            BrokerScheme::Mqtts => Transport::Tls(build_tls_config(self.ca_cert.as_deref())?),
+            BrokerScheme::Mqtt => Transport::Tcp,
+        })
     }
 
     /// Validate configuration
     fn validate(&self) -> Result<(), Box<dyn Error>> {
         if self.token.is_empty() {
-            eprintln!("❌ ERROR: MQTT_API_TOKEN is required");
-            eprintln!();
-            eprintln!("Please set MQTT_API_TOKEN in .env file or environment variable");
-            eprintln!("Generate a token from TESAIoT Admin UI:");
-            eprintln!("  1. Login to https://admin.tesaiot.com");
-            eprintln!("  2. Go to Organization Settings > MQTT API Tokens");
-            eprintln!("  3. Click \"Generate New Token\"");
+            log_event(
+                "error",
+                "config_error",
+                json!({"error": "MQTT_API_TOKEN is required"}),
+                || {
+                    eprintln!("❌ ERROR: MQTT_API_TOKEN is required");
+                    eprintln!();
+                    eprintln!("Please set MQTT_API_TOKEN in .env file or environment variable");
+                    eprintln!("Generate a token from TESAIoT Admin UI:");
+                    eprintln!("  1. Login to https://admin.tesaiot.com");
+                    eprintln!("  2. Go to Organization Settings > MQTT API Tokens");
+                    eprintln!("  3. Click \"Generate New Token\"");
+                },
+            );
             return Err("MQTT_API_TOKEN is required".into());
         }
 
         if !self.token.starts_with("tesa_mqtt_") {
-            eprintln!("❌ ERROR: Invalid token format");
-            eprintln!();
-            eprintln!("Token should start with \"tesa_mqtt_\"");
+            log_event(
+                "error",
+                "config_error",
+                json!({"error": "Invalid token format"}),
+                || {
+                    eprintln!("❌ ERROR: Invalid token format");
+                    eprintln!();
+                    eprintln!("Token should start with \"tesa_mqtt_\"");
+                },
+            );
             return Err("Invalid token format".into());
         }
 
+        for topic in &self.topics {
+            if let Err(reason) = validate_topic_filter(topic) {
+                let message = format!("Invalid topic filter '{}': {}", topic, reason);
+                log_event("error", "config_error", json!({"error": message}), || {
+                    eprintln!("❌ ERROR: {}", message);
+                    eprintln!();
+                    eprintln!("Check MQTT_TOPIC, e.g. device/+/telemetry/#");
+                });
+                return Err(message.into());
+            }
+        }
+
         Ok(())
     }
 }
 
+/// Check that `filter` is a usable MQTT topic filter
+///
+/// Beyond the MQTT rules (`+` fills a whole level, `#` is the whole last
+/// level), empty levels such as `device//telemetry` or a trailing `/` are
+/// rejected: they are legal but almost always a typo that matches nothing.
+fn validate_topic_filter(filter: &str) -> Result<(), String> {
+    if filter.is_empty() {
+        return Err("topic is empty".to_string());
+    }
+    if filter.len() > 65535 {
+        return Err("topic is longer than 65535 bytes".to_string());
+    }
+    if filter.contains('\0') {
+        return Err("topic contains a NUL character".to_string());
+    }
+
+    let levels: Vec<&str> = filter.split('/').collect();
+    for (i, level) in levels.iter().enumerate() {
+        let position = i + 1;
+        match *level {
+            "" if i == levels.len() - 1 => {
+                return Err("trailing '/' creates an empty last level".to_string())
+            }
+            "" if i == 0 => return Err("leading '/' creates an empty first level".to_string()),
+            "" => return Err(format!("level {} is empty ('//')", position)),
+            "#" if i != levels.len() - 1 => {
+                return Err("'#' is only allowed as the last level".to_string())
+            }
+            "+" | "#" => {}
+            _ if level.contains(['+', '#']) => {
+                return Err(format!(
+                    "level {} ('{}') mixes a wildcard with other characters; \
+                     '+' and '#' must fill a whole level",
+                    position, level
+                ))
+            }
+            _ => {}
+        }
+    }
+    Ok(())
+}
+
+/// Connection type selected by the `MQTT_BROKER_URL` scheme
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum BrokerScheme {
+    /// `wss://`: MQTT over WebSocket Secure
+    Wss,
+    /// `mqtts://`: MQTT over TLS
+    Mqtts,
+    /// `mqtt://`: plain TCP, unencrypted
+    Mqtt,
+}
+
+impl BrokerScheme {
+    fn parse(scheme: &str) -> Option<Self> {
+        match scheme {
+            "wss" => Some(BrokerScheme::Wss),
+            "mqtts" => Some(BrokerScheme::Mqtts),
+            "mqtt" => Some(BrokerScheme::Mqtt),
+            _ => None,
+        }
+    }
+
+    /// Port used when the URL doesn't specify one
+    fn default_port(&self) -> u16 {
+        match self {
+            BrokerScheme::Wss => 8085,
+            BrokerScheme::Mqtts => 8883,
+            BrokerScheme::Mqtt => 1883,
+        }
+    }
+
+    fn label(&self) -> &'static str {
+        match self {
+            BrokerScheme::Wss => "WSS",
+            BrokerScheme::Mqtts => "TLS",
+            BrokerScheme::Mqtt => "TCP",
+        }
+    }
+}
+
+/// Console output style (`MQTT_LOG_FORMAT`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum LogFormat {
+    Pretty,
+    Json,
+}
+
+/// Set once at startup; background tasks log too, so it isn't passed around
+static LOG_FORMAT: OnceLock<LogFormat> = OnceLock::new();
+
+/// Log a message or lifecycle event
+///
+/// In the default pretty format this just runs `pretty`. With
+/// `MQTT_LOG_FORMAT=json` it instead prints one line to stdout:
+/// `{"level", "ts", "event"}` plus the entries of the `fields` object.
+fn log_event(level: &str, event: &str, fields: Value, pretty: impl FnOnce()) {
+    if LOG_FORMAT.get() != Some(&LogFormat::Json) {
+        pretty();
+        return;
+    }
+    let mut line = json!({
+        "level": level,
+        "ts": Utc::now().to_rfc3339(),
+        "event": event,
+    });
+    if let (Some(line), Value::Object(fields)) = (line.as_object_mut(), fields) {
+        line.extend(fields);
+    }
+    println!("{}", line);
+}
+
+/// Counters for received messages, reported every stats interval
+struct MessageStats {
+    started: Instant,
+    total_messages: u64,
+    total_bytes: u64,
+    window_start: Instant,
+    window_messages: u64,
+}
+
+impl MessageStats {
+    fn new() -> Self {
+        let now = Instant::now();
+        MessageStats {
+            started: now,
+            total_messages: 0,
+            total_bytes: 0,
+            window_start: now,
+            window_messages: 0,
+        }
+    }
+
+    fn record(&mut self, bytes: usize) {
+        self.total_messages += 1;
+        self.total_bytes += bytes as u64;
+        self.window_messages += 1;
+    }
+
+    /// Counters for a JSON `stats` event; call before [`report`](Self::report)
+    fn fields(&self) -> Value {
+        let window = self.window_start.elapsed().as_secs_f64().max(f64::EPSILON);
+        json!({
+            "rate_per_sec": self.window_messages as f64 / window,
+            "window_secs": window,
+            "total_messages": self.total_messages,
+            "total_bytes": self.total_bytes,
+            "uptime_secs": self.started.elapsed().as_secs_f64(),
+        })
+    }
+
+    /// Format a one-line summary and start a new rate window
+    fn report(&mut self) -> String {
+        let window = self.window_start.elapsed().as_secs_f64().max(f64::EPSILON);
+        let line = format!(
+            "📊 {:.2} msg/s over last {:.0}s | {} messages, {} bytes in {:.0}s",
+            self.window_messages as f64 / window,
+            window,
+            self.total_messages,
+            self.total_bytes,
+            self.started.elapsed().as_secs_f64()
+        );
+        self.window_start = Instant::now();
+        self.window_messages = 0;
+        line
+    }
+}
+
+/// Mask a secret token for display; always `"***redacted***"`
+///
+/// Matches `redact_token` in the analytics client. It is repeated here rather
+/// than shared because this example is a standalone crate that doesn't depend
+/// on the analytics client.
+fn redact_token(_token: &str) -> String {
+    "***redacted***".to_string()
+}
+
+/// Split a comma-separated env value into trimmed, non-empty items
+fn split_list(value: &str) -> impl Iterator<Item = String> + '_ {
+    value
+        .split(',')
+        .map(|item| item.trim().to_string())
+        .filter(|item| !item.is_empty())
+}
+
+/// Topic layout used when `MQTT_TOPIC_PATTERN` is unset
+const DEFAULT_TOPIC_PATTERN: &str = "device/+device_id/telemetry/#sensor";
+
+/// One level of a [`TopicPattern`]
+#[derive(Debug, Clone, PartialEq)]
+enum PatternLevel {
+    Literal(String),
+    /// `+` or `+name`: exactly one level
+    Single(Option<String>),
+    /// `#` or `#name`: all remaining levels (possibly none); always last
+    Multi(Option<String>),
+}
+
+/// MQTT topic filter whose wildcards can name the levels they match
+///
+/// `device/+device_id/telemetry/#sensor` matches the same topics as
+/// `device/+/telemetry/#` and captures `device_id` and `sensor` from them.
+#[derive(Debug, Clone)]
+struct TopicPattern {
+    levels: Vec<PatternLevel>,
+}
+
+impl TopicPattern {
+    fn parse(pattern: &str) -> Result<Self, String> {
+        let parts: Vec<&str> = pattern.split('/').collect();
+        let mut levels = Vec::with_capacity(parts.len());
+        for (i, part) in parts.iter().enumerate() {
+            let name = |rest: &str| (!rest.is_empty()).then(|| rest.to_string());
+            let level = if let Some(rest) = part.strip_prefix('+') {
+                PatternLevel::Single(name(rest))
+            } else if let Some(rest) = part.strip_prefix('#') {
+                if i != parts.len() - 1 {
+                    return Err(format!(
+                        "'{}' must be the last level of '{}'",
+                        part, pattern
+                    ));
+                }
+                PatternLevel::Multi(name(rest))
+            } else {
+                PatternLevel::Literal(part.to_string())
+            };
+            let text = match &level {
+                PatternLevel::Literal(text) => text.as_str(),
+                PatternLevel::Single(name) | PatternLevel::Multi(name) => {
+                    name.as_deref().unwrap_or("")
+                }
+            };
+            if text.contains(['+', '#']) {
+                return Err(format!(
+                    "wildcard in '{}' must start the level and appear once",
+                    part
+                ));
+            }
+            levels.push(level);
+        }
+        Ok(TopicPattern { levels })
+    }
+
+    /// Named captures if `topic` matches, otherwise `None`
+    ///
+    /// A `#` capture joins the remaining levels with `/` and is empty when
+    /// the topic ends at the parent level (`a/#` matches `a`). As in MQTT, a
+    /// leading wildcard doesn't match `$SYS`-style topics.
+    fn captures(&self, topic: &str) -> Option<HashMap<String, String>> {
+        let parts: Vec<&str> = topic.split('/').collect();
+        if topic.starts_with('$') && !matches!(self.levels.first(), Some(PatternLevel::Literal(_)))
+        {
+            return None;
+        }
+
+        let mut captures = HashMap::new();
+        for (i, level) in self.levels.iter().enumerate() {
+            match level {
+                PatternLevel::Literal(text) => {
+                    if parts.get(i) != Some(&text.as_str()) {
+                        return None;
+                    }
+                }
+                PatternLevel::Single(name) => {
+                    let part = parts.get(i)?;
+                    if let Some(name) = name {
+                        captures.insert(name.clone(), part.to_string());
+                    }
+                }
+                PatternLevel::Multi(name) => {
+                    if let Some(name) = name {
+                        let rest = parts.get(i..).map(|rest| rest.join("/"));
+                        captures.insert(name.clone(), rest.unwrap_or_default());
+                    }
+                    return Some(captures);
+                }
+            }
+        }
+        (parts.len() == self.levels.len()).then_some(captures)
+    }
+}
+
+/// Whether the broker refused the connection because of the token
+fn is_auth_failure(error: &ConnectionError) -> bool {
+    matches!(
+        error,
+        ConnectionError::ConnectionRefused(
+            ConnectReturnCode::BadUserNamePassword | ConnectReturnCode::NotAuthorized
+        )
+    )
+}
+
+/// Exponential reconnect backoff with random jitter
+struct Backoff {
+    base: Duration,
+    max: Duration,
+    current: Duration,
+}
+
+impl Backoff {
+    fn new(base: Duration, max: Duration) -> Self {
+        Backoff {
+            base,
+            max,
+            current: base,
+        }
+    }
+
+    /// Return the next delay (with up to 25% jitter) and double the delay for next time
+    fn next_delay(&mut self) -> Duration {
+        let delay = self.current;
+        self.current = (self.current * 2).min(self.max);
+
+        let jitter_ms = rand::thread_rng().gen_range(0..=delay.as_millis() as u64 / 4);
+        delay + Duration::from_millis(jitter_ms)
+    }
+
+    /// Reset to the base delay after a successful connection
+    fn reset(&mut self) {
+        self.current = self.base;
+    }
+}
+
+/// Appends received messages to a newline-delimited JSON file
+struct NdjsonWriter {
+    writer: BufWriter<File>,
+}
+
+impl NdjsonWriter {
+    /// Open (or create) the file in append mode
+    fn open(path: &str) -> io::Result<Self> {
+        let file = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(NdjsonWriter {
+            writer: BufWriter::new(file),
+        })
+    }
+
+    /// Write one record as a single JSON line
+    fn write(&mut self, record: &Value) -> io::Result<()> {
+        serde_json::to_writer(&mut self.writer, record)?;
+        self.writer.write_all(b"\n")
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.writer.flush()
+    }
+}
+
+/// Maximum number of messages waiting to be sent to the webhook
+const WEBHOOK_QUEUE_SIZE: usize = 256;
+
+/// Attempts per message before a webhook delivery is dropped
+const WEBHOOK_MAX_ATTEMPTS: u32 = 3;
+
+/// Header carrying the hex HMAC-SHA256 of the webhook request body
+const SIGNATURE_HEADER: &str = "X-TESAIoT-Signature";
+
+/// Sign a webhook body with HMAC-SHA256, returning the lowercase hex digest
+fn sign_payload(secret: &[u8], body: &[u8]) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.finalize()
+        .into_bytes()
+        .iter()
+        .map(|b| format!("{:02x}", b))
+        .collect()
+}
+
+/// Check a webhook body against its `X-TESAIoT-Signature` header value
+///
+/// For use on the receiving side. The digest comparison is constant-time, so
+/// response timing doesn't reveal how much of a forged signature was correct.
+#[allow(dead_code)] // Provided for webhook receivers
+fn verify_signature(secret: &[u8], body: &[u8], signature: &str) -> bool {
+    let Some(expected) = decode_hex(signature.trim()) else {
+        return false;
+    };
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Decode a hex string (either case); `None` if it isn't valid hex
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    // `from_str_radix` alone would also accept a leading `+`
+    if !hex.len().is_multiple_of(2) || !hex.bytes().all(|b| b.is_ascii_hexdigit()) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
+
+/// Forwards records to an HTTP webhook from a background task
+///
+/// Records are queued on a bounded channel so a slow webhook never blocks
+/// the MQTT event loop; when the queue is full new records are dropped.
+/// With a secret, each request is signed in the `X-TESAIoT-Signature` header.
+struct WebhookForwarder {
+    sender: mpsc::Sender<Value>,
+}
+
+impl WebhookForwarder {
+    fn spawn(url: String, secret: Option<String>) -> Self {
+        let (sender, receiver) = mpsc::channel(WEBHOOK_QUEUE_SIZE);
+        tokio::spawn(run_webhook(url, secret, receiver));
+        WebhookForwarder { sender }
+    }
+
+    fn forward(&self, record: Value) {
+        match self.sender.try_send(record) {
+            Ok(()) => {}
+            Err(TrySendError::Full(_)) => {
+                log_event(
+                    "warn",
+                    "webhook_dropped",
+                    json!({"reason": "queue full"}),
+                    || eprintln!("⚠️  Webhook queue full, dropping message"),
+                );
+            }
+            Err(TrySendError::Closed(_)) => {
+                let fields = json!({"reason": "forwarder stopped"});
+                log_event("warn", "webhook_dropped", fields, || {
+                    eprintln!("⚠️  Webhook forwarder stopped, dropping message")
+                });
+            }
+        }
+    }
+}
+
+/// Deliver queued records to the webhook, retrying transient failures
+async fn run_webhook(url: String, secret: Option<String>, mut receiver: mpsc::Receiver<Value>) {
+    let client = reqwest::Client::new();
+
+    while let Some(record) = receiver.recv().await {
+        // Serialize once so the signature covers exactly the bytes sent
+        let body = match serde_json::to_vec(&record) {
+            Ok(body) => body,
+            Err(e) => {
+                let fields = json!({"reason": "serialization failed", "error": e.to_string()});
+                log_event("warn", "webhook_dropped", fields, || {
+                    eprintln!("⚠️  Cannot serialize webhook message, dropping it: {}", e)
+                });
+                continue;
+            }
+        };
+        let signature = secret
+            .as_ref()
+            .map(|secret| sign_payload(secret.as_bytes(), &body));
+
+        for attempt in 1..=WEBHOOK_MAX_ATTEMPTS {
+            let mut request = client
+                .post(&url)
+                .header(reqwest::header::CONTENT_TYPE, "application/json")
+                .body(body.clone());
+            if let Some(signature) = &signature {
+                request = request.header(SIGNATURE_HEADER, signature);
+            }
+            let result = request
+                .send()
+                .await
+                .and_then(|response| response.error_for_status());
+
+            match result {
+                Ok(_) => break,
+                Err(e) if attempt == WEBHOOK_MAX_ATTEMPTS => {
+                    let fields = json!({"reason": "delivery failed", "error": e.to_string()});
+                    log_event("warn", "webhook_dropped", fields, || {
+                        eprintln!("⚠️  Webhook delivery failed, dropping message: {}", e)
+                    });
+                }
+                Err(_) => {
+                    tokio::time::sleep(Duration::from_millis(500 * attempt as u64)).await;
+                }
+            }
+        }
+    }
+}
+
+/// Comparison used by an [`AlertRule`]
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Greater,
+    Less,
+    GreaterOrEqual,
+    LessOrEqual,
+}
+
+impl Comparison {
+    fn as_str(&self) -> &'static str {
+        match self {
+            Comparison::Greater => ">",
+            Comparison::Less => "<",
+            Comparison::GreaterOrEqual => ">=",
+            Comparison::LessOrEqual => "<=",
+        }
+    }
+}
+
+/// Threshold on one telemetry reading, e.g. `temperature>80`
+#[derive(Debug, Clone, PartialEq)]
+struct AlertRule {
+    reading: String,
+    comparison: Comparison,
+    threshold: f64,
+}
+
+impl AlertRule {
+    /// Parse `<reading><op><number>` where op is `>`, `<`, `>=` or `<=`
+    fn parse(rule: &str) -> Result<Self, String> {
+        let index = rule
+            .find(['>', '<'])
+            .ok_or_else(|| format!("'{}' has no comparison (use >, <, >= or <=)", rule))?;
+        let (reading, rest) = rule.split_at(index);
+        let (comparison, threshold) = if let Some(value) = rest.strip_prefix(">=") {
+            (Comparison::GreaterOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix("<=") {
+            (Comparison::LessOrEqual, value)
+        } else if let Some(value) = rest.strip_prefix('>') {
+            (Comparison::Greater, value)
+        } else {
+            (Comparison::Less, &rest[1..])
+        };
+
+        let reading = reading.trim();
+        if reading.is_empty() {
+            return Err(format!("'{}' has no reading name", rule));
+        }
+        if reading.contains('=') {
+            return Err(format!(
+                "'{}' has a misplaced '=' (use >, <, >= or <=)",
+                rule
+            ));
+        }
+        let threshold = threshold
+            .trim()
+            .parse::<f64>()
+            .ok()
+            .filter(|t| t.is_finite())
+            .ok_or_else(|| format!("'{}' has an invalid threshold", rule))?;
+
+        Ok(AlertRule {
+            reading: reading.to_string(),
+            comparison,
+            threshold,
+        })
+    }
+
+    /// The reading's value if this rule fires for `telemetry`
+    fn check(&self, telemetry: &Telemetry) -> Option<f64> {
+        let value = *telemetry.readings.get(&self.reading)?;
+        let fires = match self.comparison {
+            Comparison::Greater => value > self.threshold,
+            Comparison::Less => value < self.threshold,
+            Comparison::GreaterOrEqual => value >= self.threshold,
+            Comparison::LessOrEqual => value <= self.threshold,
+        };
+        fires.then_some(value)
+    }
+}
+
+impl std::fmt::Display for AlertRule {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "{}{}{}",
+            self.reading,
+            self.comparison.as_str(),
+            self.threshold
+        )
+    }
+}
+
+/// Optional destinations and actions for processed messages
+struct Outputs {
+    ndjson: Option<NdjsonWriter>,
+    webhook: Option<WebhookForwarder>,
+    alert_rules: Vec<AlertRule>,
+}
+
+impl Outputs {
+    fn from_config(config: &Config) -> Result<Self, Box<dyn Error>> {
+        let ndjson = match &config.output_file {
+            Some(path) => Some(
+                NdjsonWriter::open(path)
+                    .map_err(|e| format!("Cannot open MQTT_OUTPUT_FILE {}: {}", path, e))?,
+            ),
+            None => None,
+        };
+        let webhook = config
+            .webhook_url
+            .clone()
+            .map(|url| WebhookForwarder::spawn(url, config.webhook_secret.clone()));
+        Ok(Outputs {
+            ndjson,
+            webhook,
+            alert_rules: config.alert_rules.clone(),
+        })
+    }
+
+    /// Flush buffered output (called periodically and on shutdown)
+    fn flush(&mut self) {
+        if let Some(ndjson) = &mut self.ndjson {
+            if let Err(e) = ndjson.flush() {
+                log_event(
+                    "warn",
+                    "output_error",
+                    json!({"error": e.to_string()}),
+                    || eprintln!("⚠️  Failed to flush output file: {}", e),
+                );
+            }
+        }
+    }
+}
+
+/// Telemetry payload decoded into numeric readings
+#[derive(Debug, Clone)]
+#[allow(dead_code)] // Fields are provided for custom processing logic
+struct Telemetry {
+    device_id: String,
+    sensor_type: String,
+    timestamp: DateTime<Utc>,
+    readings: HashMap<String, f64>,
+}
+
+/// Try to decode a telemetry message into a typed [`Telemetry`]
+///
+/// Every top-level numeric field of a JSON object payload becomes a reading.
+/// A bare `value` field is named after the sensor type from the topic, so
+/// `device/abc/telemetry/temperature` with `{"value": 25.5}` yields
+/// `temperature = 25.5`. An optional `timestamp` (RFC3339) or `ts` (Unix
+/// seconds) field is used as the reading time, otherwise the receive time.
+/// Returns `None` for non-object payloads or ones without numeric fields.
+fn try_decode(device_id: &str, sensor_type: &str, payload: &[u8]) -> Option<Telemetry> {
+    let value: Value = serde_json::from_slice(payload).ok()?;
+    let object = value.as_object()?;
+
+    let timestamp = object
+        .get("timestamp")
+        .and_then(Value::as_str)
+        .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+        .map(|ts| ts.with_timezone(&Utc))
+        .or_else(|| {
+            object
+                .get("ts")
+                .and_then(Value::as_i64)
+                .and_then(|ts| DateTime::from_timestamp(ts, 0))
+        })
+        .unwrap_or_else(Utc::now);
+
+    let readings: HashMap<String, f64> = object
+        .iter()
+        .filter(|(key, _)| key.as_str() != "ts")
+        .filter_map(|(key, value)| {
+            let reading = value.as_f64()?;
+            let name = if key == "value" && sensor_type != "default" {
+                sensor_type.to_string()
+            } else {
+                key.clone()
+            };
+            Some((name, reading))
+        })
+        .collect();
+
+    if readings.is_empty() {
+        return None;
+    }
+
+    Some(Telemetry {
+        device_id: device_id.to_string(),
+        sensor_type: sensor_type.to_string(),
+        timestamp,
+        readings,
+    })
+}
+
+/// Build the TLS configuration, trusting system roots plus an optional custom CA
+fn build_tls_config(ca_cert: Option<&str>) -> Result<TlsConfiguration, Box<dyn Error>> {
+    let Some(path) = ca_cert else {
+        return Ok(TlsConfiguration::default());
+    };
+
+    let pem = fs::read(path).map_err(|e| format!("Cannot read MQTT_CA_CERT {}: {}", path, e))?;
+    let certs = rustls_pemfile::certs(&mut pem.as_slice())
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|e| format!("Invalid PEM in MQTT_CA_CERT {}: {}", path, e))?;
+    if certs.is_empty() {
+        return Err(format!("No certificates found in MQTT_CA_CERT {}", path).into());
+    }
+
+    let mut roots = RootCertStore::empty();
+    for cert in rustls_native_certs::load_native_certs()? {
+        // Skip platform certificates rustls can't parse
+        let _ = roots.add(cert);
+    }
+    for cert in certs {
+        roots
+            .add(cert)
+            .map_err(|e| format!("Invalid certificate in MQTT_CA_CERT {}: {}", path, e))?;
+    }
+
+    let tls_config = ClientConfig::builder()
+        .with_root_certificates(roots)
+        .with_no_client_auth();
+    Ok(TlsConfiguration::Rustls(Arc::new(tls_config)))
+}
+
 /// Display application banner
 fn display_banner() {
     println!();
@@ -107,25 +1088,84 @@ fn display_banner() {
 /// - Forward to webhook
 /// - Trigger alerts
 /// - Update dashboard
-fn process_message(device_id: &str, sensor_type: &str, data: &Value) {
+fn process_message(
+    outputs: &mut Outputs,
+    timestamp: &str,
+    topic: &str,
+    device_id: &str,
+    sensor_type: &str,
+    data: &Value,
+    telemetry: Option<&Telemetry>,
+) {
+    let record = serde_json::json!({
+        "timestamp": timestamp,
+        "topic": topic,
+        "device_id": device_id,
+        "sensor_type": sensor_type,
+        "data": data,
+    });
+
+    // Append to NDJSON capture file (MQTT_OUTPUT_FILE)
+    if let Some(ndjson) = &mut outputs.ndjson {
+        if let Err(e) = ndjson.write(&record) {
+            log_event(
+                "warn",
+                "output_error",
+                json!({"error": e.to_string()}),
+                || eprintln!("⚠️  Failed to write output file: {}", e),
+            );
+        }
+    }
+
+    // Forward to HTTP webhook (MQTT_WEBHOOK_URL)
+    if let Some(webhook) = &outputs.webhook {
+        webhook.forward(record);
+    }
+
+    // Threshold alerts (MQTT_ALERT_RULES) on the typed readings
+    if let Some(telemetry) = telemetry {
+        for rule in &outputs.alert_rules {
+            if let Some(value) = rule.check(telemetry) {
+                let fields = json!({
+                    "device_id": device_id,
+                    "topic": topic,
+                    "reading": rule.reading,
+                    "value": value,
+                    "rule": rule.to_string(),
+                });
+                log_event("warn", "alert", fields, || {
+                    println!(
+                        "🚨 ALERT: {} {} = {} (rule {})",
+                        device_id, rule.reading, value, rule
+                    )
+                });
+            }
+        }
+    }
+
     // Example: Add your custom processing logic here
     // - Store in PostgreSQL/MongoDB
     // - Send to Redis for real-time dashboard
     // - Trigger webhook for external systems
-    // - Check thresholds and send alerts
-    let _ = (device_id, sensor_type, data); // Suppress unused warnings
 }
 
 /// Handle incoming MQTT publish message
-fn handle_publish(topic: &str, payload: &[u8]) {
-    // Parse topic: device/<device_id>/telemetry/<sensor_type>
-    let parts: Vec<&str> = topic.split('/').collect();
-    let device_id = parts.get(1).unwrap_or(&"unknown");
-    let sensor_type = if parts.len() > 3 {
-        parts[3..].join("/")
-    } else {
-        "default".to_string()
-    };
+fn handle_publish(config: &Config, outputs: &mut Outputs, topic: &str, payload: &[u8]) {
+    // Extract device and sensor using MQTT_TOPIC_PATTERN
+    // (default: device/+device_id/telemetry/#sensor)
+    let mut captures = config.topic_pattern.captures(topic).unwrap_or_default();
+    let device_id = captures
+        .remove("device_id")
+        .unwrap_or_else(|| "unknown".to_string());
+
+    // Skip devices not in the allowlist
+    if !config.allows_device(&device_id) {
+        return;
+    }
+    let sensor_type = captures
+        .remove("sensor")
+        .filter(|sensor| !sensor.is_empty())
+        .unwrap_or_else(|| "default".to_string());
 
     // Parse JSON payload
     let data: Value = match serde_json::from_slice(payload) {
@@ -138,14 +1178,392 @@ fn handle_publish(topic: &str, payload: &[u8]) {
 
     // Log received telemetry
     let timestamp = Utc::now().to_rfc3339();
-    println!("[{}] {}", timestamp, topic);
-    println!("  Device: {}", device_id);
-    println!("  Sensor: {}", sensor_type);
-    println!("  Data: {}", serde_json::to_string_pretty(&data).unwrap_or_default());
-    println!();
+    let fields = json!({
+        "topic": topic,
+        "device_id": device_id,
+        "sensor_type": sensor_type,
+        "bytes": payload.len(),
+    });
+    log_event("info", "message", fields, || {
+        println!("[{}] {}", timestamp, topic);
+        println!("  Device: {}", device_id);
+        println!("  Sensor: {}", sensor_type);
+        println!(
+            "  Data: {}",
+            serde_json::to_string_pretty(&data).unwrap_or_default()
+        );
+        println!();
+    });
+
+    // Process message, with typed readings when the payload decodes
+    let telemetry = try_decode(&device_id, &sensor_type, payload);
+    process_message(
+        outputs,
+        &timestamp,
+        topic,
+        &device_id,
+        &sensor_type,
+        &data,
+        telemetry.as_ref(),
+    );
+}
+
+/// Maximum number of received messages waiting to be processed
+const PROCESSING_QUEUE_SIZE: usize = 1024;
+
+/// How long the `block` overflow policy waits for queue space
+const OVERFLOW_BLOCK_TIMEOUT: Duration = Duration::from_secs(1);
+
+/// What to do with a message when the processing queue is full
+/// (`MQTT_OVERFLOW_POLICY`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum OverflowPolicy {
+    /// Wait up to [`OVERFLOW_BLOCK_TIMEOUT`] for space, then drop the new message
+    Block,
+    /// Drop the oldest queued message to make room
+    DropOldest,
+}
+
+impl OverflowPolicy {
+    fn as_str(&self) -> &'static str {
+        match self {
+            OverflowPolicy::Block => "block",
+            OverflowPolicy::DropOldest => "drop-oldest",
+        }
+    }
+}
+
+/// Hands received messages to a processing task
+///
+/// `handle_publish` may be slow (file writes, custom database code), and
+/// running it inside the event loop would delay keepalive pings until the
+/// broker drops the connection. The event loop only queues messages here.
+struct ProcessingQueue {
+    sender: mpsc::Sender<Publish>,
+    /// Shared with the processing task so `DropOldest` can pop from the front
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<Publish>>>,
+    policy: OverflowPolicy,
+    processor: JoinHandle<()>,
+    /// Messages dropped since the last [`take_dropped`](Self::take_dropped)
+    dropped: u64,
+}
+
+impl ProcessingQueue {
+    fn spawn(config: Arc<Config>, outputs: Outputs) -> Self {
+        let (sender, receiver) = mpsc::channel(PROCESSING_QUEUE_SIZE);
+        let receiver = Arc::new(tokio::sync::Mutex::new(receiver));
+        let policy = config.overflow_policy;
+        let processor = tokio::spawn(run_processor(config, outputs, Arc::clone(&receiver)));
+        ProcessingQueue {
+            sender,
+            receiver,
+            policy,
+            processor,
+            dropped: 0,
+        }
+    }
+
+    /// Queue a message, applying the overflow policy when the queue is full
+    async fn push(&mut self, publish: Publish) {
+        let publish = match self.sender.try_send(publish) {
+            Ok(()) => return,
+            Err(TrySendError::Full(publish)) => publish,
+            Err(TrySendError::Closed(_)) => {
+                self.dropped += 1;
+                return;
+            }
+        };
+
+        match self.policy {
+            OverflowPolicy::Block => {
+                if let Err(SendTimeoutError::Timeout(_) | SendTimeoutError::Closed(_)) = self
+                    .sender
+                    .send_timeout(publish, OVERFLOW_BLOCK_TIMEOUT)
+                    .await
+                {
+                    self.dropped += 1;
+                }
+            }
+            OverflowPolicy::DropOldest => {
+                // The queue is full, so the processing task isn't waiting on
+                // an empty queue and releases the lock right away
+                let mut receiver = self.receiver.lock().await;
+                if receiver.try_recv().is_ok() {
+                    self.dropped += 1;
+                }
+                if self.sender.try_send(publish).is_err() {
+                    self.dropped += 1;
+                }
+            }
+        }
+    }
+
+    /// Dropped message count since the last call
+    fn take_dropped(&mut self) -> u64 {
+        std::mem::take(&mut self.dropped)
+    }
+
+    /// Stop accepting messages and wait until the queued ones are processed
+    async fn close(self) {
+        drop(self.sender);
+        let _ = self.processor.await;
+    }
+}
 
-    // Process message
-    process_message(device_id, &sensor_type, &data);
+/// Process queued messages until the queue is closed and empty
+async fn run_processor(
+    config: Arc<Config>,
+    mut outputs: Outputs,
+    receiver: Arc<tokio::sync::Mutex<mpsc::Receiver<Publish>>>,
+) {
+    // Output destinations are flushed every few seconds
+    let mut flush_interval = tokio::time::interval(Duration::from_secs(5));
+    loop {
+        tokio::select! {
+            publish = async { receiver.lock().await.recv().await } => match publish {
+                Some(publish) => {
+                    handle_publish(&config, &mut outputs, &publish.topic, &publish.payload)
+                }
+                None => break,
+            },
+            _ = flush_interval.tick() => outputs.flush(),
+        }
+    }
+    outputs.flush();
+}
+
+/// One record of an `MQTT_OUTPUT_FILE` capture, as read back for replay
+struct ReplayRecord {
+    timestamp: Option<DateTime<Utc>>,
+    topic: String,
+    payload: Vec<u8>,
+}
+
+impl ReplayRecord {
+    /// Parse a `{"timestamp", "topic", "data", ...}` line
+    ///
+    /// The payload is rebuilt from `data`: non-JSON payloads were captured as
+    /// `{"raw": "..."}` and are restored to their original text.
+    fn parse(line: &str) -> Result<Self, String> {
+        let record: Value = serde_json::from_str(line).map_err(|e| e.to_string())?;
+        let topic = record
+            .get("topic")
+            .and_then(Value::as_str)
+            .ok_or("missing \"topic\"")?
+            .to_string();
+        let data = record.get("data").ok_or("missing \"data\"")?;
+        let timestamp = record
+            .get("timestamp")
+            .and_then(Value::as_str)
+            .and_then(|ts| DateTime::parse_from_rfc3339(ts).ok())
+            .map(|ts| ts.with_timezone(&Utc));
+
+        let raw = data
+            .as_object()
+            .filter(|object| object.len() == 1)
+            .and_then(|object| object.get("raw"))
+            .and_then(Value::as_str);
+        let payload = match raw {
+            Some(raw) => raw.as_bytes().to_vec(),
+            None => serde_json::to_vec(data).map_err(|e| e.to_string())?,
+        };
+
+        Ok(ReplayRecord {
+            timestamp,
+            topic,
+            payload,
+        })
+    }
+}
+
+/// Feed a capture file through `handle_publish` instead of connecting
+/// (`MQTT_REPLAY_FILE`), sleeping between records when `MQTT_REPLAY_SPEED` > 0
+async fn replay(config: &Config, path: &str) -> Result<(), Box<dyn Error>> {
+    if config.output_file.as_deref() == Some(path) {
+        return Err("MQTT_REPLAY_FILE and MQTT_OUTPUT_FILE must be different files".into());
+    }
+    let file =
+        File::open(path).map_err(|e| format!("Cannot open MQTT_REPLAY_FILE {}: {}", path, e))?;
+    let mut outputs = Outputs::from_config(config)?;
+
+    let fields = json!({"file": path, "speed": config.replay_speed});
+    log_event("info", "replay_started", fields, || {
+        println!("Replaying {} (no broker connection)...", path);
+        if config.replay_speed > 0.0 {
+            println!("  Speed: {}x recorded timing", config.replay_speed);
+        }
+        println!();
+    });
+
+    let mut replayed: u64 = 0;
+    let mut skipped: u64 = 0;
+    let mut previous: Option<DateTime<Utc>> = None;
+    for (index, line) in BufReader::new(file).lines().enumerate() {
+        let line = line.map_err(|e| format!("Cannot read MQTT_REPLAY_FILE {}: {}", path, e))?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let record = match ReplayRecord::parse(&line) {
+            Ok(record) => record,
+            Err(e) => {
+                skipped += 1;
+                let fields = json!({"line": index + 1, "error": e});
+                log_event("warn", "replay_skipped", fields, || {
+                    eprintln!("⚠️  Skipping line {} of {}: {}", index + 1, path, e)
+                });
+                continue;
+            }
+        };
+
+        // Reproduce the gap to the previous record, scaled by the speed
+        if let Some(timestamp) = record.timestamp {
+            if config.replay_speed > 0.0 {
+                if let Some(gap) = previous.and_then(|prev| (timestamp - prev).to_std().ok()) {
+                    tokio::time::sleep(gap.div_f64(config.replay_speed)).await;
+                }
+            }
+            previous = Some(timestamp);
+        }
+
+        handle_publish(config, &mut outputs, &record.topic, &record.payload);
+        replayed += 1;
+    }
+    outputs.flush();
+
+    let fields = json!({"messages": replayed, "skipped": skipped});
+    log_event("info", "replay_finished", fields, || {
+        println!("✅ Replayed {} message(s), skipped {}", replayed, skipped)
+    });
+    Ok(())
+}
+
+/// Log messages dropped because the processing queue was full
+fn report_dropped(dropped: u64, policy: OverflowPolicy) {
+    if dropped == 0 {
+        return;
+    }
+    let fields = json!({"dropped": dropped, "policy": policy.as_str()});
+    log_event("warn", "queue_overflow", fields, || {
+        eprintln!(
+            "⚠️  Processing queue full: dropped {} message(s) (MQTT_OVERFLOW_POLICY={})",
+            dropped,
+            policy.as_str()
+        )
+    });
+}
+
+/// Subscribe to every configured topic filter
+async fn subscribe_all(
+    client: &AsyncClient,
+    topics: &[String],
+    qos: QoS,
+) -> Result<(), ClientError> {
+    for topic in topics {
+        client.subscribe(topic, qos).await?;
+    }
+    Ok(())
+}
+
+/// Publish a JSON telemetry payload to `device/<device_id>/telemetry/<sensor>`
+async fn publish_telemetry(
+    client: &AsyncClient,
+    device_id: &str,
+    sensor: &str,
+    payload: &Value,
+    qos: QoS,
+) -> Result<(), Box<dyn Error + Send + Sync>> {
+    let topic = format!("device/{}/telemetry/{}", device_id, sensor);
+    let body = serde_json::to_vec(payload)?;
+    client.publish(topic, qos, false, body).await?;
+    Ok(())
+}
+
+/// Publish one sample reading to verify the token's publish permission
+async fn publish_test_message(client: &AsyncClient, qos: QoS) {
+    let device_id =
+        env::var("MQTT_PUBLISH_DEVICE_ID").unwrap_or_else(|_| "rust-publish-test".to_string());
+    let payload = serde_json::json!({
+        "value": 25.5,
+        "unit": "°C",
+        "timestamp": Utc::now().to_rfc3339(),
+    });
+
+    let topic = format!("device/{}/telemetry/temperature", device_id);
+    match publish_telemetry(client, &device_id, "temperature", &payload, qos).await {
+        Ok(()) => log_event("info", "test_published", json!({"topic": topic}), || {
+            println!("📤 Published test message to {}", topic)
+        }),
+        Err(e) => {
+            let fields = json!({"topic": topic, "error": e.to_string()});
+            log_event("error", "test_publish_failed", fields, || {
+                eprintln!("❌ Failed to publish test message: {}", e)
+            });
+        }
+    }
+}
+
+/// Handle messages that were already on their way when shutdown began
+///
+/// Unsubscribes from every topic so no new messages are routed to us, then
+/// keeps polling the event loop (which also acks QoS 1/2 messages) until the
+/// broker has confirmed every unsubscribe or `config.drain_timeout` passes.
+/// Returns the number of messages received.
+async fn drain(
+    config: &Config,
+    client: &AsyncClient,
+    eventloop: &mut EventLoop,
+    queue: &mut ProcessingQueue,
+    stats: &mut MessageStats,
+) -> usize {
+    let deadline = tokio::time::Instant::now() + config.drain_timeout;
+    let mut drained = 0;
+    let mut pending_unsubacks = config.topics.len();
+
+    // Unsubscribe from a task so the event loop keeps polling while the
+    // requests are queued
+    let unsubscriber = client.clone();
+    let topics = config.topics.clone();
+    tokio::spawn(async move {
+        for topic in topics {
+            if unsubscriber.unsubscribe(topic).await.is_err() {
+                break;
+            }
+        }
+    });
+
+    while pending_unsubacks > 0 {
+        match tokio::time::timeout_at(deadline, eventloop.poll()).await {
+            Ok(Ok(Event::Incoming(Incoming::Publish(publish)))) => {
+                stats.record(publish.payload.len());
+                queue.push(publish).await;
+                drained += 1;
+            }
+            Ok(Ok(Event::Incoming(Incoming::UnsubAck(_)))) => pending_unsubacks -= 1,
+            Ok(Ok(_)) => {}
+            // Connection lost or grace period over: nothing more will arrive
+            Ok(Err(_)) | Err(_) => break,
+        }
+    }
+    drained
+}
+
+/// Send DISCONNECT and poll until it has been written, for up to `timeout`
+async fn disconnect(
+    client: &AsyncClient,
+    eventloop: &mut EventLoop,
+    timeout: Duration,
+) -> Result<(), ClientError> {
+    client.disconnect().await?;
+    let _ = tokio::time::timeout(timeout, async {
+        loop {
+            match eventloop.poll().await {
+                Ok(Event::Outgoing(Outgoing::Disconnect)) | Err(_) => break,
+                Ok(_) => {}
+            }
+        }
+    })
+    .await;
+    Ok(())
 }
 
 #[tokio::main]
@@ -153,42 +1571,132 @@ async fn main() -> Result<(), Box<dyn Error>> {
     // Load .env file
     dotenv::dotenv().ok();
 
-    display_banner();
-
     // Load and validate configuration
-    let config = Config::from_env()?;
+    let config = Arc::new(Config::from_env()?);
+    LOG_FORMAT.get_or_init(|| config.log_format);
+    if config.log_format == LogFormat::Pretty {
+        display_banner();
+    }
+
+    // Offline replay of a capture file: no token or broker needed
+    if let Some(path) = &config.replay_file {
+        tokio::select! {
+            result = replay(&config, path) => return result,
+            _ = signal::ctrl_c() => {
+                log_event("info", "shutdown", Value::Null, || {
+                    println!();
+                    println!("Received shutdown signal. Stopping replay.");
+                });
+                return Ok(());
+            }
+        }
+    }
+
     config.validate()?;
 
-    println!("Connecting to TESAIoT MQTT Broker via WSS...");
-    println!("  Broker: {}", config.broker_url);
-    println!("  Client ID: {}", config.client_id);
-    println!(
-        "  Token: {}...{}",
-        &config.token[..20.min(config.token.len())],
-        &config.token[config.token.len().saturating_sub(4)..]
-    );
-    println!();
+    let fields = json!({
+        "broker": config.broker_url,
+        "transport": config.scheme.label(),
+        "client_id": config.client_id,
+        "topics": config.topics,
+        "qos": config.qos as u8,
+        "clean_session": config.clean_session,
+    });
+    log_event("info", "starting", fields, || {
+        println!(
+            "Connecting to TESAIoT MQTT Broker via {}...",
+            config.scheme.label()
+        );
+        println!("  Broker: {}", config.broker_url);
+        println!("  Client ID: {}", config.client_id);
+        println!(
+            "  QoS: {:?}, clean session: {}",
+            config.qos, config.clean_session
+        );
+        if !config.device_filter.is_empty() {
+            println!("  Device filter: {} device(s)", config.device_filter.len());
+        }
+        if let Some(path) = &config.output_file {
+            println!("  Output file: {}", path);
+        }
+        if let Some(url) = &config.webhook_url {
+            let signing = if config.webhook_secret.is_some() {
+                " (signed)"
+            } else {
+                ""
+            };
+            println!("  Webhook: {}{}", url, signing);
+        }
+        if let Some(path) = &config.ca_cert {
+            println!("  CA certificate: {}", path);
+        }
+        #[cfg(feature = "metrics")]
+        if let Some(port) = config.health_port {
+            println!("  Readiness probe: http://0.0.0.0:{}/", port);
+        }
+        println!("  Token: {}", redact_token(&config.token));
+        println!();
+    });
+    #[cfg(not(feature = "metrics"))]
+    if env::var_os("MQTT_HEALTH_PORT").is_some() {
+        let fields = json!({"error": "MQTT_HEALTH_PORT requires the metrics feature"});
+        log_event("warn", "config_warning", fields, || {
+            eprintln!("⚠️  MQTT_HEALTH_PORT is ignored: rebuild with --features metrics")
+        });
+    }
+
+    // Readiness probe, updated from the event loop below
+    #[cfg(feature = "metrics")]
+    let health = match config.health_port {
+        Some(port) => Some(
+            health::spawn(port, config.health_max_silence)
+                .await
+                .map_err(|e| format!("Cannot serve MQTT_HEALTH_PORT {}: {}", port, e))?,
+        ),
+        None => None,
+    };
+
+    if config.scheme == BrokerScheme::Mqtt {
+        let fields = json!({"error": "mqtt:// is unencrypted; the token is sent in cleartext"});
+        log_event("warn", "config_warning", fields, || {
+            eprintln!("⚠️  mqtt:// is unencrypted: the API token is sent in cleartext");
+            eprintln!("   Use mqtts:// or wss:// outside trusted networks");
+            eprintln!();
+        });
+    }
 
     // Create MQTT options
-    let mut mqtt_options = MqttOptions::new(&config.client_id, &config.host, config.port);
+    let mut mqtt_options =
+        MqttOptions::new(&config.client_id, config.broker_address()?, config.port);
     mqtt_options.set_credentials(&config.token, &config.token);
     mqtt_options.set_keep_alive(Duration::from_secs(60));
-    mqtt_options.set_transport(Transport::wss_with_default_config());
+    mqtt_options.set_clean_session(config.clean_session);
+    mqtt_options.set_transport(config.transport()?);
 
     // Create async client and event loop
     let (client, mut eventloop) = AsyncClient::new(mqtt_options, 10);
 
-    // Subscribe to topic
-    client
-        .subscribe(&config.topic, QoS::AtLeastOnce)
-        .await?;
+    // Subscriptions are issued on every ConnAck (see the event loop below)
+    let mut connected_once = false;
 
-    println!("✅ Connected to TESAIoT MQTT Broker!");
-    println!("📡 Subscribed to: {}", config.topic);
-    println!();
-    println!("Waiting for telemetry messages...");
-    println!("{}", "─".repeat(50));
-    println!();
+    // --publish-test sends one sample message after the first connect
+    let publish_test = env::args().any(|arg| arg == "--publish-test");
+
+    // Reconnect backoff: 1s, 2s, 4s... capped at 60s
+    let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(60));
+
+    // Connection failures since the last ConnAck (see MQTT_MAX_RECONNECTS)
+    let mut failed_attempts: u32 = 0;
+
+    // Received messages are processed (and outputs written) by a separate task
+    let mut queue = ProcessingQueue::spawn(Arc::clone(&config), Outputs::from_config(&config)?);
+    let mut drop_report_interval = tokio::time::interval(Duration::from_secs(5));
+
+    // Optional message-rate statistics (MQTT_STATS_INTERVAL)
+    let mut stats = MessageStats::new();
+    let stats_period = config.stats_interval.unwrap_or(Duration::from_secs(60));
+    let mut stats_interval =
+        tokio::time::interval_at(tokio::time::Instant::now() + stats_period, stats_period);
 
     // Event loop with graceful shutdown
     loop {
@@ -196,10 +1704,60 @@ async fn main() -> Result<(), Box<dyn Error>> {
             event = eventloop.poll() => {
                 match event {
                     Ok(Event::Incoming(Incoming::Publish(publish))) => {
-                        handle_publish(&publish.topic, &publish.payload);
+                        stats.record(publish.payload.len());
+                        #[cfg(feature = "metrics")]
+                        if let Some(health) = &health {
+                            health.message_received();
+                        }
+                        queue.push(publish).await;
                     }
                     Ok(Event::Incoming(Incoming::ConnAck(_))) => {
                         // Connection acknowledged
+                        backoff.reset();
+                        failed_attempts = 0;
+                        #[cfg(feature = "metrics")]
+                        if let Some(health) = &health {
+                            health.connected();
+                        }
+
+                        // (Re)subscribe on every ConnAck. With clean_session=true the
+                        // broker forgets our subscriptions whenever the connection
+                        // drops, so without this a reconnect would receive nothing.
+                        // Subscribing from a task keeps the event loop polling while
+                        // the requests are queued.
+                        let client = client.clone();
+                        let topics = config.topics.clone();
+                        let qos = config.qos;
+                        let send_test = publish_test && !connected_once;
+                        tokio::spawn(async move {
+                            if let Err(e) = subscribe_all(&client, &topics, qos).await {
+                                log_event("error", "subscribe_failed", json!({"error": e.to_string()}), || {
+                                    eprintln!("❌ Failed to subscribe: {}", e)
+                                });
+                            }
+                            if send_test {
+                                publish_test_message(&client, qos).await;
+                            }
+                        });
+
+                        let fields = json!({"reconnect": connected_once, "topics": config.topics});
+                        if connected_once {
+                            log_event("info", "connected", fields, || {
+                                println!("🔄 Reconnected, restoring {} subscription(s)", config.topics.len())
+                            });
+                        } else {
+                            connected_once = true;
+                            log_event("info", "connected", fields, || {
+                                println!("✅ Connected to TESAIoT MQTT Broker!");
+                                for topic in &config.topics {
+                                    println!("📡 Subscribed to: {}", topic);
+                                }
+                                println!();
+                                println!("Waiting for telemetry messages...");
+                                println!("{}", "─".repeat(50));
+                                println!();
+                            });
+                        }
                     }
                     Ok(Event::Incoming(Incoming::SubAck(_))) => {
                         // Subscription acknowledged
@@ -208,17 +1766,89 @@ async fn main() -> Result<(), Box<dyn Error>> {
                         // Other events (PingReq, PingResp, etc.)
                     }
                     Err(e) => {
-                        eprintln!("❌ Connection error: {:?}", e);
-                        eprintln!("   Reconnecting in 5 seconds...");
-                        tokio::time::sleep(Duration::from_secs(5)).await;
+                        failed_attempts += 1;
+                        #[cfg(feature = "metrics")]
+                        if let Some(health) = &health {
+                            health.disconnected();
+                        }
+                        let auth_failure = is_auth_failure(&e);
+                        let fields = json!({"error": e.to_string(), "auth_failure": auth_failure});
+                        log_event("error", "connection_error", fields, || {
+                            if auth_failure {
+                                eprintln!("❌ Authentication failed: the broker rejected MQTT_API_TOKEN ({})", e);
+                                eprintln!("   Check that the token is valid and has not expired or been revoked");
+                            } else {
+                                eprintln!("❌ Connection error: {:?}", e);
+                            }
+                        });
+
+                        // The first attempt isn't a reconnect, hence `>`
+                        if config.max_reconnects.is_some_and(|max| failed_attempts > max) {
+                            let reconnects = failed_attempts - 1;
+                            log_event("fatal", "giving_up", json!({"failed_reconnects": reconnects}), || {
+                                eprintln!(
+                                    "💀 FATAL: giving up after {} failed reconnect attempt(s) (MQTT_MAX_RECONNECTS)",
+                                    reconnects
+                                )
+                            });
+                            queue.close().await;
+                            std::process::exit(1);
+                        }
+
+                        let delay = backoff.next_delay();
+                        let fields = json!({"delay_secs": delay.as_secs_f64()});
+                        log_event("info", "reconnecting", fields, || {
+                            eprintln!("   Reconnecting in {:.1} seconds...", delay.as_secs_f64())
+                        });
+                        // Still honour Ctrl+C while waiting to reconnect
+                        tokio::select! {
+                            _ = tokio::time::sleep(delay) => {}
+                            _ = signal::ctrl_c() => {
+                                log_event("info", "shutdown", json!({"drain_timeout_secs": 0}), || {
+                                    println!();
+                                    println!("Received shutdown signal while reconnecting. Exiting...");
+                                });
+                                report_dropped(queue.take_dropped(), config.overflow_policy);
+                                queue.close().await;
+                                break;
+                            }
+                        }
                     }
                 }
             }
+            _ = drop_report_interval.tick() => {
+                report_dropped(queue.take_dropped(), config.overflow_policy);
+            }
+            _ = stats_interval.tick(), if config.stats_interval.is_some() => {
+                let fields = stats.fields();
+                let line = stats.report();
+                log_event("info", "stats", fields, || println!("{}", line));
+            }
             _ = signal::ctrl_c() => {
-                println!();
-                println!("Received shutdown signal. Disconnecting...");
-                client.disconnect().await?;
-                println!("✅ Disconnected. Goodbye!");
+                if connected_once && !config.drain_timeout.is_zero() {
+                    let timeout = config.drain_timeout.as_secs();
+                    log_event("info", "shutdown", json!({"drain_timeout_secs": timeout}), || {
+                        println!();
+                        println!("Received shutdown signal. Draining in-flight messages (up to {}s)...", timeout);
+                    });
+                    let drained =
+                        drain(&config, &client, &mut eventloop, &mut queue, &mut stats).await;
+                    log_event("info", "drained", json!({"messages": drained}), || {
+                        println!("Drained {} message(s). Disconnecting...", drained)
+                    });
+                } else {
+                    log_event("info", "shutdown", json!({"drain_timeout_secs": 0}), || {
+                        println!();
+                        println!("Received shutdown signal. Disconnecting...");
+                    });
+                }
+                disconnect(&client, &mut eventloop, Duration::from_secs(1)).await?;
+                report_dropped(queue.take_dropped(), config.overflow_policy);
+                // Finish processing what is already queued
+                queue.close().await;
+                log_event("info", "disconnected", Value::Null, || {
+                    println!("✅ Disconnected. Goodbye!")
+                });
                 break;
             }
         }
@@ -226,3 +1856,364 @@ async fn main() -> Result<(), Box<dyn Error>> {
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Config as `from_env` would build it for `broker_url` with nothing else set
+    fn config(broker_url: &str) -> Config {
+        let (scheme, host, port) = Config::parse_broker_url(broker_url).unwrap();
+        Config {
+            token: "tesa_mqtt_test".to_string(),
+            broker_url: broker_url.to_string(),
+            topics: vec!["device/+/telemetry/#".to_string()],
+            client_id: "test".to_string(),
+            scheme,
+            host,
+            port,
+            device_filter: HashSet::new(),
+            topic_pattern: TopicPattern::parse(DEFAULT_TOPIC_PATTERN).unwrap(),
+            output_file: None,
+            webhook_url: None,
+            webhook_secret: None,
+            alert_rules: Vec::new(),
+            qos: QoS::AtLeastOnce,
+            clean_session: true,
+            stats_interval: None,
+            ca_cert: None,
+            drain_timeout: Duration::from_secs(5),
+            max_reconnects: None,
+            log_format: LogFormat::Pretty,
+            overflow_policy: OverflowPolicy::Block,
+            replay_file: None,
+            replay_speed: 0.0,
+            #[cfg(feature = "metrics")]
+            health_port: None,
+            #[cfg(feature = "metrics")]
+            health_max_silence: Duration::from_secs(300),
+        }
+    }
+
+    #[test]
+    fn empty_device_filter_allows_every_device() {
+        let config = config("wss://mqtt.tesaiot.com:8085/mqtt");
+        assert!(config.allows_device("sensor-1"));
+        assert!(config.allows_device(""));
+    }
+
+    #[test]
+    fn device_filter_allows_only_listed_devices() {
+        let mut config = config("wss://mqtt.tesaiot.com:8085/mqtt");
+        config.device_filter = split_list(" sensor-1, sensor-2 ,,").collect();
+
+        assert!(config.allows_device("sensor-1"));
+        assert!(config.allows_device("sensor-2"));
+        assert!(!config.allows_device("sensor-3"));
+        assert!(!config.allows_device("Sensor-1"));
+    }
+
+    #[test]
+    fn backoff_doubles_up_to_the_cap_and_resets() {
+        let mut backoff = Backoff::new(Duration::from_secs(1), Duration::from_secs(4));
+        for expected in [1, 2, 4, 4] {
+            let delay = backoff.next_delay();
+            let base = Duration::from_secs(expected);
+            assert!(delay >= base && delay <= base + base / 4, "{:?}", delay);
+        }
+
+        backoff.reset();
+        assert!(backoff.next_delay() <= Duration::from_millis(1250));
+    }
+
+    #[test]
+    fn redact_token_hides_the_whole_token() {
+        for token in ["", "short", "tesa_mqtt_yourorg_0123456789abcdef"] {
+            assert_eq!(redact_token(token), "***redacted***");
+        }
+    }
+
+    fn captures(pattern: &str, topic: &str) -> Option<Vec<(String, String)>> {
+        let mut captures: Vec<_> = TopicPattern::parse(pattern)
+            .unwrap()
+            .captures(topic)?
+            .into_iter()
+            .collect();
+        captures.sort();
+        Some(captures)
+    }
+
+    fn pairs(pairs: &[(&str, &str)]) -> Option<Vec<(String, String)>> {
+        Some(
+            pairs
+                .iter()
+                .map(|(k, v)| (k.to_string(), v.to_string()))
+                .collect(),
+        )
+    }
+
+    #[test]
+    fn topic_pattern_captures_named_wildcards() {
+        assert_eq!(
+            captures(DEFAULT_TOPIC_PATTERN, "device/d1/telemetry/env/temp"),
+            pairs(&[("device_id", "d1"), ("sensor", "env/temp")])
+        );
+        assert_eq!(
+            captures(DEFAULT_TOPIC_PATTERN, "device/d1/telemetry"),
+            pairs(&[("device_id", "d1"), ("sensor", "")])
+        );
+        assert_eq!(
+            captures("site/+/+name", "site/north/pump"),
+            pairs(&[("name", "pump")])
+        );
+    }
+
+    #[test]
+    fn topic_pattern_rejects_mismatches() {
+        assert_eq!(captures(DEFAULT_TOPIC_PATTERN, "device/d1/status"), None);
+        assert_eq!(
+            captures(DEFAULT_TOPIC_PATTERN, "devices/d1/telemetry"),
+            None
+        );
+        assert_eq!(captures(DEFAULT_TOPIC_PATTERN, "device"), None);
+        // `+` matches exactly one level, never zero or two
+        assert_eq!(captures("a/+x", "a"), None);
+        assert_eq!(captures("a/+x", "a/b/c"), None);
+        assert_eq!(captures("a/+x", "a/"), pairs(&[("x", "")]));
+        // Leading wildcards don't match `$` topics
+        assert_eq!(captures("#all", "$SYS/broker/load"), None);
+        assert_eq!(captures("+x/broker/load", "$SYS/broker/load"), None);
+        assert_eq!(
+            captures("$SYS/#rest", "$SYS/broker/load"),
+            pairs(&[("rest", "broker/load")])
+        );
+    }
+
+    #[test]
+    fn topic_pattern_parse_errors() {
+        assert!(TopicPattern::parse("a/#rest/b").is_err());
+        assert!(TopicPattern::parse("a/b+/c").is_err());
+        assert!(TopicPattern::parse("a/+x+y").is_err());
+        assert!(TopicPattern::parse("a/#x#").is_err());
+        assert!(TopicPattern::parse("a/+/#").is_ok());
+    }
+
+    #[test]
+    fn topic_filter_accepts_valid_filters() {
+        for filter in [
+            "device/+/telemetry",
+            "device/#",
+            "#",
+            "+",
+            "+/+/status",
+            "$SYS/#",
+        ] {
+            assert_eq!(validate_topic_filter(filter), Ok(()), "{}", filter);
+        }
+    }
+
+    #[test]
+    fn topic_filter_rejects_invalid_filters() {
+        let cases = [
+            ("", "empty"),
+            ("device//telemetry", "level 2 is empty"),
+            ("device/telemetry/", "trailing '/'"),
+            ("/device/telemetry", "leading '/'"),
+            ("/", "leading '/'"),
+            (
+                "device/#/telemetry",
+                "'#' is only allowed as the last level",
+            ),
+            ("device/d1+", "mixes a wildcard"),
+            ("device/#d1", "mixes a wildcard"),
+            ("device/\0", "NUL"),
+        ];
+        for (filter, reason) in cases {
+            let err = validate_topic_filter(filter).unwrap_err();
+            assert!(err.contains(reason), "{:?}: {}", filter, err);
+        }
+        assert!(validate_topic_filter(&"a".repeat(65536)).is_err());
+    }
+
+    fn telemetry(reading: &str, value: f64) -> Telemetry {
+        Telemetry {
+            device_id: "d1".to_string(),
+            sensor_type: "env".to_string(),
+            timestamp: Utc::now(),
+            readings: HashMap::from([(reading.to_string(), value)]),
+        }
+    }
+
+    #[test]
+    fn alert_rule_parses_each_operator() {
+        let cases = [
+            ("temperature>80", Comparison::Greater, 80.0),
+            ("temperature<-5", Comparison::Less, -5.0),
+            ("temperature>=80.5", Comparison::GreaterOrEqual, 80.5),
+            (" temperature <= 1e2 ", Comparison::LessOrEqual, 100.0),
+        ];
+        for (input, comparison, threshold) in cases {
+            let rule = AlertRule::parse(input).unwrap();
+            assert_eq!(rule.reading, "temperature", "{}", input);
+            assert_eq!(rule.comparison, comparison, "{}", input);
+            assert_eq!(rule.threshold, threshold, "{}", input);
+        }
+        assert_eq!(
+            AlertRule::parse("humidity>=80.5").unwrap().to_string(),
+            "humidity>=80.5"
+        );
+    }
+
+    #[test]
+    fn alert_rule_rejects_bad_syntax() {
+        for input in [
+            "",
+            "temperature",
+            "temperature=80",
+            ">80",
+            "temperature>",
+            "temperature>hot",
+            "temperature>>80",
+            "temperature=>80",
+            "temperature>NaN",
+            "temperature>inf",
+        ] {
+            assert!(AlertRule::parse(input).is_err(), "{:?}", input);
+        }
+    }
+
+    #[test]
+    fn alert_rule_threshold_edges() {
+        let check = |rule: &str, value: f64| {
+            AlertRule::parse(rule)
+                .unwrap()
+                .check(&telemetry("t", value))
+        };
+        assert_eq!(check("t>80", 80.0), None);
+        assert_eq!(check("t>80", 80.1), Some(80.1));
+        assert_eq!(check("t>=80", 80.0), Some(80.0));
+        assert_eq!(check("t>=80", 79.9), None);
+        assert_eq!(check("t<0", 0.0), None);
+        assert_eq!(check("t<0", -0.5), Some(-0.5));
+        assert_eq!(check("t<=0", 0.0), Some(0.0));
+        assert_eq!(check("t<=0", 0.5), None);
+        // A rule on a reading the message doesn't carry never fires
+        assert_eq!(
+            AlertRule::parse("humidity>0")
+                .unwrap()
+                .check(&telemetry("t", 1.0)),
+            None
+        );
+    }
+
+    #[test]
+    fn broker_url_selects_host_port_and_transport() {
+        let cases = [
+            ("wss://mqtt.tesaiot.com", BrokerScheme::Wss, 8085),
+            ("mqtts://mqtt.tesaiot.com", BrokerScheme::Mqtts, 8883),
+            ("mqtt://mqtt.tesaiot.com", BrokerScheme::Mqtt, 1883),
+            ("wss://mqtt.tesaiot.com:9443/mqtt", BrokerScheme::Wss, 9443),
+            ("mqtts://mqtt.tesaiot.com:9883", BrokerScheme::Mqtts, 9883),
+            ("mqtt://mqtt.tesaiot.com:11883", BrokerScheme::Mqtt, 11883),
+        ];
+        for (url, scheme, port) in cases {
+            let config = config(url);
+            assert_eq!(config.scheme, scheme, "{}", url);
+            assert_eq!(config.host, "mqtt.tesaiot.com", "{}", url);
+            assert_eq!(config.port, port, "{}", url);
+            let transport = config.transport().unwrap();
+            let expected = match scheme {
+                BrokerScheme::Wss => matches!(transport, Transport::Wss(_)),
+                BrokerScheme::Mqtts => matches!(transport, Transport::Tls(_)),
+                BrokerScheme::Mqtt => matches!(transport, Transport::Tcp),
+            };
+            assert!(expected, "{}", url);
+        }
+    }
+
+    #[test]
+    fn broker_address_spells_out_the_websocket_port() {
+        assert_eq!(
+            config("wss://mqtt.tesaiot.com/mqtt")
+                .broker_address()
+                .unwrap(),
+            "wss://mqtt.tesaiot.com:8085/mqtt"
+        );
+        assert_eq!(
+            config("wss://mqtt.tesaiot.com:9443/mqtt")
+                .broker_address()
+                .unwrap(),
+            "wss://mqtt.tesaiot.com:9443/mqtt"
+        );
+        assert_eq!(
+            config("mqtts://mqtt.tesaiot.com").broker_address().unwrap(),
+            "mqtt.tesaiot.com"
+        );
+        assert_eq!(
+            config("mqtt://mqtt.tesaiot.com:1884")
+                .broker_address()
+                .unwrap(),
+            "mqtt.tesaiot.com"
+        );
+    }
+
+    #[test]
+    fn broker_url_rejects_unsupported_schemes() {
+        for url in [
+            "ws://mqtt.tesaiot.com",
+            "https://mqtt.tesaiot.com",
+            "tcp://mqtt.tesaiot.com",
+            "mqtt.tesaiot.com:8883",
+        ] {
+            assert!(Config::parse_broker_url(url).is_err(), "{}", url);
+        }
+    }
+
+    #[test]
+    fn sign_payload_matches_hmac_sha256_test_vector() {
+        // RFC 4231, test case 2
+        assert_eq!(
+            sign_payload(b"Jefe", b"what do ya want for nothing?"),
+            "5bdcc146bf60754e6a042426089575c75a003f089d2739839dec58b964ec3843"
+        );
+    }
+
+    #[test]
+    fn signature_round_trip() {
+        let body = br#"{"device_id":"d1","temperature":25.5}"#;
+        let signature = sign_payload(b"secret", body);
+        assert!(verify_signature(b"secret", body, &signature));
+        assert!(verify_signature(b"secret", body, &signature.to_uppercase()));
+        assert!(verify_signature(
+            b"secret",
+            body,
+            &format!(" {}\n", signature)
+        ));
+    }
+
+    #[test]
+    fn signature_rejects_tampering_and_malformed_hex() {
+        let body = br#"{"device_id":"d1","temperature":25.5}"#;
+        let signature = sign_payload(b"secret", body);
+
+        let tampered = br#"{"device_id":"d1","temperature":99.5}"#;
+        assert!(!verify_signature(b"secret", tampered, &signature));
+        assert!(!verify_signature(b"other", body, &signature));
+        assert!(!verify_signature(b"secret", body, &signature[..63]));
+        assert!(!verify_signature(b"secret", body, &signature[..62]));
+        assert!(!verify_signature(b"secret", body, ""));
+        let non_hex = format!("zz{}", &signature[2..]);
+        assert!(!verify_signature(b"secret", body, &non_hex));
+        let plus = format!("+{}", &signature[1..]);
+        assert!(!verify_signature(b"secret", body, &plus));
+    }
+
+    #[test]
+    fn decode_hex_accepts_only_hex_pairs() {
+        assert_eq!(decode_hex("00ff7A"), Some(vec![0x00, 0xff, 0x7a]));
+        assert_eq!(decode_hex(""), Some(vec![]));
+        for input in ["abc", "0g", "+1", "-1", " 1", "é1"] {
+            assert_eq!(decode_hex(input), None, "{:?}", input);
+        }
+    }
+}